@@ -51,7 +51,7 @@ exports.code = [check_auth, asyncHandler(async (req, res, next) => {
     }
 })]
 
-// Start or Stop an algorithm.
+// Start, stop, pause or resume an algorithm.
 exports.toggle_running = [check_auth, asyncHandler(async (req, res, next) => {
     try {
         const response = await axios.post(`http://127.0.0.1:8080/algorithms/${req.params.id}/${req.params.start_or_run}`, 
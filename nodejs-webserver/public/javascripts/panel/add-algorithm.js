@@ -3,12 +3,30 @@ const default_code =
 # import math
 # import pandas
 # import numpy
+# from algonline import ta (indicators: sma, ema, rsi, macd, bollinger, atr, vwap)
+
+
+# data is a list of candles with the fields t (open time), o, h, l, c, v and x.
+# The last candle is updated on every tick until it is closed (x is True).
+# With the numpy data format data is a dict of read-only numpy arrays, e.g data["c"],
+# with the pandas data format a DataFrame with the columns o, h, l, c and v.
 
 
 # Write your algorithm logic here. Returning a positive value means that
 # value is bought. Returning a negative value is idem but for selling. Return 0
-# to do nothing.
-def func(data):
+# to do nothing. For more control return a dict, e.g:
+# {"side": "BUY", "quote_quantity": 100, "type": "LIMIT", "limit_price": 42000,
+#  "stop_loss": 41000, "take_profit": 45000, "time_in_force": "GTC", "tag": "dip"}
+# "STOP_LOSS_LIMIT" and "TAKE_PROFIT_LIMIT" orders also need a "stop_price".
+# Only a buy can have a stop_loss and take_profit, or a bracket which the server
+# watches instead:
+# "bracket": {"trailing_percent": 2, "stop": 41000, "target": 45000}
+# state is a dict which is kept between executions, use it to remember values.
+# It has to contain JSON-serializable values only.
+# Add a params argument to receive the parameters of the algorithm, e.g:
+# def func(data, state, params):
+# Use print() or log() to write to the logs of the algorithm.
+def func(data, state):
     return 0
 `
 
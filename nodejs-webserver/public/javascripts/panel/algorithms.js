@@ -39,8 +39,14 @@ function show_algorithm_data() {
                 
                 if (response.is_running == true) {
                     // Set power-button text to "is-running". We check if the HTML of the cell is already equal
-                    // to the HTML we want to update it to avoid blinking DOM.
-                    let html = "<img src=\"/images/panel/turn-off.png\" title=\"Stop algorithm\" onclick=\"toggle_running('"+id+"', 'stop')\"><span color=\"#088A08\">Running...</span>"
+                    // to the HTML we want to update it to avoid blinking DOM. A paused algorithm can be
+                    // resumed, a running algorithm paused.
+                    let html = "<img src=\"/images/panel/turn-off.png\" title=\"Stop algorithm\" onclick=\"toggle_running('"+id+"', 'stop')\">"
+                    if (response.state == "paused") {
+                        html += "<span color=\"#6E6E6E\" title=\"Resume algorithm\" onclick=\"toggle_running('"+id+"', 'resume')\">Paused.</span>"
+                    } else {
+                        html += "<span color=\"#088A08\" title=\"Pause algorithm\" onclick=\"toggle_running('"+id+"', 'pause')\">Running...</span>"
+                    }
                     if (ircell.innerHTML.replaceAll("&quot;", "\"") != html) {
                         ircell.innerHTML = html
                     }
@@ -67,7 +73,8 @@ function show_algorithm_data() {
     })
 }
 
-// Function executed when the user clicks to power-button to start/stop an algorithm.
+// Function executed when the user clicks to power-button to start/stop an algorithm, or the
+// status to pause/resume it.
 function toggle_running(id, start_or_run) {
     // Set temporary "Wait..." text after click so user doesn't click twice.
     let cell = document.querySelectorAll(".row#" + id + " .is-running")[0]
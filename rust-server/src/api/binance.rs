@@ -6,6 +6,15 @@ use serde_json::json;
 
 type HmacSha256 = Hmac<Sha256>;
 
+// Error code of the exchange when an order is not known, e.g it was never placed.
+const ORDER_DOES_NOT_EXIST: i64 = -2013;
+
+lazy_static! {
+    // Rules of the symbols per REST API, with the time they were retrieved. Every order needs
+    // them and they rarely change, see config::SYMBOL_RULES_TTL_SEC.
+    static ref SYMBOL_RULES: Mutex<std::collections::HashMap<String, (std::time::Instant, rules::SymbolRules)>> = Mutex::new(std::collections::HashMap::new());
+}
+
 #[derive(Clone)]
 pub struct Binance {
     rest_api_url: String,
@@ -36,8 +45,14 @@ impl ExchangeAPI for Binance {
 
     // Set API Keys to this instance of the API by retrieving user from database by session token.
     async fn auth(&mut self, session_token: &str, psql: Psql) -> Result<(), api::Error> {
+        let user_id = api::user_id(session_token, psql.clone()).await?;
+        self.auth_user(user_id, psql).await
+    }
+
+    // Use the stored keys of the user.
+    async fn auth_user(&mut self, user_id: i32, psql: Psql) -> Result<(), api::Error> {
         
-        // Retrieve keys with session token.
+        // Retrieve keys of the user.
         let query = psql.lock().await
             .query("
                 SELECT
@@ -45,13 +60,13 @@ impl ExchangeAPI for Binance {
                 FROM
                     users
                 WHERE
-                    session_token = $1
-             ", &[&session_token]).await;
+                    id = $1
+             ", &[&user_id]).await;
 
         let (api_key, api_secret) : (Option<String>, Option<String>) = match query {
             Ok(q) => {
                 if q.len() == 0 {
-                    return Err(api::Error::AuthenticationError("No user found with session token.".into()));
+                    return Err(api::Error::AuthenticationError("No user found with ID.".into()));
                 }
 
                 (q[0].get("api_key"), q[0].get("api_secret"))
@@ -161,8 +176,8 @@ impl ExchangeAPI for Binance {
     }
 
 
-    // Get account balance. Returns a tuple -> (USDT, BTC).
-    async fn account_balance(&self) -> Result<(f64, f64), api::Error> {
+    // Get account balance. Returns the free balance of every asset in the account.
+    async fn account_balance(&self) -> Result<std::collections::HashMap<String, f64>, api::Error> {
 
         // Initiate client to make request.
         let http_client = reqwest::Client::new();
@@ -207,33 +222,26 @@ impl ExchangeAPI for Binance {
         let response = request.text().await?;
         let json = serde_json::from_str::<serde_json::Value>(&*response)?;
 
-        let mut balances = (None, None);
-        if let Some(arr) = json["balances"].as_array() {
-            for a in arr {
-                if a["asset"].as_str().unwrap() == "USDT" {
-                    balances.0 = Some(json_str_to_f64!(a["free"]));
-                }
-                if a["asset"].as_str().unwrap() == "BTC" {
-                    balances.1 = Some(json_str_to_f64!(a["free"]));
-                }
-
-                if balances.0.is_some() && balances.1.is_some() {
-                    break;
-                }
+        let arr = match json["balances"].as_array() {
+            Some(arr) => arr,
+            None => {
+                return Err(api::Error::ExchangeAPIError("Balances were not retrieved.".to_string()));
             }
-        } 
+        };
 
-        match (balances.0, balances.1) {
-            (Some(usdt), Some(btc)) => Ok((usdt, btc)),
-            _ => {
-                return Err(api::Error::ExchangeAPIError("Balances were not retrieved.".to_string()));
+        let mut balances = std::collections::HashMap::<String, f64>::new();
+        for a in arr {
+            if let Some(asset) = a["asset"].as_str() {
+                balances.insert(asset.into(), json_str_to_f64!(a["free"]));
             }
         }
+
+        Ok(balances)
     }
     
     
     // Get trade history.
-    async fn trade_history(&self) -> Result<(), api::Error> {
+    async fn trade_history(&self, symbol: &Symbol) -> Result<(), api::Error> {
         // Initiate client to make request.
         let http_client = reqwest::Client::new();
 
@@ -241,7 +249,7 @@ impl ExchangeAPI for Binance {
 
         // Generate timestamp as string required to make the order-request.
         let timestamp = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis().to_string(); 
-        params.insert("symbol".to_string(), "BTCUSDT".into());
+        params.insert("symbol".to_string(), symbol.to_string());
         params.insert("limit".to_string(), "1".into());
         params.insert("timestamp".to_string(), timestamp);
 
@@ -277,43 +285,149 @@ impl ExchangeAPI for Binance {
         }
     }
 
-    async fn ws_order(&self, params: &mut std::collections::HashMap<String, String>, ws_send: Arc<Mutex<SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>>>) -> Result<(), api::Error> {
+    // Place an order over the REST API. Returns the orders as returned by the exchange, with
+    // the fills, one for every order the request placed.
+    // Read: https://binance-docs.github.io/apidocs/spot/en/#new-order-trade
+    async fn place_order(&self, order: &order::OrderRequest) -> Result<std::vec::Vec<serde_json::Value>, api::Error> {
+        let mut params = order.params();
+        params.insert("newOrderRespType".into(), "FULL".into());
+
+        let endpoint = if order.is_list() { "order/oco" } else { "order" };
+        let request = self.signed_request(reqwest::Method::POST, endpoint, &mut params).await?;
+
+        // An error status below 500 means the order was refused, otherwise the exchange doesn't
+        // know what happened with it.
+        let status = request.status();
+        let response = request.text().await?;
+        if status.is_success() {
+            return Ok(Binance::order_reports(order, serde_json::from_str(&response)?));
+        }
+
+        match status.is_client_error() {
+            true => Err(api::Error::OrderRejected(Binance::error_msg(response))),
+            false => Err(api::Error::ExchangeAPIError(Binance::error_msg(response))),
+        }
+    }
+
+    // Place an order over the websocket API and wait for the response. The client order ID is
+    // used as id of the request. Returns the orders as returned by the REST API, with the fills.
+    // Read: https://binance-docs.github.io/apidocs/websocket_api/en/#place-new-order-trade
+    async fn ws_order(&self, order: &order::OrderRequest, ws_api: &wsapi::WsApi) -> Result<std::vec::Vec<serde_json::Value>, api::Error> {
        
         // Get timestamp.
         let timestamp = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis().to_string(); 
 
-        // Set parameters payload for order request.
+        // Set parameters payload for order request. The full response holds the fills.
+        let mut params = order.params();
         params.insert("apiKey".into(), self.api_key.to_string());
         params.insert("timestamp".into(), timestamp.to_string());
+        params.insert("newOrderRespType".into(), "FULL".into());
 
         // Generate signature.
-        let signature = self.generate_signature(params)?;
+        let signature = self.generate_signature(&mut params)?;
+
+        // JSON order-message.
+        let mut json = json!({
+            "id": order.client_order_id,
+            "method": if order.is_list() { "orderList.place" } else { "order.place" },
+            "params": params,
+        });
+        json["params"]["signature"] = json!(signature);
 
-        // JSON string order-message.
-        let json = 
-        format!("
-        {{\n
-          \"id\": \"{}\",\n
-          \"method\": \"order.place\",\n
-          \"params\": {}\n
-          }}
-        ",  timestamp, serde_json::to_string(&params)?); 
-    
+        // Send to websocket and wait for the response. An error status below 500 means the
+        // order was refused, otherwise the exchange doesn't know what happened with it.
+        let response = ws_api.request(json).await?;
+        let error = response["error"]["msg"].as_str().unwrap_or("Unknown error").to_string();
 
-        // JSON string to JSON object.
-        let mut json = serde_json::from_str::<serde_json::Value>(&*json)?;
-        json["params"] = json!(params);
-        json["params"]["signature"] = json!(signature);
+        match response["status"].as_u64() {
+            Some(200) => Ok(Binance::order_reports(order, response["result"].clone())),
+            Some(status) if status < 500 => Err(api::Error::OrderRejected(error)),
+            _ => Err(api::Error::ExchangeAPIError(error)),
+        }
+    }
 
-        // Send to websocket.
-        match ws_send.lock().await.send(Message::Text(json.to_string())).await {
-            Ok(_) => Ok(()),
-            Err(e) => {
-                return Err(api::Error::ExchangeAPIError(format!("Error in websocket {}", e)));
+    // Get the open orders of the account, of all symbols when no symbol is given.
+    // Read: https://binance-docs.github.io/apidocs/spot/en/#current-open-orders-user_data
+    async fn open_orders(&self, symbol: Option<&Symbol>) -> Result<std::vec::Vec<serde_json::Value>, api::Error> {
+        let mut params = std::collections::HashMap::<String, String>::new();
+        if let Some(symbol) = symbol {
+            params.insert("symbol".to_string(), symbol.to_string());
+        }
+
+        let request = self.signed_request(reqwest::Method::GET, "openOrders", &mut params).await?;
+
+        match request.status().is_success() {
+            true => {
+                Ok(serde_json::from_str::<std::vec::Vec<serde_json::Value>>(&request.text().await?)?)
+            },
+            false => {
+                Err(api::Error::ExchangeAPIError(Binance::error_msg(request.text().await?)))
+            }
+        }
+    }
+
+    // Cancel an open order by its client order ID. Canceling an order of an OCO cancels both.
+    // Returns the canceled order.
+    // Read: https://binance-docs.github.io/apidocs/spot/en/#cancel-order-trade
+    async fn cancel_order(&self, symbol: &Symbol, client_order_id: &str) -> Result<serde_json::Value, api::Error> {
+        let mut params = std::collections::HashMap::<String, String>::new();
+        params.insert("symbol".to_string(), symbol.to_string());
+        params.insert("origClientOrderId".to_string(), client_order_id.to_string());
+
+        let request = self.signed_request(reqwest::Method::DELETE, "order", &mut params).await?;
+
+        match request.status().is_success() {
+            true => {
+                Ok(serde_json::from_str::<serde_json::Value>(&request.text().await?)?)
+            },
+            false => {
+                Err(api::Error::ExchangeAPIError(Binance::error_msg(request.text().await?)))
+            }
+        }
+    }
+
+    // Get an order by its client order ID.
+    // Read: https://binance-docs.github.io/apidocs/spot/en/#query-order-user_data
+    async fn order_status(&self, symbol: &Symbol, client_order_id: &str) -> Result<serde_json::Value, api::Error> {
+        let mut params = std::collections::HashMap::<String, String>::new();
+        params.insert("symbol".to_string(), symbol.to_string());
+        params.insert("origClientOrderId".to_string(), client_order_id.to_string());
+
+        let request = self.signed_request(reqwest::Method::GET, "order", &mut params).await?;
+
+        match request.status().is_success() {
+            true => {
+                Ok(serde_json::from_str::<serde_json::Value>(&request.text().await?)?)
+            },
+            false => {
+                let response = request.text().await?;
+                match Binance::error_code(&response) {
+                    Some(ORDER_DOES_NOT_EXIST) => Err(api::Error::OrderNotFound(Binance::error_msg(response))),
+                    _ => Err(api::Error::ExchangeAPIError(response)),
+                }
             }
         }
     }
     
+    // Get the trades which filled an order, with their price, quantity and commission.
+    // Read: https://binance-docs.github.io/apidocs/spot/en/#account-trade-list-user_data
+    async fn order_trades(&self, symbol: &Symbol, order_id: i64) -> Result<std::vec::Vec<serde_json::Value>, api::Error> {
+        let mut params = std::collections::HashMap::<String, String>::new();
+        params.insert("symbol".to_string(), symbol.to_string());
+        params.insert("orderId".to_string(), order_id.to_string());
+
+        let request = self.signed_request(reqwest::Method::GET, "myTrades", &mut params).await?;
+
+        match request.status().is_success() {
+            true => {
+                Ok(serde_json::from_str::<std::vec::Vec<serde_json::Value>>(&request.text().await?)?)
+            },
+            false => {
+                Err(api::Error::ExchangeAPIError(request.text().await?))
+            }
+        }
+    }
+
     // Get klines using Rest-API.
     // :param params:   A hashmap holding the key-values necessary for requesting the klines.
     //                  Read: https://binance-docs.github.io/apidocs/spot/en/#kline-candlestick-data
@@ -364,13 +478,13 @@ impl ExchangeAPI for Binance {
         Ok(data)
     }
 
-    // Use Binance websocket API to retrieve a stream of klines of BTCUSDT with a specified
+    // Use Binance websocket API to retrieve a stream of klines of a symbol with a specified
     // interval.
     // The klines are send to Sender 's'.
-    async fn ws_kline(&self, s: Arc<Mutex<mpsc::Sender<CandleStick>>>, interval: String) -> Result<(), api::Error> {
+    async fn ws_kline(&self, s: Arc<Mutex<mpsc::Sender<CandleStick>>>, symbol: &Symbol, interval: String) -> Result<(), api::Error> {
         // Automatically ury to reconnect after 5 seconds if connection drops.
         loop {
-            let url = url::Url::parse(&*format!("{url}/btcusdt@kline_{interval}", url = &*self.ws_stream_url, interval = interval))?;
+            let url = url::Url::parse(&*format!("{url}/{stream}@kline_{interval}", url = &*self.ws_stream_url, stream = symbol.stream_name(), interval = interval))?;
             
             let (ws_stream, _) = connect_async(url).await.expect("Failed to connect");
             let (_, mut read) = ws_stream.split();
@@ -380,14 +494,17 @@ impl ExchangeAPI for Binance {
                 let data = &*String::from_utf8(data).unwrap();
                 let json = serde_json::from_str::<serde_json::Value>(data).unwrap();
 
+                // Every update of the kline is forwarded. The timestamp is the open time so
+                // updates of the same candle can be matched, "x" tells if the candle is closed.
                 let candlestick = || -> Result<CandleStick, &str> {
                     Ok(CandleStick {
-                        timestamp: json["k"]["T"].as_number().ok_or("Error")?.as_u64().ok_or("Error")?,
+                        timestamp: json["k"]["t"].as_number().ok_or("Error")?.as_u64().ok_or("Error")?,
                         open: json_str_to_f64!(json["k"]["o"]),
                         close: json_str_to_f64!(json["k"]["c"]),
                         low: json_str_to_f64!(json["k"]["l"]),
                         high: json_str_to_f64!(json["k"]["h"]),
                         volume: json_str_to_f64!(json["k"]["v"]),
+                        closed: json["k"]["x"].as_bool().ok_or("Error")?,
                     })
                 };
 
@@ -407,13 +524,14 @@ impl ExchangeAPI for Binance {
         }
     }
 
-    async fn get_btc_price(&self) -> Result<f64, api::Error> {
+    // Get the last price of a symbol.
+    async fn get_price(&self, symbol: &Symbol) -> Result<f64, api::Error> {
         // Initiate client to make request.
         let http_client = reqwest::Client::new();
         
         // Send request.
         let request = http_client
-            .get(format!("{url}/klines?symbol=BTCUSDT&interval=1s&limit=1", url = self.rest_api_url))
+            .get(format!("{url}/klines?symbol={symbol}&interval=1s&limit=1", url = self.rest_api_url, symbol = symbol))
             .send()
             .await;
 
@@ -433,29 +551,131 @@ impl ExchangeAPI for Binance {
         };
 
         if json.is_empty() {
-            return Err(api::Error::ExchangeAPIError("No klines retrieved to get btc price".into()));
+            return Err(api::Error::ExchangeAPIError(format!("No klines retrieved to get {} price", symbol)));
         }
 
         Ok(json_str_to_f64!(json[0][4]))
     }
+
+    // Get the trading rules of a symbol from exchangeInfo. The rules are cached.
+    // Read: https://binance-docs.github.io/apidocs/spot/en/#exchange-information
+    async fn symbol_rules(&self, symbol: &Symbol) -> Result<rules::SymbolRules, api::Error> {
+        let key = format!("{} {}", self.rest_api_url, symbol);
+        if let Some((retrieved_at, rules)) = SYMBOL_RULES.lock().await.get(&key) {
+            if retrieved_at.elapsed().as_secs() < *config::SYMBOL_RULES_TTL_SEC {
+                return Ok(rules.clone());
+            }
+        }
+
+        let rules = rules::SymbolRules::from_exchange_info(&self.exchange_info(&symbol.to_string()).await?)?;
+
+        SYMBOL_RULES.lock().await.insert(key, (std::time::Instant::now(), rules.clone()));
+
+        Ok(rules)
+    }
+
+    // Get the base and quote asset of a symbol from exchangeInfo.
+    async fn symbol(&self, name: &str) -> Result<Symbol, api::Error> {
+        let info = self.exchange_info(name).await?;
+
+        match (info["baseAsset"].as_str(), info["quoteAsset"].as_str()) {
+            (Some(base), Some(quote)) => Ok(Symbol { base: base.into(), quote: quote.into() }),
+            _ => Err(api::Error::ParseError(format!("No assets in exchangeInfo of {}", name))),
+        }
+    }
+
     fn keys(&self) -> String {
         self.api_key.to_string()   
     }
 }
 
 impl Binance {
-    // Generate HMAC-SHA256 signature of paramerers to validate API-call.
-    fn generate_signature(&self, params: &mut std::collections::HashMap<String, String>) -> Result<String, api::Error> {
-        // Sort the parameters alphabetically by key
+    // Get a symbol from exchangeInfo, e.g BTCUSDT.
+    async fn exchange_info(&self, name: &str) -> Result<serde_json::Value, api::Error> {
+        // Initiate client to make request.
+        let http_client = reqwest::Client::new();
+
+        // Send request.
+        let request = http_client
+            .get(format!("{url}/exchangeInfo?symbol={symbol}", url = self.rest_api_url, symbol = name))
+            .send()
+            .await?;
+
+        if !request.status().is_success() {
+            return Err(api::Error::ExchangeAPIError(Binance::error_msg(request.text().await?)));
+        }
+
+        let mut json = serde_json::from_str::<serde_json::Value>(&request.text().await?)?;
+        match json["symbols"].as_array_mut().filter(|s| !s.is_empty()) {
+            Some(s) => Ok(s.remove(0)),
+            None => Err(api::Error::ExchangeAPIError(format!("No exchangeInfo retrieved for {}", name))),
+        }
+    }
+
+    // Send a signed request to the REST API, the parameters are sent in the query string.
+    async fn signed_request(&self, method: reqwest::Method, endpoint: &str, params: &mut std::collections::HashMap<String, String>) -> Result<reqwest::Response, api::Error> {
+        // Initiate client to make request.
+        let http_client = reqwest::Client::new();
+
+        // Generate timestamp as string required to make the request.
+        let timestamp = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis().to_string(); 
+        params.insert("timestamp".to_string(), timestamp);
+
+        // The signature is appended to the query string it signs.
+        let signature = self.generate_signature(params)?;
+        let payload = format!("{}&signature={}", Binance::query_string(params), signature);
+
+        // Send request.
+        let request = 
+            http_client.request(method, format!("{url}/{endpoint}?{payload}", url = self.rest_api_url, endpoint = endpoint, payload = payload))
+            .header("X-MBX-APIKEY", self.api_key.to_string())
+            .send()
+            .await;
+
+        match request {
+            Ok(req) => Ok(req),
+            Err(e) => Err(api::Error::ExchangeAPIError(format!("Request could not be made: {}", e))),
+        }
+    }
+
+    // The orders in the response to an order request. An OCO responds with the list, which
+    // holds its orders.
+    fn order_reports(order: &order::OrderRequest, response: serde_json::Value) -> std::vec::Vec<serde_json::Value> {
+        match order.is_list() {
+            true => response["orderReports"].as_array().cloned().unwrap_or_default(),
+            false => vec![response],
+        }
+    }
+
+    // Code of an error response of the exchange e.g {"code": -2010, "msg": "..."}.
+    fn error_code(response: &str) -> Option<i64> {
+        serde_json::from_str::<serde_json::Value>(response).ok().and_then(|json| json["code"].as_i64())
+    }
+
+    // Message of an error response of the exchange e.g {"code": -2010, "msg": "..."}.
+    fn error_msg(response: String) -> String {
+        match serde_json::from_str::<serde_json::Value>(&response) {
+            Ok(json) if json["msg"].is_string() => json["msg"].as_str().unwrap_or_default().to_string(),
+            _ => response,
+        }
+    }
+
+    // Query string of the parameters sorted by key: key1=value1&key2=value2
+    fn query_string(params: &std::collections::HashMap<String, String>) -> String {
         let mut sorted_params: Vec<(&String, &String)> = params.iter().collect();
         sorted_params.sort_by(|a, b| a.0.cmp(b.0));
 
-        // Generate query string to create signature.
-        let query_string = sorted_params
+        sorted_params
             .iter()
             .map(|(k, v)| format!("{}={}", k, v))
             .collect::<Vec<String>>()
-            .join("&");
+            .join("&")
+    }
+
+    // Generate HMAC-SHA256 signature of paramerers to validate API-call.
+    fn generate_signature(&self, params: &mut std::collections::HashMap<String, String>) -> Result<String, api::Error> {
+        // Generate query string of the parameters sorted by key to create signature.
+        let query_string = Binance::query_string(params);
 
         // Generate signature.
         let mut hmac = HmacSha256::new_from_slice(self.api_secret.as_bytes())?;
@@ -1,6 +1,10 @@
 use super::*;
 
 pub mod binance;
+pub mod order;
+pub mod paper;
+pub mod rules;
+pub mod wsapi;
 
 // Thanks to this trait we can easily adapt between different implementations for different
 // exchanges.
@@ -8,18 +12,213 @@ pub mod binance;
 pub trait ExchangeAPI : Send + Sync {
     fn new(rest_api_url: &str, ws_api_url: &str, ws_stream_url: &str, api_key: &str, api_secret: &str) -> Self where Self: Sized;
     async fn auth(&mut self, session_token: &str, psql: Psql) -> Result<(), Error>;
+    async fn auth_user(&mut self, user_id: i32, psql: Psql) -> Result<(), Error>;
     fn get_urls(&self, url_type: &str) -> Option<&str>;
     async fn ping(&self) -> bool;
     async fn order(&self, params: &mut std::collections::HashMap<String, String>) -> Result<String, Error>;
-    async fn trade_history(&self) -> Result<(), Error>;
-    async fn ws_order(&self, params: &mut std::collections::HashMap<String, String>, ws_send: Arc<Mutex<SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>>>) -> Result<(), Error>;
-    async fn account_balance(&self) -> Result<(f64, f64), Error>;
+    async fn trade_history(&self, symbol: &Symbol) -> Result<(), Error>;
+    async fn place_order(&self, order: &order::OrderRequest) -> Result<std::vec::Vec<serde_json::Value>, Error>;
+    async fn ws_order(&self, order: &order::OrderRequest, ws_api: &wsapi::WsApi) -> Result<std::vec::Vec<serde_json::Value>, Error>;
+    async fn open_orders(&self, symbol: Option<&Symbol>) -> Result<std::vec::Vec<serde_json::Value>, Error>;
+    async fn cancel_order(&self, symbol: &Symbol, client_order_id: &str) -> Result<serde_json::Value, Error>;
+    async fn order_status(&self, symbol: &Symbol, client_order_id: &str) -> Result<serde_json::Value, Error>;
+    async fn order_trades(&self, symbol: &Symbol, order_id: i64) -> Result<std::vec::Vec<serde_json::Value>, Error>;
+    async fn account_balance(&self) -> Result<std::collections::HashMap<String, f64>, Error>;
     async fn klines(&self, params: &mut std::collections::HashMap<String, String>) -> Result<std::vec::Vec<serde_json::Value>, Error>;
-    async fn ws_kline(&self, s :Arc<Mutex<mpsc::Sender<CandleStick>>>, interval: String) -> Result<(), Error>;
-    async fn get_btc_price(&self) -> Result<f64, Error>;
+    async fn ws_kline(&self, s :Arc<Mutex<mpsc::Sender<CandleStick>>>, symbol: &Symbol, interval: String) -> Result<(), Error>;
+    async fn get_price(&self, symbol: &Symbol) -> Result<f64, Error>;
+    async fn symbol_rules(&self, symbol: &Symbol) -> Result<rules::SymbolRules, Error>;
+    async fn symbol(&self, name: &str) -> Result<Symbol, Error>;
     fn keys(&self) -> String;
 }
 
+// A trading pair e.g BTCUSDT, where BTC is the base asset and USDT the quote asset.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Symbol {
+    pub base: String,
+    pub quote: String,
+}
+
+impl Symbol {
+    // Name of the symbol as used in websocket stream names e.g btcusdt.
+    pub fn stream_name(&self) -> String {
+        self.to_string().to_lowercase()
+    }
+
+    // Symbol as stored with its assets. Symbols stored before the assets were kept only have
+    // their name, which is split by Symbol::from_str.
+    pub fn from_stored(name: &str, base: Option<String>, quote: Option<String>) -> Result<Self, &'static str> {
+        match (base, quote) {
+            (Some(base), Some(quote)) => Ok(Symbol { base, quote }),
+            _ => name.parse(),
+        }
+    }
+}
+
+// Look up the base and quote asset of a symbol at the exchange. The known quote assets of
+// Symbol::from_str are only a fallback for when the exchange can't be reached.
+pub async fn find_symbol(api: &Api, name: &str) -> Result<Symbol, Error> {
+    let name = name.trim().to_uppercase();
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(Error::ParseError("Parse error for Symbol.".into()));
+    }
+
+    match api.symbol(&name).await {
+        Ok(symbol) => Ok(symbol),
+        Err(Error::RequestError(_)) => name.parse::<Symbol>().map_err(|e| Error::ParseError(e.into())),
+        Err(e) => Err(e),
+    }
+}
+
+impl Default for Symbol {
+    fn default() -> Self {
+        Symbol {
+            base: "BTC".into(),
+            quote: "USDT".into(),
+        }
+    }
+}
+
+// Split a symbol into base and quote asset by matching a known quote asset at the end. A pair
+// with another quote asset, e.g ETHBRL, can't be split, so symbols of the exchange are looked up
+// with find_symbol instead.
+impl std::str::FromStr for Symbol {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let quote_assets = ["FDUSD", "USDT", "USDC", "BUSD", "TUSD", "BTC", "ETH", "BNB", "EUR", "TRY"];
+        let s = s.trim().to_uppercase();
+
+        for quote in quote_assets {
+            if let Some(base) = s.strip_suffix(quote) {
+                if !base.is_empty() && base.chars().all(|c| c.is_ascii_alphanumeric()) {
+                    return Ok(Symbol {
+                        base: base.into(),
+                        quote: quote.into(),
+                    });
+                }
+            }
+        }
+
+        Err("Parse error for Symbol.")
+    }
+}
+
+impl std::fmt::Display for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}{}", self.base, self.quote)
+    }
+}
+
+// Free balance of an asset. Assets which are not in the balances have a balance of zero.
+pub fn balance_of(balances: &std::collections::HashMap<String, f64>, asset: &str) -> f64 {
+    balances.get(asset).copied().unwrap_or(0f64)
+}
+
+// The exchanges a user or a single algorithm can trade on.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ExchangeKind {
+    Binance,
+    Paper,
+}
+
+impl std::str::FromStr for ExchangeKind {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_uppercase().as_str() {
+            "BINANCE" => Ok(ExchangeKind::Binance),
+            "PAPER" => Ok(ExchangeKind::Paper),
+            _ => Err("Parse error for ExchangeKind."),
+        }
+    }
+}
+
+impl std::fmt::Display for ExchangeKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ExchangeKind::Binance => write!(f, "binance"),
+            ExchangeKind::Paper => write!(f, "paper"),
+        }
+    }
+}
+
+// Retrieve the ID of the user with this session token.
+pub async fn user_id(session_token: &str, psql: Psql) -> Result<i32, Error> {
+    let query = psql.lock().await
+        .query("
+            SELECT
+                id
+            FROM
+                users
+            WHERE
+                session_token = $1
+         ", &[&session_token]).await;
+
+    match query {
+        Ok(q) => {
+            if q.is_empty() {
+                return Err(Error::AuthenticationError("No user found with session token.".into()));
+            }
+
+            Ok(q[0].get("id"))
+        },
+        Err(e) => {
+            Err(Error::DatabaseError(format!("{}", e)))
+        }
+    }
+}
+
+// Retrieve the exchange the user with this session token has selected.
+pub async fn user_exchange(session_token: &str, psql: Psql) -> Result<ExchangeKind, Error> {
+    let user_id = user_id(session_token, psql.clone()).await?;
+    exchange_of_user(user_id, psql).await
+}
+
+// Retrieve the exchange a user has selected.
+pub async fn exchange_of_user(user_id: i32, psql: Psql) -> Result<ExchangeKind, Error> {
+    let query = psql.lock().await
+        .query("
+            SELECT
+                exchange
+            FROM
+                users
+            WHERE
+                id = $1
+         ", &[&user_id]).await;
+
+    match query {
+        Ok(q) => {
+            if q.is_empty() {
+                return Err(Error::AuthenticationError("No user found with ID.".into()));
+            }
+
+            let exchange : String = q[0].get("exchange");
+            exchange.parse::<ExchangeKind>().map_err(|e| Error::ParseError(e.into()))
+        },
+        Err(e) => {
+            Err(Error::DatabaseError(format!("{}", e)))
+        }
+    }
+}
+
+// Create an API instance for the given exchange and authenticate it with the session token.
+pub async fn authenticated_instance(exchange: ExchangeKind, session_token: &str, psql: Psql) -> Result<Api, Error> {
+    let mut api = create_api_instance(exchange);
+    api.auth(session_token, psql).await?;
+
+    Ok(Arc::from(api))
+}
+
+// Create an API instance for the given exchange with the stored keys of a user. Used when there
+// is no session, e.g when the server resumes the algorithms of a user who logged out.
+pub async fn user_instance(exchange: ExchangeKind, user_id: i32, psql: Psql) -> Result<Api, Error> {
+    let mut api = create_api_instance(exchange);
+    api.auth_user(user_id, psql).await?;
+
+    Ok(Arc::from(api))
+}
+
 // Error type for ExchangeAPI.
 #[derive(Debug)]
 pub enum Error {
@@ -28,6 +227,10 @@ pub enum Error {
     ParseError(String),
     DatabaseError(String),
     AuthenticationError(String),
+    // The exchange refused the order, it was not placed.
+    OrderRejected(String),
+    // The exchange doesn't know the order.
+    OrderNotFound(String),
 }
 
 impl std::error::Error for Error {}
@@ -49,6 +252,12 @@ impl From<reqwest::Error> for Error {
     }
 }
 
+impl From<tokio_postgres::Error> for Error {
+    fn from(e: tokio_postgres::Error) -> Self {
+        Error::DatabaseError(e.to_string())
+    }
+}
+
 impl From<url::ParseError> for Error {
     fn from(e: url::ParseError) -> Self {
         Error::ParseError(e.to_string())
@@ -63,7 +272,27 @@ impl std::fmt::Display for Error {
             Error::DatabaseError(error_msg) => write!(f, "\x1b[31m[Error] ExchangeAPI - DatabaseError: {}\x1b[0m", error_msg),
             Error::RequestError(error_msg) => write!(f, "\x1b[31m[Error] ExchangeAPI - RequestError: {}\x1b[0m", error_msg),
             Error::AuthenticationError(error_msg) => write!(f, "\x1b[31m[Error] ExchangeAPI - AuthenticationError: {}\x1b[0m", error_msg),
+            Error::OrderRejected(error_msg) => write!(f, "\x1b[31m[Error] ExchangeAPI - OrderRejected: {}\x1b[0m", error_msg),
+            Error::OrderNotFound(error_msg) => write!(f, "\x1b[31m[Error] ExchangeAPI - OrderNotFound: {}\x1b[0m", error_msg),
         }
     }
 }
 
+
+
+// Testing the symbols.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_symbol() {
+        assert_eq!("ethbtc".parse::<Symbol>(), Ok(Symbol { base: "ETH".into(), quote: "BTC".into() }));
+        assert!("ETHBRL".parse::<Symbol>().is_err());
+
+        // The stored assets are used, the name is only split without them.
+        assert_eq!(Symbol::from_stored("ETHBRL", Some("ETH".into()), Some("BRL".into())), Ok(Symbol { base: "ETH".into(), quote: "BRL".into() }));
+        assert_eq!(Symbol::from_stored("BTCUSDT", None, None), Ok(Symbol::default()));
+        assert!(Symbol::from_stored("ETHBRL", None, None).is_err());
+    }
+}
@@ -0,0 +1,311 @@
+// Typed order requests. The parameters of the exchange are built from the request in one place,
+// see OrderRequest::params, so callers can't forget a parameter a type of order requires.
+//
+// - MARKET fills right away at the best price.
+// - LIMIT rests on the book until the price is reached.
+// - STOP_LOSS and TAKE_PROFIT place a MARKET order once the stop price is reached.
+// - STOP_LOSS_LIMIT and TAKE_PROFIT_LIMIT place a LIMIT order once the stop price is reached.
+// - OCO places a limit maker order and a stop-loss, when one of them fills the other one is
+//   canceled. Used to close a position at a target or a stop, whichever comes first.
+
+use super::*;
+use crate::protocol::{Side, TimeInForce};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum OrderKind {
+    // Either quantity (base asset) or quote_quantity (quote asset) is set.
+    Market {
+        quantity: Option<f64>,
+        quote_quantity: Option<f64>,
+    },
+    Limit {
+        quantity: f64,
+        price: f64,
+        time_in_force: Option<TimeInForce>,
+    },
+    StopLoss {
+        quantity: f64,
+        stop_price: f64,
+    },
+    TakeProfit {
+        quantity: f64,
+        stop_price: f64,
+    },
+    StopLossLimit {
+        quantity: f64,
+        price: f64,
+        stop_price: f64,
+        time_in_force: Option<TimeInForce>,
+    },
+    TakeProfitLimit {
+        quantity: f64,
+        price: f64,
+        stop_price: f64,
+        time_in_force: Option<TimeInForce>,
+    },
+    // The limit maker is placed at price, the stop-loss at stop_price. Without
+    // stop_limit_price the stop-loss is a market order.
+    Oco {
+        quantity: f64,
+        price: f64,
+        stop_price: f64,
+        stop_limit_price: Option<f64>,
+        time_in_force: Option<TimeInForce>,
+    },
+}
+
+impl OrderKind {
+    // Name of the order type at the exchange.
+    pub fn name(&self) -> &'static str {
+        match self {
+            OrderKind::Market { .. } => "MARKET",
+            OrderKind::Limit { .. } => "LIMIT",
+            OrderKind::StopLoss { .. } => "STOP_LOSS",
+            OrderKind::TakeProfit { .. } => "TAKE_PROFIT",
+            OrderKind::StopLossLimit { .. } => "STOP_LOSS_LIMIT",
+            OrderKind::TakeProfitLimit { .. } => "TAKE_PROFIT_LIMIT",
+            OrderKind::Oco { .. } => "OCO",
+        }
+    }
+}
+
+// An order of a symbol. The client order ID identifies the order at the exchange, for an OCO
+// it identifies the list and both orders get an ID derived from it, see legs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OrderRequest {
+    pub symbol: Symbol,
+    pub side: Side,
+    pub kind: OrderKind,
+    pub client_order_id: String,
+}
+
+// A single order at the exchange which is placed by a request.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Leg {
+    pub client_order_id: String,
+    pub order_type: &'static str,
+    pub quantity: Option<f64>,
+    pub price: Option<f64>,
+}
+
+impl OrderRequest {
+    // Check if the request describes an order the exchange can accept.
+    pub fn validate(&self) -> Result<(), String> {
+        fn positive(name: &str, value: Option<f64>) -> Result<(), String> {
+            match value {
+                Some(v) if !(v.is_finite() && v > 0f64) => Err(format!("{} must be a positive number.", name)),
+                _ => Ok(()),
+            }
+        }
+
+        match &self.kind {
+            OrderKind::Market { quantity, quote_quantity } => {
+                positive("quantity", *quantity)?;
+                positive("quote_quantity", *quote_quantity)?;
+
+                if quantity.is_some() == quote_quantity.is_some() {
+                    return Err("Exactly one of quantity and quote_quantity must be given.".into());
+                }
+            },
+            OrderKind::Limit { quantity, price, .. } => {
+                positive("quantity", Some(*quantity))?;
+                positive("price", Some(*price))?;
+            },
+            OrderKind::StopLoss { quantity, stop_price } | OrderKind::TakeProfit { quantity, stop_price } => {
+                positive("quantity", Some(*quantity))?;
+                positive("stop_price", Some(*stop_price))?;
+            },
+            OrderKind::StopLossLimit { quantity, price, stop_price, .. } | OrderKind::TakeProfitLimit { quantity, price, stop_price, .. } => {
+                positive("quantity", Some(*quantity))?;
+                positive("price", Some(*price))?;
+                positive("stop_price", Some(*stop_price))?;
+            },
+            OrderKind::Oco { quantity, price, stop_price, stop_limit_price, .. } => {
+                positive("quantity", Some(*quantity))?;
+                positive("price", Some(*price))?;
+                positive("stop_price", Some(*stop_price))?;
+                positive("stop_limit_price", *stop_limit_price)?;
+
+                // Selling takes profit above and stops the loss below, buying the reverse.
+                let ordered = match self.side {
+                    Side::Sell => price > stop_price,
+                    Side::Buy => price < stop_price,
+                };
+                if !ordered {
+                    return Err("price and stop_price of an OCO are on the wrong side of each other.".into());
+                }
+            },
+        }
+
+        // Allowed characters of a client order ID at Binance.
+        for leg in self.legs() {
+            let id = &leg.client_order_id;
+            if id.is_empty() || id.len() > 36 || !id.chars().all(|c| c.is_ascii_alphanumeric() || "._-:/".contains(c)) {
+                return Err(format!("Invalid client order ID {}.", id));
+            }
+        }
+
+        Ok(())
+    }
+
+    // The orders placed by the request, in the order the exchange reports them.
+    pub fn legs(&self) -> std::vec::Vec<Leg> {
+        let leg = |client_order_id: String, order_type, quantity, price| Leg { client_order_id, order_type, quantity, price };
+
+        match &self.kind {
+            OrderKind::Market { quantity, .. } => vec![leg(self.client_order_id.to_string(), "MARKET", *quantity, None)],
+            OrderKind::Limit { quantity, price, .. }
+            | OrderKind::StopLossLimit { quantity, price, .. }
+            | OrderKind::TakeProfitLimit { quantity, price, .. } => {
+                vec![leg(self.client_order_id.to_string(), self.kind.name(), Some(*quantity), Some(*price))]
+            },
+            OrderKind::StopLoss { quantity, stop_price } | OrderKind::TakeProfit { quantity, stop_price } => {
+                vec![leg(self.client_order_id.to_string(), self.kind.name(), Some(*quantity), Some(*stop_price))]
+            },
+            OrderKind::Oco { quantity, price, stop_price, stop_limit_price, .. } => {
+                let stop_type = if stop_limit_price.is_some() { "STOP_LOSS_LIMIT" } else { "STOP_LOSS" };
+                vec![
+                    leg(format!("{}-S", self.client_order_id), stop_type, Some(*quantity), Some(stop_limit_price.unwrap_or(*stop_price))),
+                    leg(format!("{}-L", self.client_order_id), "LIMIT_MAKER", Some(*quantity), Some(*price)),
+                ]
+            },
+        }
+    }
+
+    // Parameters of the request at Binance, without timestamp and signature.
+    // Read: https://binance-docs.github.io/apidocs/spot/en/#new-order-trade
+    // Read: https://binance-docs.github.io/apidocs/spot/en/#new-oco-trade
+    pub fn params(&self) -> std::collections::HashMap<String, String> {
+        let mut params = std::collections::HashMap::<String, String>::new();
+        params.insert("symbol".into(), self.symbol.to_string());
+        params.insert("side".into(), self.side.to_string());
+
+        let time_in_force = |t: &Option<TimeInForce>| t.unwrap_or(TimeInForce::Gtc).to_string();
+
+        match &self.kind {
+            OrderKind::Oco { quantity, price, stop_price, stop_limit_price, time_in_force: t } => {
+                let legs = self.legs();
+                params.insert("listClientOrderId".into(), self.client_order_id.to_string());
+                params.insert("stopClientOrderId".into(), legs[0].client_order_id.to_string());
+                params.insert("limitClientOrderId".into(), legs[1].client_order_id.to_string());
+                params.insert("quantity".into(), quantity.to_string());
+                params.insert("price".into(), price.to_string());
+                params.insert("stopPrice".into(), stop_price.to_string());
+
+                if let Some(stop_limit_price) = stop_limit_price {
+                    params.insert("stopLimitPrice".into(), stop_limit_price.to_string());
+                    params.insert("stopLimitTimeInForce".into(), time_in_force(t));
+                }
+
+                return params;
+            },
+            OrderKind::Market { quantity, quote_quantity } => {
+                if let Some(q) = quantity {
+                    params.insert("quantity".into(), q.to_string());
+                }
+                if let Some(q) = quote_quantity {
+                    params.insert("quoteOrderQty".into(), q.to_string());
+                }
+            },
+            OrderKind::Limit { quantity, price, time_in_force: t } => {
+                params.insert("quantity".into(), quantity.to_string());
+                params.insert("price".into(), price.to_string());
+                params.insert("timeInForce".into(), time_in_force(t));
+            },
+            OrderKind::StopLoss { quantity, stop_price } | OrderKind::TakeProfit { quantity, stop_price } => {
+                params.insert("quantity".into(), quantity.to_string());
+                params.insert("stopPrice".into(), stop_price.to_string());
+            },
+            OrderKind::StopLossLimit { quantity, price, stop_price, time_in_force: t }
+            | OrderKind::TakeProfitLimit { quantity, price, stop_price, time_in_force: t } => {
+                params.insert("quantity".into(), quantity.to_string());
+                params.insert("price".into(), price.to_string());
+                params.insert("stopPrice".into(), stop_price.to_string());
+                params.insert("timeInForce".into(), time_in_force(t));
+            },
+        }
+
+        params.insert("type".into(), self.kind.name().into());
+        params.insert("newClientOrderId".into(), self.client_order_id.to_string());
+
+        params
+    }
+
+    // Quantity of the request in the base asset, None for a MARKET order in the quote asset.
+    pub fn quantity(&self) -> Option<f64> {
+        match &self.kind {
+            OrderKind::Market { quantity, .. } => *quantity,
+            OrderKind::Limit { quantity, .. }
+            | OrderKind::StopLoss { quantity, .. }
+            | OrderKind::TakeProfit { quantity, .. }
+            | OrderKind::StopLossLimit { quantity, .. }
+            | OrderKind::TakeProfitLimit { quantity, .. }
+            | OrderKind::Oco { quantity, .. } => Some(*quantity),
+        }
+    }
+
+    // Check if the request places a list of orders instead of a single order.
+    pub fn is_list(&self) -> bool {
+        matches!(self.kind, OrderKind::Oco { .. })
+    }
+}
+
+
+// Testing the typed order requests.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(side: Side, kind: OrderKind) -> OrderRequest {
+        OrderRequest {
+            symbol: Symbol::default(),
+            side,
+            kind,
+            client_order_id: "tag-abc123".into(),
+        }
+    }
+
+    #[test]
+    fn test_params() {
+        let limit = request(Side::Buy, OrderKind::Limit { quantity: 0.5, price: 40000.0, time_in_force: None });
+        let params = limit.params();
+        assert_eq!(params["type"], "LIMIT");
+        assert_eq!(params["price"], "40000");
+        assert_eq!(params["timeInForce"], "GTC");
+        assert_eq!(params["newClientOrderId"], "tag-abc123");
+        assert_eq!(limit.validate(), Ok(()));
+
+        let stop = request(Side::Sell, OrderKind::StopLossLimit { quantity: 0.5, price: 38900.0, stop_price: 39000.0, time_in_force: Some(TimeInForce::Ioc) });
+        let params = stop.params();
+        assert_eq!(params["type"], "STOP_LOSS_LIMIT");
+        assert_eq!(params["stopPrice"], "39000");
+        assert_eq!(params["timeInForce"], "IOC");
+
+        // An OCO is a list with an ID for both orders and no type.
+        let oco = request(Side::Sell, OrderKind::Oco { quantity: 0.5, price: 42000.0, stop_price: 39000.0, stop_limit_price: None, time_in_force: None });
+        let params = oco.params();
+        assert!(oco.is_list());
+        assert_eq!(params.get("type"), None);
+        assert_eq!(params["listClientOrderId"], "tag-abc123");
+        assert_eq!(params.get("stopLimitPrice"), None);
+        assert_eq!(oco.legs().iter().map(|l| (&*l.client_order_id, l.order_type)).collect::<Vec<_>>(),
+            vec![("tag-abc123-S", "STOP_LOSS"), ("tag-abc123-L", "LIMIT_MAKER")]);
+        assert_eq!(oco.validate(), Ok(()));
+
+        // Selling takes profit above the stop.
+        let wrong = request(Side::Sell, OrderKind::Oco { quantity: 0.5, price: 39000.0, stop_price: 42000.0, stop_limit_price: None, time_in_force: None });
+        assert!(wrong.validate().is_err());
+        assert!(request(Side::Buy, OrderKind::Market { quantity: Some(1.0), quote_quantity: Some(100.0) }).validate().is_err());
+        assert!(request(Side::Buy, OrderKind::Limit { quantity: 0.5, price: -1.0, time_in_force: None }).validate().is_err());
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let kind: OrderKind = serde_json::from_str(r#"{"type": "TAKE_PROFIT_LIMIT", "quantity": 0.1, "price": 45000, "stop_price": 44900, "side": "SELL"}"#).unwrap();
+        assert_eq!(kind, OrderKind::TakeProfitLimit { quantity: 0.1, price: 45000.0, stop_price: 44900.0, time_in_force: None });
+
+        let kind: OrderKind = serde_json::from_str(r#"{"type": "OCO", "quantity": 0.1, "price": 45000, "stop_price": 41000, "stop_limit_price": 40900, "time_in_force": "GTC"}"#).unwrap();
+        assert_eq!(kind.name(), "OCO");
+    }
+}
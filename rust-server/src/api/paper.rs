@@ -0,0 +1,396 @@
+// PaperExchange simulates an exchange in-process. Market data is read from the public
+// Binance API, but orders never leave the server: they are filled at the last streamed price
+// against virtual balances which are kept per user in the database.
+
+use super::*;
+use super::binance::Binance;
+use serde_json::json;
+
+lazy_static! {
+    // Last price received per symbol, with the time it was received. Shared by all instances so
+    // every user fills at the same price.
+    pub static ref LAST_PRICES: Mutex<std::collections::HashMap<String, (std::time::Instant, f64)>> = Mutex::new(std::collections::HashMap::new());
+
+    // Next order ID. It starts at the time the server started in milliseconds, so the IDs stay
+    // unique after a restart as long as there was less than one order per millisecond.
+    static ref NEXT_ORDER_ID: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(
+        std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis() as u64
+    );
+}
+
+// The amounts of a paper order which is filled at once.
+#[derive(Debug, PartialEq)]
+struct Fill {
+    quantity: f64,
+    quote: f64,
+    base_delta: f64,
+    quote_delta: f64,
+    commission: f64,
+    commission_asset: String,
+}
+
+// Fill an order at the price. A LIMIT order is only filled when it is immediately marketable.
+// Commission is charged in the asset that is received, like on Binance.
+fn fill(symbol: &Symbol, side: &str, order_type: &str, params: &std::collections::HashMap<String, String>, price: f64, commission_rate: f64) -> Result<Fill, api::Error> {
+    if order_type == "LIMIT" {
+        let limit_price = match params.get("price") {
+            Some(p) => p.parse::<f64>().map_err(|e| api::Error::ParseError(e.to_string()))?,
+            None => {
+                return Err(api::Error::ExchangeAPIError("LIMIT order requires price.".into()));
+            }
+        };
+
+        let marketable = (side == "BUY" && limit_price >= price) || (side == "SELL" && limit_price <= price);
+        if !marketable {
+            return Err(api::Error::ExchangeAPIError("Paper exchange only fills LIMIT orders which are immediately marketable.".into()));
+        }
+    }
+    let quantity = match (params.get("quantity"), params.get("quoteOrderQty")) {
+        (Some(q), _) => q.parse::<f64>().map_err(|e| api::Error::ParseError(e.to_string()))?,
+        (None, Some(q)) => q.parse::<f64>().map_err(|e| api::Error::ParseError(e.to_string()))? / price,
+        (None, None) => {
+            return Err(api::Error::ExchangeAPIError("Order requires quantity or quoteOrderQty.".into()));
+        }
+    };
+
+    if quantity <= 0f64 {
+        return Err(api::Error::ExchangeAPIError("Invalid quantity.".into()));
+    }
+
+    let quote = quantity * price;
+    let (base_delta, quote_delta, commission, commission_asset) = match side {
+        "BUY" => (quantity * (1f64 - commission_rate), -quote, quantity * commission_rate, &symbol.base),
+        "SELL" => (-quantity, quote * (1f64 - commission_rate), quote * commission_rate, &symbol.quote),
+        _ => {
+            return Err(api::Error::ExchangeAPIError(format!("Invalid side {}.", side)));
+        }
+    };
+
+    Ok(Fill { quantity, quote, base_delta, quote_delta, commission, commission_asset: commission_asset.clone() })
+}
+
+// Whether the balances of the user cover the fill.
+fn affordable(balances: &std::collections::HashMap<String, f64>, symbol: &Symbol, fill: &Fill) -> bool {
+    balance_of(balances, &symbol.quote) + fill.quote_delta >= 0f64 && balance_of(balances, &symbol.base) + fill.base_delta >= 0f64
+}
+
+#[derive(Clone)]
+pub struct PaperExchange {
+    market: Binance,
+    user_id: Option<i32>,
+    psql: Option<Psql>,
+}
+
+#[async_trait]
+impl ExchangeAPI for PaperExchange {
+
+    // Construct PaperExchange object. The URLs are used to retrieve market data, the keys are
+    // ignored because no signed requests are made.
+    fn new(rest_api_url: &str, ws_api_url: &str, ws_stream_url: &str, _api_key: &str, _api_secret: &str) -> Self {
+        PaperExchange {
+            market: ExchangeAPI::new(rest_api_url, ws_api_url, ws_stream_url, "", ""),
+            user_id: None,
+            psql: None,
+        }
+    }
+
+    // Bind this instance to the user with the given session token.
+    async fn auth(&mut self, session_token: &str, psql: Psql) -> Result<(), api::Error> {
+        let user_id = api::user_id(session_token, psql.clone()).await?;
+        self.auth_user(user_id, psql).await
+    }
+
+    // Bind this instance to the user.
+    async fn auth_user(&mut self, user_id: i32, psql: Psql) -> Result<(), api::Error> {
+        self.user_id = Some(user_id);
+        self.psql = Some(psql);
+
+        Ok(())
+    }
+
+    fn get_urls(&self, url_type: &str) -> Option<&str> {
+        self.market.get_urls(url_type)
+    }
+
+    // The paper exchange is online as long as we can retrieve market data.
+    async fn ping(&self) -> bool {
+        self.market.ping().await
+    }
+
+    // Fill a MARKET order, or a LIMIT order which is immediately marketable, at the last
+    // streamed price and update the virtual balances. Orders are never kept open.
+    // Returns a response in the same format as the Binance order endpoint.
+    async fn order(&self, params: &mut std::collections::HashMap<String, String>) -> Result<String, api::Error> {
+        let (user_id, psql) = self.session()?;
+
+        let symbol = match params.get("symbol") {
+            Some(s) => s.parse::<Symbol>().map_err(|e| api::Error::ParseError(e.into()))?,
+            None => Symbol::default(),
+        };
+        let side = params.get("side").cloned().unwrap_or_default().to_uppercase();
+        let order_type = params.get("type").cloned().unwrap_or_default().to_uppercase();
+        let client_order_id = params.get("newClientOrderId").cloned().unwrap_or_default();
+
+        if order_type != "MARKET" && order_type != "LIMIT" {
+            return Err(api::Error::ExchangeAPIError(format!("Paper exchange does not support order type {}.", order_type)));
+        }
+
+        let price = self.last_price(&symbol).await?;
+        let fill = fill(&symbol, &side, &order_type, params, price, *config::PAPER_COMMISSION_RATE)?;
+
+        // Hold the lock for the whole update so concurrent orders of the same user can't
+        // spend the same balance twice. Both balances are changed or neither.
+        let mut client = psql.lock().await;
+        let transaction = client.transaction().await?;
+        let balances = PaperExchange::balances(&transaction, user_id).await?;
+
+        if !affordable(&balances, &symbol, &fill) {
+            return Err(api::Error::ExchangeAPIError("Account has insufficient balance for requested action.".into()));
+        }
+
+        for (asset, delta) in [(&symbol.quote, fill.quote_delta), (&symbol.base, fill.base_delta)] {
+            transaction.query("
+                INSERT INTO paper_balances
+                    (user_id, asset, free)
+                VALUES
+                    ($1, $2, $3)
+                ON CONFLICT (user_id, asset) DO UPDATE SET
+                    free = paper_balances.free + EXCLUDED.free
+            ", &[&user_id, asset, sqlf64!(delta)]).await?;
+        }
+        transaction.commit().await?;
+        drop(client);
+
+        let transact_time = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis() as u64;
+
+        Ok(json!({
+            "symbol": symbol.to_string(),
+            "orderId": NEXT_ORDER_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed),
+            "clientOrderId": client_order_id,
+            "transactTime": transact_time,
+            "price": "0.00000000",
+            "origQty": format!("{:.8}", fill.quantity),
+            "executedQty": format!("{:.8}", fill.quantity),
+            "cummulativeQuoteQty": format!("{:.8}", fill.quote),
+            "status": "FILLED",
+            "type": order_type,
+            "side": side,
+            "fills": [{
+                "price": format!("{:.8}", price),
+                "qty": format!("{:.8}", fill.quantity),
+                "commission": format!("{:.8}", fill.commission),
+                "commissionAsset": fill.commission_asset,
+            }],
+        }).to_string())
+    }
+
+    // Orders are only kept as balance changes, there is no separate trade history.
+    async fn trade_history(&self, _symbol: &Symbol) -> Result<(), api::Error> {
+        Ok(())
+    }
+
+    // Only MARKET orders and LIMIT orders which are immediately marketable can be filled, orders
+    // which would rest on the book are refused.
+    async fn place_order(&self, order: &order::OrderRequest) -> Result<std::vec::Vec<serde_json::Value>, api::Error> {
+        match order.kind {
+            order::OrderKind::Market { .. } | order::OrderKind::Limit { .. } => {
+                let response = self.order(&mut order.params()).await?;
+                Ok(vec![serde_json::from_str(&response)?])
+            },
+            _ => Err(api::Error::OrderRejected(format!("Paper exchange does not support order type {}.", order.kind.name()))),
+        }
+    }
+
+    // Orders on the paper exchange don't go over the websocket, they are filled directly. An
+    // order which can't be filled is refused.
+    async fn ws_order(&self, order: &order::OrderRequest, _ws_api: &wsapi::WsApi) -> Result<std::vec::Vec<serde_json::Value>, api::Error> {
+        match self.place_order(order).await {
+            Err(api::Error::ExchangeAPIError(e)) => Err(api::Error::OrderRejected(e)),
+            response => response,
+        }
+    }
+
+    // Orders are filled or refused when they are placed, so there are no open orders.
+    async fn open_orders(&self, _symbol: Option<&Symbol>) -> Result<std::vec::Vec<serde_json::Value>, api::Error> {
+        Ok(vec![])
+    }
+
+    async fn cancel_order(&self, _symbol: &Symbol, client_order_id: &str) -> Result<serde_json::Value, api::Error> {
+        Err(api::Error::OrderNotFound(format!("Paper exchange does not keep order {}.", client_order_id)))
+    }
+
+    // Orders are filled or refused when they are placed, so they are never open.
+    async fn order_status(&self, _symbol: &Symbol, client_order_id: &str) -> Result<serde_json::Value, api::Error> {
+        Err(api::Error::OrderNotFound(format!("Paper exchange does not keep order {}.", client_order_id)))
+    }
+
+    // The fills are in the response of the order.
+    async fn order_trades(&self, _symbol: &Symbol, _order_id: i64) -> Result<std::vec::Vec<serde_json::Value>, api::Error> {
+        Ok(vec![])
+    }
+
+    // Get virtual account balance of every asset the user holds.
+    async fn account_balance(&self) -> Result<std::collections::HashMap<String, f64>, api::Error> {
+        let (user_id, psql) = self.session()?;
+        let client = psql.lock().await;
+
+        PaperExchange::balances(&*client, user_id).await
+    }
+
+    async fn klines(&self, params: &mut std::collections::HashMap<String, String>) -> Result<std::vec::Vec<serde_json::Value>, api::Error> {
+        self.market.klines(params).await
+    }
+
+    // Stream klines from the market and remember the last close price so orders are filled
+    // at the price the algorithm has seen.
+    async fn ws_kline(&self, s: Arc<Mutex<mpsc::Sender<CandleStick>>>, symbol: &Symbol, interval: String) -> Result<(), api::Error> {
+        let (market_tx, mut market_rx) = mpsc::channel::<CandleStick>(10);
+        let market_tx = Arc::new(Mutex::new(market_tx));
+
+        let market = self.market.clone();
+        let market_symbol = symbol.clone();
+        let market_handle = tokio::spawn(async move {
+            market.ws_kline(market_tx, &market_symbol, interval).await
+        });
+
+        while let Some(c) = market_rx.recv().await {
+            LAST_PRICES.lock().await.insert(symbol.to_string(), (std::time::Instant::now(), c.close));
+
+            if let Err(e) = s.lock().await.send(c).await {
+                market_handle.abort();
+                return Err(api::Error::ExchangeAPIError(format!("{}", e)));
+            }
+        }
+
+        match market_handle.await {
+            Ok(r) => r,
+            Err(e) => Err(api::Error::ExchangeAPIError(format!("{}", e))),
+        }
+    }
+
+    async fn get_price(&self, symbol: &Symbol) -> Result<f64, api::Error> {
+        let price = self.market.get_price(symbol).await?;
+        LAST_PRICES.lock().await.insert(symbol.to_string(), (std::time::Instant::now(), price));
+
+        Ok(price)
+    }
+
+    // Orders follow the rules of the symbol at Binance, like a real order would.
+    async fn symbol_rules(&self, symbol: &Symbol) -> Result<rules::SymbolRules, api::Error> {
+        self.market.symbol_rules(symbol).await
+    }
+
+    async fn symbol(&self, name: &str) -> Result<Symbol, api::Error> {
+        self.market.symbol(name).await
+    }
+
+    fn keys(&self) -> String {
+        "".into()
+    }
+}
+
+impl PaperExchange {
+    // Return the user and database connection this instance is bound to.
+    fn session(&self) -> Result<(i32, Psql), api::Error> {
+        match (self.user_id, self.psql.clone()) {
+            (Some(user_id), Some(psql)) => Ok((user_id, psql)),
+            _ => Err(api::Error::AuthenticationError("Paper exchange is not authenticated.".into())),
+        }
+    }
+
+    // Last streamed price of the symbol. Falls back to the REST API if nothing has been
+    // streamed for PAPER_PRICE_MAX_AGE_MS, e.g when the stream stopped.
+    async fn last_price(&self, symbol: &Symbol) -> Result<f64, api::Error> {
+        if let Some((received_at, price)) = LAST_PRICES.lock().await.get(&symbol.to_string()) {
+            if received_at.elapsed().as_millis() < *config::PAPER_PRICE_MAX_AGE_MS as u128 {
+                return Ok(*price);
+            }
+        }
+
+        self.get_price(symbol).await
+    }
+
+    // Retrieve the virtual balances of the user. A user without balances gets the configured
+    // start funds in USDT.
+    async fn balances(client: &impl tokio_postgres::GenericClient, user_id: i32) -> Result<std::collections::HashMap<String, f64>, api::Error> {
+        client.query("
+            INSERT INTO paper_balances
+                (user_id, asset, free)
+            SELECT
+                $1, 'USDT', $2
+            WHERE NOT EXISTS (
+                SELECT 1 FROM paper_balances WHERE user_id = $1
+            )
+        ", &[&user_id, sqlf64!(*config::PAPER_START_FUNDS_USDT)]).await?;
+
+        let rows = client.query("
+            SELECT
+                asset, free
+            FROM
+                paper_balances
+            WHERE
+                user_id = $1
+        ", &[&user_id]).await?;
+
+        let mut balances = std::collections::HashMap::<String, f64>::new();
+        for row in rows {
+            balances.insert(row.get("asset"), sqldec!(row.get::<&str, Decimal>("free")));
+        }
+
+        Ok(balances)
+    }
+}
+
+
+// Testing the fills of the paper exchange.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(pairs: &[(&str, &str)]) -> std::collections::HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_fill() {
+        let symbol = Symbol::default();
+
+        // The commission of a buy is paid in the base asset, of a sell in the quote asset.
+        let buy = fill(&symbol, "BUY", "MARKET", &params(&[("quantity", "0.5")]), 40000f64, 0.001).unwrap();
+        assert_eq!((buy.quantity, buy.quote), (0.5, 20000f64));
+        assert_eq!((buy.base_delta, buy.quote_delta), (0.4995, -20000f64));
+        assert_eq!((buy.commission, &*buy.commission_asset), (0.0005, "BTC"));
+
+        let sell = fill(&symbol, "SELL", "MARKET", &params(&[("quantity", "0.5")]), 40000f64, 0.001).unwrap();
+        assert_eq!((sell.base_delta, sell.quote_delta), (-0.5, 19980f64));
+        assert_eq!((sell.commission, &*sell.commission_asset), (20f64, "USDT"));
+
+        // A quote quantity is bought at the price.
+        let buy = fill(&symbol, "BUY", "MARKET", &params(&[("quoteOrderQty", "100")]), 40000f64, 0f64).unwrap();
+        assert_eq!(buy.quantity, 0.0025);
+
+        // A LIMIT order is only filled when it is marketable.
+        assert!(fill(&symbol, "BUY", "LIMIT", &params(&[("quantity", "1"), ("price", "40001")]), 40000f64, 0f64).is_ok());
+        assert!(fill(&symbol, "BUY", "LIMIT", &params(&[("quantity", "1"), ("price", "39999")]), 40000f64, 0f64).is_err());
+        assert!(fill(&symbol, "SELL", "LIMIT", &params(&[("quantity", "1"), ("price", "40001")]), 40000f64, 0f64).is_err());
+        assert!(fill(&symbol, "BUY", "LIMIT", &params(&[("quantity", "1")]), 40000f64, 0f64).is_err());
+
+        assert!(fill(&symbol, "BUY", "MARKET", &params(&[]), 40000f64, 0f64).is_err());
+        assert!(fill(&symbol, "BUY", "MARKET", &params(&[("quantity", "0")]), 40000f64, 0f64).is_err());
+    }
+
+    #[test]
+    fn test_affordable() {
+        let symbol = Symbol::default();
+        let balances = std::collections::HashMap::from([("USDT".to_string(), 100f64), ("BTC".to_string(), 0.001)]);
+
+        let buy = fill(&symbol, "BUY", "MARKET", &params(&[("quoteOrderQty", "100")]), 40000f64, 0.001).unwrap();
+        assert!(affordable(&balances, &symbol, &buy));
+        let buy = fill(&symbol, "BUY", "MARKET", &params(&[("quoteOrderQty", "101")]), 40000f64, 0.001).unwrap();
+        assert!(!affordable(&balances, &symbol, &buy));
+
+        let sell = fill(&symbol, "SELL", "MARKET", &params(&[("quantity", "0.002")]), 40000f64, 0.001).unwrap();
+        assert!(!affordable(&balances, &symbol, &sell));
+        assert!(!affordable(&std::collections::HashMap::new(), &symbol, &sell));
+    }
+}
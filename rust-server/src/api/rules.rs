@@ -0,0 +1,250 @@
+// Trading rules of a symbol at the exchange. Orders which break them are refused, so every
+// order is rounded to the rules before it is sent, see SymbolRules::normalize:
+// - PRICE_FILTER: prices are a multiple of the tick size, between the minimum and maximum price.
+// - LOT_SIZE: quantities are a multiple of the step size, between the minimum and maximum
+//   quantity.
+// - MIN_NOTIONAL or NOTIONAL: the value of an order in the quote asset is at least the minimum
+//   notional.
+// A value of zero means the rule doesn't apply.
+
+use super::*;
+use order::{OrderKind, OrderRequest};
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SymbolRules {
+    pub tick_size: f64,
+    pub min_price: f64,
+    pub max_price: f64,
+    pub step_size: f64,
+    pub min_quantity: f64,
+    pub max_quantity: f64,
+    pub min_notional: f64,
+    // Decimals of an amount in the quote asset, e.g quoteOrderQty.
+    pub quote_precision: i32,
+}
+
+// Decimals of a step e.g 0.00100000 -> 3.
+fn decimals(step: f64) -> usize {
+    let mut decimals = 0;
+    while decimals < 16 {
+        let scaled = step * 10f64.powi(decimals as i32);
+        if (scaled - scaled.round()).abs() < 1e-9 {
+            break;
+        }
+        decimals += 1;
+    }
+    decimals
+}
+
+// Round a value down to a multiple of the step. The result has no more decimals than the step,
+// so it is sent as e.g 0.001 instead of 0.0010000000000000002.
+fn floor_to(value: f64, step: f64) -> f64 {
+    if step <= 0f64 {
+        return value;
+    }
+    let rounded = (value / step + 1e-9).floor() * step;
+    format!("{:.*}", decimals(step), rounded).parse().unwrap_or(rounded)
+}
+
+// Round a value to the nearest multiple of the step, see floor_to.
+fn round_to(value: f64, step: f64) -> f64 {
+    if step <= 0f64 {
+        return value;
+    }
+    let rounded = (value / step).round() * step;
+    format!("{:.*}", decimals(step), rounded).parse().unwrap_or(rounded)
+}
+
+impl SymbolRules {
+    // Read the rules from a symbol in the response of exchangeInfo.
+    // Read: https://binance-docs.github.io/apidocs/spot/en/#filters
+    pub fn from_exchange_info(symbol: &serde_json::Value) -> Result<Self, Error> {
+        let filters = match symbol["filters"].as_array() {
+            Some(f) => f,
+            None => {
+                return Err(Error::ParseError("Symbol without filters in exchangeInfo.".into()));
+            }
+        };
+
+        let value = |filter: &serde_json::Value, key: &str| filter[key].as_str().and_then(|v| v.parse::<f64>().ok()).unwrap_or(0f64);
+
+        let mut rules = SymbolRules {
+            quote_precision: symbol["quoteAssetPrecision"].as_i64().unwrap_or(8) as i32,
+            ..Default::default()
+        };
+        for filter in filters {
+            match filter["filterType"].as_str().unwrap_or_default() {
+                "PRICE_FILTER" => {
+                    rules.tick_size = value(filter, "tickSize");
+                    rules.min_price = value(filter, "minPrice");
+                    rules.max_price = value(filter, "maxPrice");
+                },
+                "LOT_SIZE" => {
+                    rules.step_size = value(filter, "stepSize");
+                    rules.min_quantity = value(filter, "minQty");
+                    rules.max_quantity = value(filter, "maxQty");
+                },
+                "MIN_NOTIONAL" | "NOTIONAL" => {
+                    rules.min_notional = rules.min_notional.max(value(filter, "minNotional"));
+                },
+                _ => (),
+            }
+        }
+
+        Ok(rules)
+    }
+
+    // Round a quantity down to the step size and check it is allowed.
+    fn quantity(&self, quantity: f64) -> Result<f64, String> {
+        let rounded = floor_to(quantity, self.step_size);
+
+        if rounded <= 0f64 || rounded < self.min_quantity {
+            return Err(format!("Quantity {} is below the minimum quantity of {}.", quantity, self.min_quantity));
+        }
+        if self.max_quantity > 0f64 && rounded > self.max_quantity {
+            return Err(format!("Quantity {} is above the maximum quantity of {}.", quantity, self.max_quantity));
+        }
+
+        Ok(rounded)
+    }
+
+    // Round a price to the tick size and check it is allowed.
+    fn price(&self, price: f64) -> Result<f64, String> {
+        let rounded = round_to(price, self.tick_size);
+
+        if rounded <= 0f64 || rounded < self.min_price {
+            return Err(format!("Price {} is below the minimum price of {}.", price, self.min_price));
+        }
+        if self.max_price > 0f64 && rounded > self.max_price {
+            return Err(format!("Price {} is above the maximum price of {}.", price, self.max_price));
+        }
+
+        Ok(rounded)
+    }
+
+    // Check the value of an order in the quote asset.
+    fn notional(&self, notional: f64, symbol: &Symbol) -> Result<(), String> {
+        if notional < self.min_notional {
+            let notional = round_to(notional, 10f64.powi(-self.quote_precision));
+            return Err(format!("Order of {} {} is below the minimum notional of {} {}.", notional, symbol.quote, self.min_notional, symbol.quote));
+        }
+
+        Ok(())
+    }
+
+    // Round the quantities and prices of an order to the rules and check if the exchange
+    // accepts it. price is the expected price of a MARKET order.
+    pub fn normalize(&self, order: &mut OrderRequest, price: f64) -> Result<(), String> {
+        let symbol = order.symbol.clone();
+
+        match &mut order.kind {
+            OrderKind::Market { quantity: Some(q), .. } => {
+                *q = self.quantity(*q)?;
+                self.notional(*q * price, &symbol)?;
+            },
+            OrderKind::Market { quote_quantity: Some(q), .. } => {
+                *q = floor_to(*q, 10f64.powi(-self.quote_precision));
+                self.notional(*q, &symbol)?;
+            },
+            OrderKind::Market { .. } => (),
+            OrderKind::Limit { quantity, price, .. } => {
+                *price = self.price(*price)?;
+                *quantity = self.quantity(*quantity)?;
+                self.notional(*quantity * *price, &symbol)?;
+            },
+            OrderKind::StopLoss { quantity, stop_price } | OrderKind::TakeProfit { quantity, stop_price } => {
+                *stop_price = self.price(*stop_price)?;
+                *quantity = self.quantity(*quantity)?;
+                self.notional(*quantity * *stop_price, &symbol)?;
+            },
+            OrderKind::StopLossLimit { quantity, price, stop_price, .. } | OrderKind::TakeProfitLimit { quantity, price, stop_price, .. } => {
+                *price = self.price(*price)?;
+                *stop_price = self.price(*stop_price)?;
+                *quantity = self.quantity(*quantity)?;
+                self.notional(*quantity * *price, &symbol)?;
+            },
+            // Both orders of an OCO have to be allowed.
+            OrderKind::Oco { quantity, price, stop_price, stop_limit_price, .. } => {
+                *price = self.price(*price)?;
+                *stop_price = self.price(*stop_price)?;
+                if let Some(p) = stop_limit_price {
+                    *p = self.price(*p)?;
+                }
+                *quantity = self.quantity(*quantity)?;
+                self.notional(*quantity * *price, &symbol)?;
+                self.notional(*quantity * stop_limit_price.unwrap_or(*stop_price), &symbol)?;
+            },
+        }
+
+        Ok(())
+    }
+}
+
+
+// Testing the rules of symbols.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn btcusdt() -> SymbolRules {
+        let symbol = serde_json::json!({
+            "symbol": "BTCUSDT",
+            "quoteAssetPrecision": 8,
+            "filters": [
+                {"filterType": "PRICE_FILTER", "minPrice": "0.01000000", "maxPrice": "1000000.00000000", "tickSize": "0.01000000"},
+                {"filterType": "LOT_SIZE", "minQty": "0.00001000", "maxQty": "9000.00000000", "stepSize": "0.00001000"},
+                {"filterType": "NOTIONAL", "minNotional": "5.00000000", "applyMinToMarket": true, "maxNotional": "9000000.00000000"},
+            ],
+        });
+        SymbolRules::from_exchange_info(&symbol).unwrap()
+    }
+
+    fn request(kind: OrderKind) -> OrderRequest {
+        OrderRequest {
+            symbol: Symbol::default(),
+            side: protocol::Side::Buy,
+            kind,
+            client_order_id: "abc123".into(),
+        }
+    }
+
+    #[test]
+    fn test_from_exchange_info() {
+        let rules = btcusdt();
+        assert_eq!(rules.tick_size, 0.01);
+        assert_eq!(rules.step_size, 0.00001);
+        assert_eq!(rules.min_notional, 5f64);
+        assert_eq!(rules.quote_precision, 8);
+
+        assert_eq!(decimals(0.00001), 5);
+        assert_eq!(decimals(1f64), 0);
+        assert_eq!(floor_to(0.123456789, 0.00001), 0.12345);
+        assert_eq!(floor_to(0.3, 0.1), 0.3);
+        assert_eq!(round_to(42000.126, 0.01), 42000.13);
+    }
+
+    #[test]
+    fn test_normalize() {
+        let rules = btcusdt();
+
+        // Quantities are rounded down to the step, prices to the tick.
+        let mut order = request(OrderKind::Limit { quantity: 0.0123456789, price: 40000.123, time_in_force: None });
+        assert_eq!(rules.normalize(&mut order, 40000f64), Ok(()));
+        assert_eq!(order.kind, OrderKind::Limit { quantity: 0.01234, price: 40000.12, time_in_force: None });
+        assert_eq!(order.params()["quantity"], "0.01234");
+
+        let mut order = request(OrderKind::Market { quantity: None, quote_quantity: Some(100.123456789) });
+        assert_eq!(rules.normalize(&mut order, 40000f64), Ok(()));
+        assert_eq!(order.kind, OrderKind::Market { quantity: None, quote_quantity: Some(100.12345678) });
+
+        // Orders below the minimum notional or quantity are refused.
+        let mut order = request(OrderKind::Market { quantity: Some(0.0001), quote_quantity: None });
+        assert_eq!(rules.normalize(&mut order, 40000f64), Err("Order of 4 USDT is below the minimum notional of 5 USDT.".into()));
+        let mut order = request(OrderKind::Market { quantity: Some(0.000001), quote_quantity: None });
+        assert!(rules.normalize(&mut order, 40000f64).is_err());
+
+        // Both orders of an OCO are checked.
+        let mut order = request(OrderKind::Oco { quantity: 0.0002, price: 42000f64, stop_price: 20000f64, stop_limit_price: None, time_in_force: None });
+        assert!(rules.normalize(&mut order, 40000f64).is_err());
+    }
+}
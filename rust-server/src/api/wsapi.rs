@@ -0,0 +1,95 @@
+// Connection to the websocket API of an exchange. Every request carries an id which is repeated
+// in its response, so the response is passed to the request waiting for it. The connection is
+// read by a separate thread, which also keeps it alive by answering the pings of the exchange.
+
+use super::*;
+
+type Pending = std::collections::HashMap<String, tokio::sync::oneshot::Sender<serde_json::Value>>;
+
+pub struct WsApi {
+    send: Mutex<SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>>,
+    pending: Arc<std::sync::Mutex<Pending>>,
+    reader: tokio::task::JoinHandle<()>,
+}
+
+impl WsApi {
+    pub fn new(ws_stream: WebSocketStream<MaybeTlsStream<TcpStream>>) -> Self {
+        let (send, mut read) = ws_stream.split();
+        let pending = Arc::new(std::sync::Mutex::new(Pending::new()));
+
+        // Thread to pass the responses to the requests waiting for them.
+        let pending_clone = pending.clone();
+        let reader = tokio::spawn(async move {
+            while let Some(Ok(message)) = read.next().await {
+                let text = match message {
+                    Message::Text(text) => text,
+                    Message::Close(_) => break,
+                    _ => continue,
+                };
+
+                let json = match serde_json::from_str::<serde_json::Value>(&text) {
+                    Ok(json) => json,
+                    Err(_) => continue,
+                };
+
+                let id = match &json["id"] {
+                    serde_json::Value::String(id) => id.to_string(),
+                    serde_json::Value::Number(id) => id.to_string(),
+                    _ => continue,
+                };
+
+                if let Some(sender) = pending_clone.lock().unwrap().remove(&id) {
+                    sender.send(json).unwrap_or_default();
+                }
+            }
+
+            // The requests still waiting won't get a response anymore.
+            pending_clone.lock().unwrap().clear();
+        });
+
+        WsApi {
+            send: Mutex::new(send),
+            pending,
+            reader,
+        }
+    }
+
+    // Send a request and wait for its response. The request must have an id which is unique
+    // among the requests waiting for a response.
+    pub async fn request(&self, request: serde_json::Value) -> Result<serde_json::Value, api::Error> {
+        let id = match request["id"].as_str() {
+            Some(id) => id.to_string(),
+            None => {
+                return Err(api::Error::ParseError("Request to the websocket API without id.".into()));
+            }
+        };
+
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        self.pending.lock().unwrap().insert(id.to_string(), tx);
+
+        if let Err(e) = self.send.lock().await.send(Message::Text(request.to_string())).await {
+            self.pending.lock().unwrap().remove(&id);
+            return Err(api::Error::ExchangeAPIError(format!("Error in websocket {}", e)));
+        }
+
+        match tokio::time::timeout(tokio::time::Duration::from_millis(*config::WS_API_TIMEOUT_MS), rx).await {
+            Ok(Ok(response)) => Ok(response),
+            Ok(Err(_)) => Err(api::Error::ExchangeAPIError("Websocket API closed before responding.".into())),
+            Err(_) => {
+                self.pending.lock().unwrap().remove(&id);
+                Err(api::Error::ExchangeAPIError(format!("No response of the websocket API within {} ms.", *config::WS_API_TIMEOUT_MS)))
+            }
+        }
+    }
+
+    // Check if the exchange closed the connection.
+    pub fn is_closed(&self) -> bool {
+        self.reader.is_finished()
+    }
+}
+
+impl Drop for WsApi {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
@@ -0,0 +1,325 @@
+// Backtest runs the Python-script of an algorithm over historical candlesticks. The candles are
+// fed to a PyExecutor one by one, exactly like the live datastream, and the returned
+// order-signals are filled on the candle against simulated funds.
+
+use super::*;
+
+// A simulated order.
+#[derive(Clone, Debug, Serialize)]
+pub struct Trade {
+    pub timestamp: u64,
+    pub side: String,
+    pub quantity: f64,
+    pub price: f64,
+    pub quote: f64,
+    pub commission: f64,
+    pub tag: Option<String>,
+}
+
+// Value of the simulated funds after a candle.
+#[derive(Clone, Debug, Serialize)]
+pub struct EquityPoint {
+    pub timestamp: u64,
+    pub total: f64,
+    pub quote: f64,
+    pub base: f64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct BacktestResult {
+    pub symbol: String,
+    pub start_funds: f64,
+    pub end_funds: f64,
+    pub return_pct: f64,
+    pub candles: usize,
+    pub trades: std::vec::Vec<Trade>,
+    pub equity: std::vec::Vec<EquityPoint>,
+}
+
+// Simulated funds of the algorithm during the backtest.
+struct Funds {
+    quote: f64,
+    base: f64,
+    commission_rate: f64,
+}
+
+impl Funds {
+    // Fill the order-signal on the candle. MARKET orders fill at the close price, LIMIT orders at
+    // their limit price if the candle reached it. Stop-limit orders fill like LIMIT orders when
+    // the candle also reached the stop price. Orders the algorithm can't afford are skipped,
+    // like TradeAlgorithm::place_signal refuses them. Stop-loss, take-profit and brackets are not
+    // simulated.
+    fn fill(&mut self, candle: &CandleStick, signal: &protocol::Signal) -> Option<Trade> {
+        if let Some(stop) = signal.stop_price {
+            if candle.low > stop || candle.high < stop {
+                return None;
+            }
+        }
+
+        let price = match (signal.order_type, signal.limit_price) {
+            (protocol::OrderType::Limit | protocol::OrderType::StopLossLimit | protocol::OrderType::TakeProfitLimit, Some(limit)) => {
+                let reached = match signal.side {
+                    protocol::Side::Buy => candle.low <= limit,
+                    protocol::Side::Sell => candle.high >= limit,
+                };
+                if !reached {
+                    return None;
+                }
+
+                limit
+            },
+            _ => candle.close,
+        };
+
+        let (quantity, quote) = match (signal.quantity, signal.quote_quantity) {
+            (Some(q), _) => (q, q * price),
+            (None, Some(q)) => (q / price, q),
+            (None, None) => return None,
+        };
+
+        match signal.side {
+            protocol::Side::Buy => {
+                if self.quote - quote < 0f64 {
+                    return None;
+                }
+
+                let commission = quantity * self.commission_rate;
+                self.quote -= quote;
+                self.base += quantity - commission;
+
+                Some(Trade { timestamp: candle.timestamp, side: "BUY".into(), quantity, price, quote, commission, tag: signal.tag.clone() })
+            },
+            protocol::Side::Sell => {
+                if self.base - quantity < 0f64 {
+                    return None;
+                }
+
+                let commission = quote * self.commission_rate;
+                self.base -= quantity;
+                self.quote += quote - commission;
+
+                Some(Trade { timestamp: candle.timestamp, side: "SELL".into(), quantity, price, quote, commission, tag: signal.tag.clone() })
+            },
+        }
+    }
+
+    fn total(&self, price: f64) -> f64 {
+        self.quote + self.base * price
+    }
+}
+
+// Duration of a candle of a valid interval in ms, e.g 15m -> 900000.
+fn interval_ms(interval: &str) -> u128 {
+    let (amount, unit) = interval.split_at(interval.len() - 1);
+    let unit_ms = match unit {
+        "s" => 1000,
+        "m" => 60 * 1000,
+        "h" => 60 * 60 * 1000,
+        _ => 24 * 60 * 60 * 1000,
+    };
+
+    amount.parse::<u128>().unwrap_or(1) * unit_ms
+}
+
+// Refuse a backtest from starttime until endtime (ms) with more than max_candles candles.
+fn check_candles(starttime: u128, endtime: u128, interval: &str, max_candles: usize) -> Result<(), tradealgorithm::Error> {
+    if (endtime - starttime) / interval_ms(interval) > max_candles as u128 {
+        return Err(tradealgorithm::Error::AlgorithmError(format!("Backtest exceeds {} candles", max_candles)));
+    }
+
+    Ok(())
+}
+
+// Run a backtest of the algorithm from starttime until endtime (ms) with candles of interval.
+pub async fn run(algorithm: &TradeAlgorithm, api: Api, starttime: u128, endtime: u128, interval: &str) -> Result<BacktestResult, tradealgorithm::Error> {
+
+    if !tradealgorithm::INTERVALS.contains(&interval) {
+        return Err(tradealgorithm::Error::AlgorithmError("Invalid interval given".into()));
+    }
+
+    if starttime >= endtime {
+        return Err(tradealgorithm::Error::AlgorithmError("Start of backtest must be before end".into()));
+    }
+
+    // Retrieve historical data. Data before the start is prepended like when the algorithm is
+    // started live. The amount of candles is checked before they are downloaded.
+    let prepend_starttime = starttime.saturating_sub(algorithm.prepend_data as u128);
+    check_candles(prepend_starttime, endtime, interval, *config::BACKTEST_MAX_CANDLES)?;
+
+    let mut candles = algorithm.fetch_candles(api, interval, prepend_starttime, endtime).await?;
+
+    // Only closed candles are tested, the last candle can still be in progress.
+    candles.retain(|c| c.closed);
+
+    let split = candles.iter().position(|c| c.timestamp as u128 >= starttime).unwrap_or(candles.len());
+    let candles_to_test = candles.split_off(split);
+    let prepended = candles;
+
+    // Start a separate PyExecutor so a running instance of this algorithm is not disturbed.
+    // The executor has to execute on every candle, the throttle is applied on candle time below.
+    let suffix : String = rand::thread_rng()
+        .sample_iter(&rand::distributions::Alphanumeric)
+        .take(8)
+        .map(char::from)
+        .collect();
+    let channel = format!("{}_backtest_{}", algorithm.id, suffix);
+    let (mut process_handle, mut unix_stream) = algorithm.spawn_executor(&channel, &prepended, 0).await?;
+
+    let result = simulate(algorithm, &mut unix_stream, &candles_to_test).await;
+
+    // Clean up the executor.
+    process_handle.kill().unwrap_or_default();
+    process_handle.wait().unwrap_or_default();
+    std::fs::remove_file(format!("tmp/shmem/{}.bin", channel)).unwrap_or_default();
+    std::fs::remove_file(format!("tmp/sockets/{}.sock", channel)).unwrap_or_default();
+    std::fs::remove_file(format!("tmp/state/{}.json", channel)).unwrap_or_default();
+
+    result
+}
+
+// Feed the candles to the executor and fill the returned order-signals.
+async fn simulate(algorithm: &TradeAlgorithm, unix_stream: &mut UnixStream, candles: &[CandleStick]) -> Result<BacktestResult, tradealgorithm::Error> {
+    let mut funds = Funds {
+        quote: algorithm.start_funds,
+        base: 0f64,
+        commission_rate: *config::BACKTEST_COMMISSION_RATE,
+    };
+
+    let mut trades = std::vec::Vec::<Trade>::new();
+    let mut equity = std::vec::Vec::<EquityPoint>::with_capacity(candles.len());
+    let mut last_execution : Option<u64> = None;
+
+    for candle in candles {
+        protocol::write_message(unix_stream, &protocol::Message::Candle(candle.into())).await?;
+
+        // Wait for the order-signal of this candle.
+        let signal = loop {
+            match protocol::read_message(unix_stream).await? {
+                Some(protocol::Message::Signal(signal)) => break signal,
+                Some(protocol::Message::Error(e)) | Some(protocol::Message::Fatal(e)) => {
+                    return Err(tradealgorithm::Error::ExecutorError(e));
+                },
+                Some(_) => continue,
+                None => {
+                    return Err(tradealgorithm::Error::StreamError("PyExecutor closed the connection".into()));
+                }
+            }
+        };
+
+        // A live algorithm only executes every run_every_sec seconds.
+        let throttled = match last_execution {
+            Some(t) => algorithm.run_every_sec > 5 && candle.timestamp < t + algorithm.run_every_sec as u64 * 1000,
+            None => false,
+        };
+
+        if !throttled {
+            last_execution = Some(candle.timestamp);
+
+            if let Some(trade) = signal.and_then(|s| funds.fill(candle, &s)) {
+                trades.push(trade);
+            }
+        }
+
+        equity.push(EquityPoint {
+            timestamp: candle.timestamp,
+            total: funds.total(candle.close),
+            quote: funds.quote,
+            base: funds.base,
+        });
+    }
+
+    let end_funds = match candles.last() {
+        Some(c) => funds.total(c.close),
+        None => funds.quote,
+    };
+
+    Ok(BacktestResult {
+        symbol: algorithm.symbol.to_string(),
+        start_funds: algorithm.start_funds,
+        end_funds,
+        return_pct: if algorithm.start_funds > 0f64 { (end_funds / algorithm.start_funds - 1f64) * 100f64 } else { 0f64 },
+        candles: candles.len(),
+        trades,
+        equity,
+    })
+}
+
+
+// Testing the simulated fills of the backtest.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candle(low: f64, high: f64, close: f64) -> CandleStick {
+        CandleStick { timestamp: 1700000000000, open: close, close, high, low, volume: 1f64, closed: true }
+    }
+
+    fn funds() -> Funds {
+        Funds { quote: 1000f64, base: 0f64, commission_rate: 0f64 }
+    }
+
+    #[test]
+    fn test_fill() {
+        let buy = protocol::Signal::from_f64(0.5).unwrap();
+        let sell = protocol::Signal::from_f64(-0.5).unwrap();
+
+        // A MARKET order fills at the close price.
+        let mut funds = funds();
+        let trade = funds.fill(&candle(90f64, 110f64, 100f64), &buy).unwrap();
+        assert_eq!((trade.side.as_str(), trade.quantity, trade.price, trade.quote), ("BUY", 0.5, 100f64, 50f64));
+        assert_eq!((funds.quote, funds.base), (950f64, 0.5));
+
+        let trade = funds.fill(&candle(90f64, 110f64, 100f64), &protocol::Signal { quantity: None, quote_quantity: Some(100f64), ..buy.clone() }).unwrap();
+        assert_eq!(trade.quantity, 1f64);
+
+        let trade = funds.fill(&candle(90f64, 130f64, 120f64), &sell).unwrap();
+        assert_eq!((trade.side.as_str(), trade.quote), ("SELL", 60f64));
+        assert_eq!((funds.quote, funds.base), (910f64, 1f64));
+
+        // A LIMIT order fills at its limit price when the candle reached it.
+        let limit = protocol::Signal { order_type: protocol::OrderType::Limit, limit_price: Some(95f64), ..buy.clone() };
+        assert_eq!(funds.fill(&candle(96f64, 110f64, 100f64), &limit).map(|t| t.price), None);
+        assert_eq!(funds.fill(&candle(94f64, 110f64, 100f64), &limit).map(|t| t.price), Some(95f64));
+        let limit = protocol::Signal { order_type: protocol::OrderType::Limit, limit_price: Some(105f64), ..sell.clone() };
+        assert_eq!(funds.fill(&candle(90f64, 104f64, 100f64), &limit).map(|t| t.price), None);
+        assert_eq!(funds.fill(&candle(90f64, 106f64, 100f64), &limit).map(|t| t.price), Some(105f64));
+
+        // A stop-limit order also needs the candle to reach its stop price.
+        let stop_limit = protocol::Signal { order_type: protocol::OrderType::StopLossLimit, stop_price: Some(80f64), limit_price: Some(79f64), ..sell.clone() };
+        assert_eq!(funds.fill(&candle(81f64, 110f64, 100f64), &stop_limit).map(|t| t.price), None);
+        assert_eq!(funds.fill(&candle(78f64, 110f64, 100f64), &stop_limit).map(|t| t.price), Some(79f64));
+    }
+
+    #[test]
+    fn test_fill_funds() {
+        // Orders the algorithm can't afford are skipped and don't change the funds.
+        let mut funds = funds();
+        assert!(funds.fill(&candle(90f64, 110f64, 100f64), &protocol::Signal::from_f64(11f64).unwrap()).is_none());
+        assert!(funds.fill(&candle(90f64, 110f64, 100f64), &protocol::Signal::from_f64(-0.1).unwrap()).is_none());
+        assert_eq!((funds.quote, funds.base), (1000f64, 0f64));
+
+        // The commission is paid in the asset that is received.
+        let mut funds = Funds { commission_rate: 0.001, ..funds };
+        let trade = funds.fill(&candle(90f64, 110f64, 100f64), &protocol::Signal::from_f64(1f64).unwrap()).unwrap();
+        assert_eq!(trade.commission, 0.001);
+        assert_eq!((funds.quote, funds.base), (900f64, 0.999));
+
+        let trade = funds.fill(&candle(90f64, 110f64, 100f64), &protocol::Signal::from_f64(-0.5).unwrap()).unwrap();
+        assert_eq!(trade.commission, 0.05);
+        assert_eq!(funds.quote, 949.95);
+        assert_eq!(funds.total(100f64), 949.95 + 0.499 * 100f64);
+    }
+
+    #[test]
+    fn test_candles() {
+        assert_eq!(interval_ms("1s"), 1000);
+        assert_eq!(interval_ms("15m"), 900000);
+        assert_eq!(interval_ms("4h"), 14400000);
+        assert_eq!(interval_ms("1d"), 86400000);
+
+        // One day of minute candles.
+        assert!(check_candles(0, 86400000, "1m", 1440).is_ok());
+        assert!(check_candles(0, 86400000, "1m", 1439).is_err());
+        assert!(check_candles(0, 86400000, "1h", 24).is_ok());
+    }
+}
@@ -18,6 +18,52 @@ pub static REST_API_URL: LazyOnceCell<String> = LazyOnceCell::new(|| "https://te
 pub static WEBSOCKET_API_URL: LazyOnceCell<String> = LazyOnceCell::new(|| "wss://testnet.binance.vision/ws-api/v3".to_string());
 pub static WEBSOCKET_STREAM_URL: LazyOnceCell<String> = LazyOnceCell::new(|| "wss://stream.binance.com:443/ws".to_string());
 
+// Paper exchange config. Market data is read from the public API so no keys are required.
+pub static PAPER_MARKET_DATA_URL: LazyOnceCell<String> = LazyOnceCell::new(|| "https://api.binance.com/api/v3".to_string());
+pub static PAPER_START_FUNDS_USDT: LazyOnceCell<f64> = LazyOnceCell::new(|| 10000.0);
+pub static PAPER_COMMISSION_RATE: LazyOnceCell<f64> = LazyOnceCell::new(|| 0.001);
+pub static PAPER_PRICE_MAX_AGE_MS: LazyOnceCell<u64> = LazyOnceCell::new(|| 5000);
+
+// Backtest config.
+pub static BACKTEST_MAX_CANDLES: LazyOnceCell<usize> = LazyOnceCell::new(|| 100000);
+pub static BACKTEST_COMMISSION_RATE: LazyOnceCell<f64> = LazyOnceCell::new(|| 0.001);
+
+// Maximum wall-clock time in ms of a single execution of the Python function.
+pub static EXECUTOR_TIMEOUT_MS: LazyOnceCell<u64> = LazyOnceCell::new(|| 5000);
+
+// Amount of consecutive timeouts after which the algorithm is stopped.
+pub static EXECUTOR_MAX_CONSECUTIVE_TIMEOUTS: LazyOnceCell<u32> = LazyOnceCell::new(|| 3);
+
+// Maximum address space in MB of a PyExecutor.
+pub static EXECUTOR_MEMORY_LIMIT_MB: LazyOnceCell<u64> = LazyOnceCell::new(|| 2048);
+
+// Maximum CPU time in seconds of a PyExecutor. The algorithm is restarted when it is exceeded.
+pub static EXECUTOR_CPU_LIMIT_SEC: LazyOnceCell<u64> = LazyOnceCell::new(|| 60 * 60 * 24);
+
+// The PyExecutor of an algorithm is restarted when it exits or stops sending heartbeats for
+// EXECUTOR_HEARTBEAT_TIMEOUT_SEC. The delay between restarts doubles from
+// EXECUTOR_RESTART_BACKOFF_MS up to EXECUTOR_RESTART_MAX_BACKOFF_MS, after
+// EXECUTOR_MAX_RESTARTS restarts the algorithm is marked as errored. A PyExecutor running
+// longer than EXECUTOR_RESTART_RESET_SEC gets its restarts back.
+pub static EXECUTOR_HEARTBEAT_TIMEOUT_SEC: LazyOnceCell<u64> = LazyOnceCell::new(|| 30);
+pub static EXECUTOR_RESTART_BACKOFF_MS: LazyOnceCell<u64> = LazyOnceCell::new(|| 1000);
+pub static EXECUTOR_RESTART_MAX_BACKOFF_MS: LazyOnceCell<u64> = LazyOnceCell::new(|| 60000);
+pub static EXECUTOR_MAX_RESTARTS: LazyOnceCell<u32> = LazyOnceCell::new(|| 5);
+pub static EXECUTOR_RESTART_RESET_SEC: LazyOnceCell<u64> = LazyOnceCell::new(|| 600);
+
+// Time in ms to wait for the response of the websocket API of the exchange to a request.
+pub static WS_API_TIMEOUT_MS: LazyOnceCell<u64> = LazyOnceCell::new(|| 10000);
+
+// Time in seconds before a position of which the sell failed is sold again, see ordermanager.rs.
+pub static EXIT_RETRY_SEC: LazyOnceCell<u64> = LazyOnceCell::new(|| 30);
+
+// Time in seconds the trading rules of a symbol (exchangeInfo) are cached.
+pub static SYMBOL_RULES_TTL_SEC: LazyOnceCell<u64> = LazyOnceCell::new(|| 3600);
+
+// Default and maximum amount of candles passed to the Python function of an algorithm.
+pub static DEFAULT_MAX_HISTORY: LazyOnceCell<i32> = LazyOnceCell::new(|| 10000);
+pub static MAX_HISTORY_LIMIT: LazyOnceCell<i32> = LazyOnceCell::new(|| 100000);
+
 // Hash of PyExecutor binary.
 pub static PY_EXECUTOR_HASH: LazyOnceCell<String> = LazyOnceCell::new(|| "b50c1a577153f8c244025befbc41e0bb894000ef6a1f862284b47f49c4feebcf".to_string());
 
@@ -55,12 +55,16 @@ pub async fn process(mut stream: &mut TcpStream, psql: Psql) -> Result<(), http:
         }
     }
 
-    // Create API instance for this request.
-    let mut api = create_api_instance();
-    if let Some(session_token) = http.headers.get("session_token") {
-        api.auth(session_token, psql.clone()).await.unwrap_or_default();
-    }
-    let api = Arc::new(api);
+    // Create API instance for the exchange selected by the user doing this request.
+    let api : Api = match http.headers.get("session_token") {
+        Some(session_token) => {
+            let exchange = api::user_exchange(session_token, psql.clone()).await.unwrap_or(api::ExchangeKind::Binance);
+            let mut api = create_api_instance(exchange);
+            api.auth(session_token, psql.clone()).await.unwrap_or_default();
+            Arc::from(api)
+        },
+        None => Arc::from(create_api_instance(api::ExchangeKind::Binance)),
+    };
 
     // Check if there is a route matching the HTTP-method and path.
     let mut response = match RouteHandler::goto(&mut http, psql, api).await {
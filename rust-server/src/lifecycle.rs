@@ -0,0 +1,113 @@
+// Lifecycle of an algorithm. The state is stored with the algorithm and every transition is
+// stored as an event with the time and the reason, see TradeAlgorithm::transition.
+//
+// - Created -> Starting when the algorithm is started for the first time.
+// - Starting -> Running when the PyExecutor is ready.
+// - Running -> Starting when the PyExecutor stopped and is restarted by the supervisor.
+// - Running <-> Paused.
+// - Starting, Running or Paused -> Stopping when the user stops the algorithm.
+// - Stopping -> Stopped when the PyExecutor is killed.
+// - Starting, Running, Paused or Stopping -> Errored when the PyExecutor gave up.
+// - Stopped or Errored -> Starting when the algorithm is started again.
+
+use super::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum State {
+    Created,
+    Starting,
+    Running,
+    Paused,
+    Stopping,
+    Stopped,
+    Errored,
+}
+
+impl State {
+    // Check if the algorithm can go from this state to the given state.
+    pub fn can_transition(self, to: State) -> bool {
+        use State::*;
+
+        matches!((self, to),
+            (Created | Stopped | Errored, Starting)
+            // Restarts of the supervisor, and algorithms resumed after a restart of the server.
+            | (Starting | Running | Paused, Starting)
+            | (Starting, Running)
+            | (Running, Paused)
+            | (Paused, Running)
+            | (Starting | Running | Paused, Stopping)
+            | (Stopping, Stopped)
+            | (Starting | Running | Paused | Stopping, Errored)
+        )
+    }
+
+    // Check if the algorithm has a PyExecutor, or is getting or losing one.
+    pub fn is_active(self) -> bool {
+        matches!(self, State::Starting | State::Running | State::Paused | State::Stopping)
+    }
+}
+
+impl std::fmt::Display for State {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let state = match self {
+            State::Created => "created",
+            State::Starting => "starting",
+            State::Running => "running",
+            State::Paused => "paused",
+            State::Stopping => "stopping",
+            State::Stopped => "stopped",
+            State::Errored => "errored",
+        };
+        write!(f, "{}", state)
+    }
+}
+
+impl std::str::FromStr for State {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "created" => Ok(State::Created),
+            "starting" => Ok(State::Starting),
+            "running" => Ok(State::Running),
+            "paused" => Ok(State::Paused),
+            "stopping" => Ok(State::Stopping),
+            "stopped" => Ok(State::Stopped),
+            "errored" => Ok(State::Errored),
+            _ => Err("Parse error for State."),
+        }
+    }
+}
+
+
+// Testing the lifecycle of an algorithm.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transitions() {
+        use State::*;
+
+        // Going through the whole lifecycle.
+        let states = [Created, Starting, Running, Paused, Running, Stopping, Stopped, Starting, Errored, Starting];
+        for pair in states.windows(2) {
+            assert!(pair[0].can_transition(pair[1]), "{} -> {}", pair[0], pair[1]);
+        }
+
+        // Skipping states or leaving a stopped algorithm isn't allowed.
+        assert!(!Created.can_transition(Running));
+        assert!(!Stopped.can_transition(Running));
+        assert!(!Running.can_transition(Stopped));
+        assert!(!Paused.can_transition(Paused));
+        assert!(!Stopped.can_transition(Errored));
+        assert!(!Errored.can_transition(Stopping));
+
+        for state in states {
+            assert_eq!(state.to_string().parse::<State>(), Ok(state));
+        }
+        assert!(!Stopped.is_active());
+        assert!(Stopping.is_active());
+    }
+}
@@ -13,7 +13,6 @@ use once_cell::sync::Lazy as LazyOnceCell;
 use lazy_static::lazy_static;
 use async_trait::async_trait;
 use rust_decimal::Decimal;
-use tokio::io::Interest;
 use tokio::net::UnixStream;
 use tokio::sync::mpsc;
 use tokio::sync::Mutex;
@@ -31,10 +30,16 @@ use url;
 
 mod api;
 mod http;
+mod backtest;
 mod config;
+mod lifecycle;
+mod orders;
+mod ordermanager;
+mod protocol;
 mod routes;
 mod websocket;
 mod routehandler;
+mod supervisor;
 mod tradealgorithm;
 
 use routehandler::RouteHandler;
@@ -100,33 +105,55 @@ lazy_static! {
         route!("GET", "/ping-exchange", Routes::ping_exchange),
         route!("POST", "/algorithms/{id}/start", Routes::start_algorithm),
         route!("POST", "/algorithms/{id}/stop", Routes::stop_algorithm),
+        route!("POST", "/algorithms/{id}/pause", Routes::pause_algorithm),
+        route!("POST", "/algorithms/{id}/resume", Routes::resume_algorithm),
         route!("GET", "/algorithms/{id}", Routes::get_algorithm),
         route!("GET", "/algorithms/{id}/history/{start_at}", Routes::get_algorithm_history),
+        route!("GET", "/algorithms/{id}/logs", Routes::get_algorithm_logs),
+        route!("GET", "/algorithms/{id}/logs/{before}", Routes::get_algorithm_logs),
+        route!("GET", "/algorithms/{id}/events", Routes::get_algorithm_events),
+        route!("GET", "/algorithms/{id}/orders", Routes::get_algorithm_orders),
+        route!("GET", "/algorithms/{id}/positions", Routes::get_algorithm_positions),
+        route!("DELETE", "/algorithms/{id}/orders/{client_order_id}", Routes::cancel_algorithm_order),
         route!("GET", "/algorithms/{id}/code", Routes::get_algorithm_code),
         route!("PUT", "/algorithms/{id}/reset", Routes::reset_algorithm),
+        route!("PUT", "/algorithms/{id}/params", Routes::set_algorithm_params),
         route!("GET", "/algorithms/{id}/chart/{interval}", Routes::get_algorithm_chart),
+        route!("POST", "/algorithms/{id}/backtest", Routes::backtest_algorithm),
         route!("POST", "/algorithms/add", Routes::add_algorithm),
         route!("DELETE", "/algorithms/{id}", Routes::delete_algorithm),
         route!("GET", "/balance", Routes::balance),
         route!("PUT", "/users/init", Routes::init_user),
+        route!("PUT", "/users/exchange", Routes::set_user_exchange),
         route!("GET", "/trade_history", Routes::trade_history),
         route!("GET", "/btc_price", Routes::get_btc_price),
         route!("GET", "/klines/{interval}/{amount}", Routes::get_klines),
+        route!("GET", "/klines/{symbol}/{interval}/{amount}", Routes::get_klines),
         route!("GET", "/btc_price", Routes::get_btc_price),
         route!("POST", "/order", Routes::order),
+        route!("GET", "/orders", Routes::get_open_orders),
+        route!("GET", "/orders/{symbol}", Routes::get_open_orders),
+        route!("DELETE", "/orders/{symbol}/{client_order_id}", Routes::cancel_order),
     ]; 
 }
 
-fn create_api_instance() -> api::binance::Binance {
-    let api = ExchangeAPI::new(
-        config::REST_API_URL.as_str(),
-        config::WEBSOCKET_API_URL.as_str(),
-        config::WEBSOCKET_STREAM_URL.as_str(),
-        "",
-        "",
-    );
-
-    api
+fn create_api_instance(exchange: api::ExchangeKind) -> Box<dyn ExchangeAPI> {
+    match exchange {
+        api::ExchangeKind::Binance => Box::new(<api::binance::Binance as ExchangeAPI>::new(
+            config::REST_API_URL.as_str(),
+            config::WEBSOCKET_API_URL.as_str(),
+            config::WEBSOCKET_STREAM_URL.as_str(),
+            "",
+            "",
+        )),
+        api::ExchangeKind::Paper => Box::new(<api::paper::PaperExchange as ExchangeAPI>::new(
+            config::PAPER_MARKET_DATA_URL.as_str(),
+            config::WEBSOCKET_API_URL.as_str(),
+            config::WEBSOCKET_STREAM_URL.as_str(),
+            "",
+            "",
+        )),
+    }
 }
 
 
@@ -149,7 +176,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     tokio::spawn(listener_connection);
         
     // Connect with API.
-    let api = Arc::new(create_api_instance());
+    let api : Api = Arc::from(create_api_instance(api::ExchangeKind::Binance));
 
     // Start server.
     let server = TcpListener::bind("127.0.0.1:8080").await.expect("Failed to bind to 127.0.0.1:8080");
@@ -158,13 +185,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let ws_server = TcpListener::bind("127.0.0.1:8081").await.expect("Failed to bind to 127.0.0.1:8080");
     println!("Websocket server on 127.0.0.1:8081...");
 
-    // Insert BTC price into all algorithm history for accurate charts.
+    // Resume the algorithms which were running when the server stopped.
+    supervisor::resume(client.clone()).await;
+
+    // Insert the price of the traded symbol into all algorithm history for accurate charts.
     let api_clone = api.clone();
     let client_clone = client.clone();
     tokio::spawn(async move {
         loop {
             tokio::time::sleep(tokio::time::Duration::from_millis(60000)).await;
-            tradealgorithm::TradeAlgorithm::insert_btc_price(api_clone.clone(), client_clone.clone()).await.unwrap_or_default();
+            tradealgorithm::TradeAlgorithm::insert_prices(api_clone.clone(), client_clone.clone()).await.unwrap_or_default();
         }
     });
 
@@ -0,0 +1,695 @@
+// Server-side exits of positions. Binance spot has no trailing stop, so a buy signal can have a
+// bracket (protocol::Bracket) which is watched here instead of at the exchange. Every bracket is
+// stored as a position in the table positions:
+// - pending until the buy is done, the position is the filled amount minus the commission.
+// - open while the price is watched. The highest price since the buy is kept for the trailing
+//   stop.
+// - selling when an exit was hit, until its MARKET sell is done. When the sell wasn't placed or
+//   wasn't filled completely, the rest of the position is open again and the next exit is
+//   tried after EXIT_RETRY_SEC, the error is kept as reason.
+// - closed when the position is sold, or when the buy wasn't filled. A position which the
+//   algorithm sold itself with its sell signals is closed when its exit is hit, an exit never
+//   sells more than the base the algorithm holds.
+//
+// The stop-loss and take-profit of a signal are placed at the exchange, but only once the order
+// of the signal is done: an order resting on the book or filled partly doesn't hold the
+// requested quantity yet. They are stored in the table exits, pending until the order is done
+// and then placed for the filled quantity, as an OCO when there are both.
+//
+// The prices come from the shared price stream of the symbol, which is fed by the candles and
+// the prices every running algorithm receives. The positions of an algorithm are watched while
+// it runs, also when it is paused.
+
+use super::*;
+
+lazy_static! {
+    // Last price per symbol. Algorithms trading the same symbol share the stream.
+    static ref PRICES: std::sync::Mutex<std::collections::HashMap<String, tokio::sync::watch::Sender<f64>>> = std::sync::Mutex::new(std::collections::HashMap::new());
+}
+
+// Publish the last price of a symbol.
+pub fn publish(symbol: &api::Symbol, price: f64) {
+    let mut prices = PRICES.lock().unwrap();
+    match prices.get(&symbol.to_string()) {
+        Some(sender) => {
+            sender.send_replace(price);
+        },
+        None => {
+            prices.insert(symbol.to_string(), tokio::sync::watch::channel(price).0);
+        }
+    }
+}
+
+// Receive the prices of a symbol as they are published.
+pub fn subscribe(symbol: &api::Symbol) -> tokio::sync::watch::Receiver<f64> {
+    PRICES.lock().unwrap()
+        .entry(symbol.to_string())
+        .or_insert_with(|| tokio::sync::watch::channel(0f64).0)
+        .subscribe()
+}
+
+// Why a position was sold.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Exit {
+    TrailingStop(f64),
+    Stop(f64),
+    Target(f64),
+}
+
+impl std::fmt::Display for Exit {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Exit::TrailingStop(level) => write!(f, "Trailing stop at {} hit.", level),
+            Exit::Stop(price) => write!(f, "Stop at {} hit.", price),
+            Exit::Target(price) => write!(f, "Target at {} hit.", price),
+        }
+    }
+}
+
+// An open position with the highest price since the buy.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Position {
+    pub quantity: f64,
+    pub peak: f64,
+    pub bracket: protocol::Bracket,
+}
+
+impl Position {
+    // Update the highest price with the price and check if an exit is hit. The target is
+    // checked first, a price can't be both above the target and below a stop.
+    pub fn check(&mut self, price: f64) -> Option<Exit> {
+        if price > self.peak {
+            self.peak = price;
+        }
+
+        if let Some(target) = self.bracket.target {
+            if price >= target {
+                return Some(Exit::Target(target));
+            }
+        }
+
+        if let Some(stop) = self.bracket.stop {
+            if price <= stop {
+                return Some(Exit::Stop(stop));
+            }
+        }
+
+        if let Some(trailing_percent) = self.bracket.trailing_percent {
+            let level = self.peak * (1f64 - trailing_percent / 100f64);
+            if price <= level {
+                return Some(Exit::TrailingStop(level));
+            }
+        }
+
+        None
+    }
+}
+
+// Store the bracket of a buy of an algorithm. The position waits for the buy to be done.
+pub async fn open(algorithm_id: &str, entry: &api::order::OrderRequest, bracket: &protocol::Bracket, psql: Psql) -> Result<(), tradealgorithm::Error> {
+    let decimal = |v: Option<f64>| v.map(|v| Decimal::from_f64_retain(v).unwrap());
+
+    let query = psql.lock().await
+       .query("
+            INSERT INTO positions
+                (algorithm_id, entry_order_id, symbol, trailing_percent, stop_price, target_price)
+            VALUES
+                ($1, $2, $3, $4, $5, $6)
+        ", &[&algorithm_id, &entry.client_order_id, &entry.symbol.to_string(),
+             &decimal(bracket.trailing_percent), &decimal(bracket.stop), &decimal(bracket.target)]).await;
+
+    match query {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            Err(tradealgorithm::Error::DatabaseError(e.to_string()))
+        }
+    }
+}
+
+// Store the stop-loss and take-profit of an order of an algorithm. They are placed when the
+// order is done.
+pub async fn protect(algorithm_id: &str, entry: &api::order::OrderRequest, stop_loss: Option<f64>, take_profit: Option<f64>, psql: Psql) -> Result<(), tradealgorithm::Error> {
+    let decimal = |v: Option<f64>| v.map(|v| Decimal::from_f64_retain(v).unwrap());
+
+    let query = psql.lock().await
+       .query("
+            INSERT INTO exits
+                (algorithm_id, entry_order_id, symbol, side, stop_loss, take_profit)
+            VALUES
+                ($1, $2, $3, $4, $5, $6)
+        ", &[&algorithm_id, &entry.client_order_id, &entry.symbol.to_string(), &entry.side.opposite().to_string(),
+             &decimal(stop_loss), &decimal(take_profit)]).await;
+
+    match query {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            Err(tradealgorithm::Error::DatabaseError(e.to_string()))
+        }
+    }
+}
+
+// The exit orders of a filled quantity. With both a stop-loss and a take-profit they are placed
+// as an OCO, so the other one is canceled when one of them fills.
+fn exit_kind(quantity: f64, stop_loss: Option<f64>, take_profit: Option<f64>) -> Option<(api::order::OrderKind, &'static str)> {
+    match (stop_loss, take_profit) {
+        (Some(stop_loss), Some(take_profit)) => Some((api::order::OrderKind::Oco { quantity, price: take_profit, stop_price: stop_loss, stop_limit_price: None, time_in_force: None }, "X")),
+        (Some(stop_loss), None) => Some((api::order::OrderKind::StopLoss { quantity, stop_price: stop_loss }, "SL")),
+        (None, Some(take_profit)) => Some((api::order::OrderKind::TakeProfit { quantity, stop_price: take_profit }, "TP")),
+        (None, None) => None,
+    }
+}
+
+// The quantity an order which is done added to the funds of the algorithm: the filled quantity
+// minus the commission paid in the base asset.
+fn filled_quantity(row: &tokio_postgres::Row, symbol: &api::Symbol) -> f64 {
+    let executed_quantity = sqldec!(row.get::<_, Decimal>("executed_quantity"));
+    let commission = match row.get::<_, Option<&str>>("commission_asset") {
+        Some(asset) if asset == symbol.base => sqldec!(row.get::<_, Decimal>("commission")),
+        _ => 0f64,
+    };
+
+    executed_quantity - commission
+}
+
+// Check if the order of a row is no longer open at the exchange.
+fn entry_done(row: &tokio_postgres::Row) -> Result<bool, tradealgorithm::Error> {
+    let order_status: orders::Status = row.get::<_, String>("order_status").parse().map_err(|e: &str| tradealgorithm::Error::ParseError(e.into()))?;
+    Ok(!matches!(order_status, orders::Status::PendingNew | orders::Status::New | orders::Status::PartiallyFilled))
+}
+
+// Finish the exits of an order, with the exit order placed for them or why they weren't placed.
+async fn finish_exits(id: i32, status: &str, exit_order_id: Option<&str>, reason: &str, psql: Psql) -> Result<(), tradealgorithm::Error> {
+    let query = psql.lock().await
+       .query("
+            UPDATE
+                exits
+            SET
+                status = $1, exit_order_id = $2, reason = $3, closed_at = CURRENT_TIMESTAMP
+            WHERE
+                id = $4
+        ", &[&status, &exit_order_id, &reason, &id]).await;
+
+    match query {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            Err(tradealgorithm::Error::DatabaseError(e.to_string()))
+        }
+    }
+}
+
+// Place the pending exits of an algorithm whose order is done.
+async fn place_exits(algorithm: &TradeAlgorithm, price: f64, api: Api, ws_api: &api::wsapi::WsApi, psql: Psql) -> Result<(), tradealgorithm::Error> {
+    let query = psql.lock().await
+       .query("
+            SELECT
+                e.id, e.entry_order_id, e.side, e.stop_loss, e.take_profit,
+                o.status AS order_status, o.executed_quantity, o.commission, o.commission_asset
+            FROM
+                exits e
+            JOIN
+                orders o ON o.client_order_id = e.entry_order_id
+            WHERE
+                e.algorithm_id = $1
+            AND
+                e.status = 'pending'
+        ", &[&algorithm.id]).await;
+
+    let rows = match query {
+        Ok(q) => q,
+        Err(e) => {
+            return Err(tradealgorithm::Error::DatabaseError(e.to_string()));
+        }
+    };
+
+    for row in rows {
+        if !entry_done(&row)? {
+            continue;
+        }
+
+        let id: i32 = row.get("id");
+        let entry_order_id: String = row.get("entry_order_id");
+        let side: protocol::Side = row.get::<_, String>("side").parse().map_err(|e: &str| tradealgorithm::Error::ParseError(e.into()))?;
+        let decimal = |column: &str| row.get::<_, Option<Decimal>>(column).map(|d| sqldec!(d));
+
+        let quantity = filled_quantity(&row, &algorithm.symbol);
+        let (kind, suffix) = match exit_kind(quantity, decimal("stop_loss"), decimal("take_profit")) {
+            Some(exit) if quantity > 0f64 => exit,
+            _ => {
+                finish_exits(id, "canceled", None, "The order was not filled.", psql.clone()).await?;
+                continue;
+            }
+        };
+
+        let mut exit = api::order::OrderRequest {
+            symbol: algorithm.symbol.clone(),
+            side,
+            kind,
+            client_order_id: format!("{}-{}", entry_order_id, suffix),
+        };
+
+        let placed = match api.symbol_rules(&algorithm.symbol).await {
+            Ok(rules) => match rules.normalize(&mut exit, price) {
+                Ok(_) => orders::place(&algorithm.id, &exit, api.clone(), ws_api, psql.clone()).await.map_err(|e| e.to_string()),
+                Err(e) => Err(format!("Invalid order: {}", e)),
+            },
+            Err(e) => Err(e.to_string()),
+        };
+        let message = match placed {
+            Ok(status) => {
+                finish_exits(id, "placed", Some(&exit.client_order_id), &status.to_string(), psql.clone()).await?;
+                format!("Placing {} order for {} {}: {}", exit.kind.name(), exit.quantity().unwrap_or(quantity), algorithm.symbol.base, status)
+            },
+            Err(e) => {
+                finish_exits(id, "failed", None, &e, psql.clone()).await?;
+                format!("Could not place {} order for {} {}: {}", exit.kind.name(), quantity, algorithm.symbol.base, e)
+            },
+        };
+        println!("\x1b[32m[order] {} - {}\x1b[0m", algorithm.id, message);
+
+        let line = protocol::LogLine {
+            stream: protocol::LogStream::Log,
+            message,
+            candle_timestamp: None,
+        };
+        algorithm.add_log(&line, psql.clone()).await?;
+    }
+
+    Ok(())
+}
+
+// Mark a position as being sold by the exit order.
+async fn sell(id: i32, exit_order_id: &str, reason: &str, attempts: i32, psql: Psql) -> Result<(), tradealgorithm::Error> {
+    let query = psql.lock().await
+       .query("
+            UPDATE
+                positions
+            SET
+                status = 'selling', exit_order_id = $1, reason = $2, exit_attempts = $3
+            WHERE
+                id = $4
+        ", &[&exit_order_id, &reason, &attempts, &id]).await;
+
+    match query {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            Err(tradealgorithm::Error::DatabaseError(e.to_string()))
+        }
+    }
+}
+
+// Watch the rest of a position again which wasn't sold. The next exit is tried after
+// EXIT_RETRY_SEC, so a sell the exchange keeps refusing isn't sent on every price.
+async fn reopen(id: i32, quantity: f64, psql: Psql) -> Result<(), tradealgorithm::Error> {
+    let retry_sec = *config::EXIT_RETRY_SEC as f64;
+
+    let query = psql.lock().await
+       .query("
+            UPDATE
+                positions
+            SET
+                status = 'open', quantity = $1, retry_at = CURRENT_TIMESTAMP + make_interval(secs => $2)
+            WHERE
+                id = $3
+        ", &[super::sqlf64!(quantity), &retry_sec, &id]).await;
+
+    match query {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            Err(tradealgorithm::Error::DatabaseError(e.to_string()))
+        }
+    }
+}
+
+// Close a position which is no longer watched.
+async fn close(id: i32, exit_order_id: Option<&str>, reason: &str, psql: Psql) -> Result<(), tradealgorithm::Error> {
+    let query = psql.lock().await
+       .query("
+            UPDATE
+                positions
+            SET
+                status = 'closed', exit_order_id = $1, reason = $2, closed_at = CURRENT_TIMESTAMP
+            WHERE
+                id = $3
+        ", &[&exit_order_id, &reason, &id]).await;
+
+    match query {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            Err(tradealgorithm::Error::DatabaseError(e.to_string()))
+        }
+    }
+}
+
+// Stop watching the positions of an algorithm, e.g when it is reset. The positions are not sold
+// and pending exits are not placed.
+pub async fn close_all(algorithm_id: &str, reason: &str, psql: Psql) -> Result<(), tradealgorithm::Error> {
+    let query = psql.lock().await
+       .query("
+            UPDATE
+                exits
+            SET
+                status = 'canceled', reason = $1, closed_at = CURRENT_TIMESTAMP
+            WHERE
+                algorithm_id = $2
+            AND
+                status = 'pending'
+        ", &[&reason, &algorithm_id]).await;
+
+    if let Err(e) = query {
+        return Err(tradealgorithm::Error::DatabaseError(e.to_string()));
+    }
+
+    let query = psql.lock().await
+       .query("
+            UPDATE
+                positions
+            SET
+                status = 'closed', reason = $1, closed_at = CURRENT_TIMESTAMP
+            WHERE
+                algorithm_id = $2
+            AND
+                status IN ('pending', 'open', 'selling')
+        ", &[&reason, &algorithm_id]).await;
+
+    match query {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            Err(tradealgorithm::Error::DatabaseError(e.to_string()))
+        }
+    }
+}
+
+// Get the positions of an algorithm which are watched, newest first.
+pub async fn get_positions(algorithm_id: &str, psql: Psql) -> Result<serde_json::Value, tradealgorithm::Error> {
+    let query = psql.lock().await
+       .query("
+            SELECT
+                entry_order_id, symbol, status, quantity, entry_price, peak_price, trailing_percent, stop_price, target_price, created_at::TEXT
+            FROM
+                positions
+            WHERE
+                algorithm_id = $1
+            AND
+                status IN ('pending', 'open', 'selling')
+            ORDER BY
+                created_at DESC
+        ", &[&algorithm_id]).await;
+
+    let decimal = |row: &tokio_postgres::Row, column: &str| row.get::<_, Option<Decimal>>(column).map(|d| sqldec!(d));
+
+    match query {
+        Ok(q) => {
+            Ok(serde_json::json!(q.iter().map(|row| serde_json::json!({
+                "entry_order_id": row.get::<_, String>("entry_order_id"),
+                "symbol": row.get::<_, String>("symbol"),
+                "status": row.get::<_, String>("status"),
+                "quantity": decimal(row, "quantity"),
+                "entry_price": decimal(row, "entry_price"),
+                "peak_price": decimal(row, "peak_price"),
+                "trailing_percent": decimal(row, "trailing_percent"),
+                "stop_price": decimal(row, "stop_price"),
+                "target_price": decimal(row, "target_price"),
+                "created_at": row.get::<_, String>("created_at"),
+            })).collect::<std::vec::Vec<_>>()))
+        },
+        Err(e) => {
+            Err(tradealgorithm::Error::DatabaseError(e.to_string()))
+        }
+    }
+}
+
+// Watch the positions of an algorithm until the task is aborted, see TradeAlgorithm::start.
+pub async fn run(algorithm: TradeAlgorithm, api: Api, ws_api: Arc<api::wsapi::WsApi>, psql: Psql) {
+    let mut prices = subscribe(&algorithm.symbol);
+
+    while prices.changed().await.is_ok() {
+        let price = *prices.borrow_and_update();
+        if price <= 0f64 {
+            continue;
+        }
+
+        if let Err(e) = check(&algorithm, price, api.clone(), &ws_api, psql.clone()).await {
+            eprintln!("{}", e);
+        }
+    }
+}
+
+// Check the positions of an algorithm against the price. Pending exits are placed and pending
+// positions are opened when their order is done, open positions are sold when an exit is hit
+// and positions being sold are closed when their sell is done.
+async fn check(algorithm: &TradeAlgorithm, price: f64, api: Api, ws_api: &api::wsapi::WsApi, psql: Psql) -> Result<(), tradealgorithm::Error> {
+    place_exits(algorithm, price, api.clone(), ws_api, psql.clone()).await?;
+
+    let query = psql.lock().await
+       .query("
+            SELECT
+                p.id, p.entry_order_id, p.status, p.quantity, p.peak_price, p.trailing_percent, p.stop_price, p.target_price,
+                p.exit_order_id, p.exit_attempts, p.reason, COALESCE(p.retry_at > CURRENT_TIMESTAMP, false) AS waiting,
+                o.status AS order_status, o.executed_quantity, o.executed_quote, o.commission, o.commission_asset,
+                x.status AS exit_status, x.executed_quantity AS exit_executed_quantity
+            FROM
+                positions p
+            JOIN
+                orders o ON o.client_order_id = p.entry_order_id
+            LEFT JOIN
+                orders x ON x.client_order_id = p.exit_order_id
+            WHERE
+                p.algorithm_id = $1
+            AND
+                p.status IN ('pending', 'open', 'selling')
+        ", &[&algorithm.id]).await;
+
+    let rows = match query {
+        Ok(q) => q,
+        Err(e) => {
+            return Err(tradealgorithm::Error::DatabaseError(e.to_string()));
+        }
+    };
+
+    // The base the algorithm holds and which isn't being sold by an exit yet, it is fetched once
+    // an exit is hit.
+    let mut available: Option<f64> = None;
+
+    for row in rows.iter() {
+        let id: i32 = row.get("id");
+        let entry_order_id: String = row.get("entry_order_id");
+        let decimal = |column: &str| row.get::<_, Option<Decimal>>(column).map(|d| sqldec!(d));
+
+        let mut position = match row.get::<_, &str>("status") {
+            "pending" => {
+                if !entry_done(row)? {
+                    continue;
+                }
+
+                // The position is what the buy added to the funds of the algorithm.
+                let quantity = filled_quantity(row, &algorithm.symbol);
+                if quantity <= 0f64 {
+                    close(id, None, "The buy was not filled.", psql.clone()).await?;
+                    continue;
+                }
+
+                let entry_price = sqldec!(row.get::<_, Decimal>("executed_quote")) / sqldec!(row.get::<_, Decimal>("executed_quantity"));
+                let query = psql.lock().await
+                   .query("
+                        UPDATE
+                            positions
+                        SET
+                            status = 'open', quantity = $1, entry_price = $2, peak_price = $2
+                        WHERE
+                            id = $3
+                    ", &[super::sqlf64!(quantity), super::sqlf64!(entry_price), &id]).await;
+
+                if let Err(e) = query {
+                    return Err(tradealgorithm::Error::DatabaseError(e.to_string()));
+                }
+
+                Position {
+                    quantity,
+                    peak: entry_price,
+                    bracket: protocol::Bracket {
+                        trailing_percent: decimal("trailing_percent"),
+                        stop: decimal("stop_price"),
+                        target: decimal("target_price"),
+                    },
+                }
+            },
+            "selling" => {
+                // A sell of which the exchange didn't respond yet may be placed, it waits for
+                // sync to find out.
+                let exit_status = match row.get::<_, Option<String>>("exit_status") {
+                    Some(s) => Some(s.parse::<orders::Status>().map_err(|e: &str| tradealgorithm::Error::ParseError(e.into()))?),
+                    None => None,
+                };
+                let remaining = decimal("quantity").unwrap_or_default() - decimal("exit_executed_quantity").unwrap_or_default();
+
+                match exit_status {
+                    Some(orders::Status::PendingNew | orders::Status::New | orders::Status::PartiallyFilled) => (),
+                    Some(orders::Status::Filled) => {
+                        close(id, row.get("exit_order_id"), row.get::<_, Option<&str>>("reason").unwrap_or_default(), psql.clone()).await?;
+                    },
+                    _ if remaining <= 0f64 => {
+                        close(id, row.get("exit_order_id"), row.get::<_, Option<&str>>("reason").unwrap_or_default(), psql.clone()).await?;
+                    },
+                    // Not placed, refused or not filled completely.
+                    _ => {
+                        reopen(id, remaining, psql.clone()).await?;
+                    },
+                }
+                continue;
+            },
+            _ => Position {
+                quantity: decimal("quantity").unwrap_or_default(),
+                peak: decimal("peak_price").unwrap_or_default(),
+                bracket: protocol::Bracket {
+                    trailing_percent: decimal("trailing_percent"),
+                    stop: decimal("stop_price"),
+                    target: decimal("target_price"),
+                },
+            },
+        };
+
+        let peak = position.peak;
+        let exit = match position.check(price) {
+            Some(exit) if !row.get::<_, bool>("waiting") => exit,
+            _ => {
+                if position.peak > peak {
+                    let query = psql.lock().await
+                       .query("
+                            UPDATE
+                                positions
+                            SET
+                                peak_price = $1
+                            WHERE
+                                id = $2
+                        ", &[super::sqlf64!(position.peak), &id]).await;
+
+                    if let Err(e) = query {
+                        return Err(tradealgorithm::Error::DatabaseError(e.to_string()));
+                    }
+                }
+                continue;
+            }
+        };
+
+        // The algorithm may have sold a part of the position itself, an exit never sells more
+        // than the algorithm holds.
+        let holding = match available {
+            Some(a) => a,
+            None => {
+                let (_, base) = algorithm.get_current_funds(psql.clone()).await?;
+                base - rows.iter()
+                    .filter(|r| r.get::<_, &str>("status") == "selling")
+                    .map(|r| {
+                        let decimal = |column: &str| r.get::<_, Option<Decimal>>(column).map(|d| sqldec!(d)).unwrap_or_default();
+                        decimal("quantity") - decimal("exit_executed_quantity")
+                    })
+                    .sum::<f64>()
+            },
+        };
+        let quantity = position.quantity.min(holding);
+        available = Some(holding - quantity.max(0f64));
+        if quantity <= 0f64 {
+            close(id, None, "The position was sold by the algorithm.", psql.clone()).await?;
+            continue;
+        }
+
+        // The position is marked as being sold before it is sold, so it is never sold twice.
+        // Every attempt gets its own client order ID.
+        let attempts = row.get::<_, i32>("exit_attempts") + 1;
+        let client_order_id = match attempts {
+            1 => format!("{}-EX", entry_order_id),
+            n => format!("{}-EX{}", entry_order_id, n),
+        };
+        let mut order = api::order::OrderRequest {
+            symbol: algorithm.symbol.clone(),
+            side: protocol::Side::Sell,
+            kind: api::order::OrderKind::Market { quantity: Some(quantity), quote_quantity: None },
+            client_order_id,
+        };
+        sell(id, &order.client_order_id, &exit.to_string(), attempts, psql.clone()).await?;
+
+        let placed = match api.symbol_rules(&algorithm.symbol).await {
+            Ok(rules) => match rules.normalize(&mut order, price) {
+                Ok(_) => orders::place(&algorithm.id, &order, api.clone(), ws_api, psql.clone()).await.map_err(|e| e.to_string()),
+                Err(e) => Err(format!("Invalid order: {}", e)),
+            },
+            Err(e) => Err(e.to_string()),
+        };
+        let message = match placed {
+            Ok(status) => format!("{} Selling {} {}: {}", exit, order.quantity().unwrap_or(quantity), algorithm.symbol.base, status),
+            Err(e) => {
+                // The position is watched again once the failed sell is checked, with the
+                // error as reason.
+                let query = psql.lock().await
+                   .query("
+                        UPDATE
+                            positions
+                        SET
+                            reason = $1
+                        WHERE
+                            id = $2
+                    ", &[&e, &id]).await;
+
+                if let Err(e) = query {
+                    return Err(tradealgorithm::Error::DatabaseError(e.to_string()));
+                }
+
+                format!("{} Could not sell {} {}: {}", exit, quantity, algorithm.symbol.base, e)
+            },
+        };
+        println!("\x1b[32m[order] {} - {}\x1b[0m", algorithm.id, message);
+
+        let line = protocol::LogLine {
+            stream: protocol::LogStream::Log,
+            message,
+            candle_timestamp: None,
+        };
+        algorithm.add_log(&line, psql.clone()).await?;
+    }
+
+    Ok(())
+}
+
+
+// Testing the exits of positions.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_kind() {
+        assert_eq!(exit_kind(1f64, None, None), None);
+        assert_eq!(exit_kind(1f64, Some(90f64), None), Some((api::order::OrderKind::StopLoss { quantity: 1f64, stop_price: 90f64 }, "SL")));
+        assert_eq!(exit_kind(1f64, None, Some(110f64)), Some((api::order::OrderKind::TakeProfit { quantity: 1f64, stop_price: 110f64 }, "TP")));
+        assert!(matches!(exit_kind(1f64, Some(90f64), Some(110f64)), Some((api::order::OrderKind::Oco { price, stop_price, .. }, "X")) if price == 110f64 && stop_price == 90f64));
+    }
+
+    #[test]
+    fn test_check() {
+        let bracket = protocol::Bracket { trailing_percent: Some(10f64), stop: Some(90f64), target: Some(150f64) };
+        let mut position = Position { quantity: 1f64, peak: 100f64, bracket };
+
+        // The trailing stop follows the highest price.
+        assert_eq!(position.check(95f64), None);
+        assert_eq!(position.check(120f64), None);
+        assert_eq!(position.peak, 120f64);
+        assert_eq!(position.check(110f64), None);
+        assert_eq!(position.check(108f64), Some(Exit::TrailingStop(108f64)));
+
+        // The hard stop and target don't move.
+        let mut position = Position { quantity: 1f64, peak: 100f64, bracket: protocol::Bracket { trailing_percent: None, ..bracket } };
+        assert_eq!(position.check(91f64), None);
+        assert_eq!(position.check(90f64), Some(Exit::Stop(90f64)));
+        assert_eq!(position.check(151f64), Some(Exit::Target(150f64)));
+    }
+
+    #[test]
+    fn test_prices() {
+        let symbol: api::Symbol = "TESTUSDT".parse().unwrap();
+        let mut prices = subscribe(&symbol);
+
+        publish(&symbol, 42f64);
+        assert!(prices.has_changed().unwrap());
+        assert_eq!(*prices.borrow_and_update(), 42f64);
+    }
+}
@@ -0,0 +1,440 @@
+// Orders placed for algorithms. Every order is stored in the table orders with its status at
+// the exchange:
+// - PENDING_NEW until the exchange responded to the order.
+// - NEW or PARTIALLY_FILLED while the order is open, e.g a limit or stop-loss order.
+// - FILLED, CANCELED, REJECTED or EXPIRED when the order is done.
+// Open orders are updated by asking the exchange for their status, see sync. An OCO is stored
+// as its two orders, see api::order::OrderRequest::legs.
+//
+// Only the amounts which were actually filled are written to the history of the algorithm, a
+// row for every fill we learn about. The price is the average price of the fill and the
+// commission is subtracted from the asset it was paid in.
+
+use super::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Status {
+    PendingNew,
+    New,
+    PartiallyFilled,
+    Filled,
+    Canceled,
+    Rejected,
+    Expired,
+}
+
+impl std::fmt::Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let status = match self {
+            Status::PendingNew => "PENDING_NEW",
+            Status::New => "NEW",
+            Status::PartiallyFilled => "PARTIALLY_FILLED",
+            Status::Filled => "FILLED",
+            Status::Canceled => "CANCELED",
+            Status::Rejected => "REJECTED",
+            Status::Expired => "EXPIRED",
+        };
+        write!(f, "{}", status)
+    }
+}
+
+impl std::str::FromStr for Status {
+    type Err = &'static str;
+
+    // Statuses of the exchange we don't track are mapped to the closest one.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_uppercase().as_str() {
+            "PENDING_NEW" => Ok(Status::PendingNew),
+            "NEW" => Ok(Status::New),
+            "PARTIALLY_FILLED" => Ok(Status::PartiallyFilled),
+            "FILLED" => Ok(Status::Filled),
+            "CANCELED" | "PENDING_CANCEL" => Ok(Status::Canceled),
+            "REJECTED" => Ok(Status::Rejected),
+            "EXPIRED" | "EXPIRED_IN_MATCH" => Ok(Status::Expired),
+            _ => Err("Parse error for Status."),
+        }
+    }
+}
+
+// Amount in an order of the exchange, which are strings.
+fn amount(value: &serde_json::Value) -> f64 {
+    value.as_str().and_then(|v| v.parse::<f64>().ok()).unwrap_or(0f64)
+}
+
+// Commission of the fills of an order, in the asset of the first fill. The fills are those in
+// the response of an order or the trades of an order. Fills paying in another asset are rare
+// and left out, they can't be added up.
+fn commission(fills: &serde_json::Value) -> Option<(f64, String)> {
+    let fills = fills.as_array()?;
+    let asset = fills.first()?["commissionAsset"].as_str()?.to_string();
+
+    let total = fills.iter()
+        .filter(|f| f["commissionAsset"].as_str() == Some(&*asset))
+        .map(|f| amount(&f["commission"]))
+        .sum();
+
+    Some((total, asset))
+}
+
+// Amounts of the base and quote asset a fill adds to the funds of an algorithm. Buying adds
+// base and removes quote, selling the reverse.
+fn fill_amounts(symbol: &api::Symbol, side: &str, quantity: f64, quote: f64, commission: f64, commission_asset: Option<&str>) -> (f64, f64) {
+    let (mut base_amount, mut quote_amount) = match side {
+        "BUY" => (quantity, -quote),
+        _ => (-quantity, quote),
+    };
+
+    if commission_asset == Some(&*symbol.base) {
+        base_amount -= commission;
+    } else if commission_asset == Some(&*symbol.quote) {
+        quote_amount -= commission;
+    }
+
+    (base_amount, quote_amount)
+}
+
+// Store the orders of a request of an algorithm before it is sent to the exchange.
+pub async fn insert(algorithm_id: &str, order: &api::order::OrderRequest, psql: Psql) -> Result<(), tradealgorithm::Error> {
+    let client = psql.lock().await;
+
+    for leg in order.legs() {
+        let query = client
+           .query("
+                INSERT INTO orders
+                    (algorithm_id, client_order_id, symbol, side, type, status, quantity, price)
+                VALUES
+                    ($1, $2, $3, $4, $5, $6, $7, $8)
+            ", &[&algorithm_id, &leg.client_order_id, &order.symbol.to_string(), &order.side.to_string(), &leg.order_type, &Status::PendingNew.to_string(),
+                 &leg.quantity.map(|q| Decimal::from_f64_retain(q).unwrap()), &leg.price.map(|p| Decimal::from_f64_retain(p).unwrap())]).await;
+
+        if let Err(e) = query {
+            return Err(tradealgorithm::Error::DatabaseError(e.to_string()));
+        }
+    }
+
+    Ok(())
+}
+
+// Update an order with the order as returned by the exchange. The part of the order which was
+// filled since the last update is written to the history of the algorithm.
+pub async fn apply(client_order_id: &str, order: &serde_json::Value, psql: Psql) -> Result<Status, tradealgorithm::Error> {
+    let status: Status = order["status"].as_str().unwrap_or_default().parse().map_err(|e: &str| tradealgorithm::Error::ParseError(e.into()))?;
+    let executed_quantity = amount(&order["executedQty"]);
+    let executed_quote = amount(&order["cummulativeQuoteQty"]);
+    let exchange_order_id = order["orderId"].as_i64();
+
+    let mut client = psql.lock().await;
+
+    // The order is locked so a fill isn't written twice when the response of the order and
+    // the status of the exchange arrive at the same time.
+    let transaction = client.transaction().await?;
+
+    let query = transaction
+       .query("
+            SELECT
+                o.algorithm_id, o.symbol, a.base_asset, a.quote_asset, o.side, o.executed_quantity, o.executed_quote, o.commission, o.commission_asset
+            FROM
+                orders o
+            LEFT JOIN
+                algorithms a ON a.id = o.algorithm_id AND a.symbol = o.symbol
+            WHERE
+                o.client_order_id = $1
+            FOR UPDATE OF o
+        ", &[&client_order_id]).await;
+
+    let row = match query {
+        Ok(mut q) => {
+            if q.is_empty() {
+                return Err(tradealgorithm::Error::AlgorithmError(format!("Order {} not found", client_order_id)));
+            }
+            q.remove(0)
+        },
+        Err(e) => {
+            return Err(tradealgorithm::Error::DatabaseError(e.to_string()));
+        }
+    };
+
+    let algorithm_id: String = row.get("algorithm_id");
+    let symbol = api::Symbol::from_stored(row.get("symbol"), row.get("base_asset"), row.get("quote_asset")).map_err(|e| tradealgorithm::Error::ParseError(e.into()))?;
+    let side: String = row.get("side");
+    let previous_quantity = sqldec!(row.get::<_, Decimal>("executed_quantity"));
+    let previous_quote = sqldec!(row.get::<_, Decimal>("executed_quote"));
+    let previous_commission = sqldec!(row.get::<_, Decimal>("commission"));
+
+    // Without fills the commission is unchanged.
+    let (total_commission, commission_asset) = match commission(&order["fills"]) {
+        Some((c, asset)) => (c, Some(asset)),
+        None => (previous_commission, row.get::<_, Option<String>>("commission_asset")),
+    };
+
+    let query = transaction
+       .query("
+            UPDATE
+                orders
+            SET
+                status = $1, exchange_order_id = COALESCE($2, exchange_order_id), executed_quantity = $3, executed_quote = $4, commission = $5, commission_asset = $6, updated_at = CURRENT_TIMESTAMP
+            WHERE
+                client_order_id = $7
+        ", &[&status.to_string(), &exchange_order_id, super::sqlf64!(executed_quantity), super::sqlf64!(executed_quote), super::sqlf64!(total_commission), &commission_asset, &client_order_id]).await;
+
+    if let Err(e) = query {
+        return Err(tradealgorithm::Error::DatabaseError(e.to_string()));
+    }
+
+    // Register the new fill in the history.
+    let quantity = executed_quantity - previous_quantity;
+    let quote = executed_quote - previous_quote;
+    let commission = total_commission - previous_commission;
+    if quantity > 0f64 {
+        let (btc, usdt) = fill_amounts(&symbol, &side, quantity, quote, commission, commission_asset.as_deref());
+
+        let query = transaction
+           .query("
+                INSERT INTO history
+                    (algorithm_id, order_id, action, btc, usdt, btc_price, commission, commission_asset)
+                VALUES
+                    ($1, $2, $3, $4, $5, $6, $7, $8)
+            ", &[&algorithm_id, &client_order_id, &side, super::sqlf64!(btc), super::sqlf64!(usdt), super::sqlf64!(quote / quantity), super::sqlf64!(commission), &commission_asset]).await;
+
+        if let Err(e) = query {
+            return Err(tradealgorithm::Error::DatabaseError(e.to_string()));
+        }
+    }
+
+    transaction.commit().await?;
+
+    Ok(status)
+}
+
+// Mark an order as rejected with the reason given by the exchange.
+pub async fn reject(client_order_id: &str, reason: &str, psql: Psql) -> Result<(), tradealgorithm::Error> {
+    let query = psql.lock().await
+       .query("
+            UPDATE
+                orders
+            SET
+                status = $1, reason = $2, updated_at = CURRENT_TIMESTAMP
+            WHERE
+                client_order_id = $3
+        ", &[&Status::Rejected.to_string(), &reason, &client_order_id]).await;
+
+    match query {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            Err(tradealgorithm::Error::DatabaseError(e.to_string()))
+        }
+    }
+}
+
+// Store the response of the exchange to an order request. A refused order is stored as
+// rejected and returned as an error. When the exchange doesn't respond the order stays pending
+// until sync finds out what happened with it. Returns the status of the first order.
+pub async fn record(algorithm_id: &str, order: &api::order::OrderRequest, response: Result<std::vec::Vec<serde_json::Value>, api::Error>, psql: Psql) -> Result<Status, tradealgorithm::Error> {
+    match response {
+        Ok(reports) => {
+            let mut status = Status::PendingNew;
+            for (i, report) in reports.iter().enumerate() {
+                let client_order_id = report["clientOrderId"].as_str().unwrap_or_default();
+                let s = apply(client_order_id, report, psql.clone()).await?;
+                if i == 0 {
+                    status = s;
+                }
+            }
+            Ok(status)
+        },
+        Err(api::Error::OrderRejected(reason)) => {
+            for leg in order.legs() {
+                reject(&leg.client_order_id, &reason, psql.clone()).await?;
+            }
+            Err(tradealgorithm::Error::AlgorithmError(format!("{} - Order {} rejected: {}", algorithm_id, order.client_order_id, reason)))
+        },
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Place an order of an algorithm over the websocket API and store it with the response of the
+// exchange, see record.
+pub async fn place(algorithm_id: &str, order: &api::order::OrderRequest, api: Api, ws_api: &api::wsapi::WsApi, psql: Psql) -> Result<Status, tradealgorithm::Error> {
+    insert(algorithm_id, order, psql.clone()).await?;
+
+    let response = api.ws_order(order, ws_api).await;
+    record(algorithm_id, order, response, psql).await
+}
+
+// Add the trades of an order as its fills. The status of an order has no fills, the commission
+// is in its trades. The order is updated with both at once, so the commission of a fill isn't
+// lost.
+async fn with_fills(symbol: &api::Symbol, mut order: serde_json::Value, api: Api) -> Result<serde_json::Value, api::Error> {
+    if let (true, Some(order_id)) = (amount(&order["executedQty"]) > 0f64, order["orderId"].as_i64()) {
+        order["fills"] = serde_json::json!(api.order_trades(symbol, order_id).await?);
+    }
+
+    Ok(order)
+}
+
+// Get the open orders of an algorithm, newest first.
+pub async fn open(algorithm_id: &str, psql: Psql) -> Result<std::vec::Vec<serde_json::Value>, tradealgorithm::Error> {
+    let query = psql.lock().await
+       .query("
+            SELECT
+                client_order_id, symbol, side, type, status, quantity, price, executed_quantity, created_at::TEXT
+            FROM
+                orders
+            WHERE
+                algorithm_id = $1
+            AND
+                status IN ('PENDING_NEW', 'NEW', 'PARTIALLY_FILLED')
+            ORDER BY
+                created_at DESC
+        ", &[&algorithm_id]).await;
+
+    match query {
+        Ok(q) => {
+            Ok(q.iter().map(|row| serde_json::json!({
+                "client_order_id": row.get::<_, String>("client_order_id"),
+                "symbol": row.get::<_, String>("symbol"),
+                "side": row.get::<_, String>("side"),
+                "type": row.get::<_, String>("type"),
+                "status": row.get::<_, String>("status"),
+                "quantity": row.get::<_, Option<Decimal>>("quantity").map(|q| sqldec!(q)),
+                "price": row.get::<_, Option<Decimal>>("price").map(|p| sqldec!(p)),
+                "executed_quantity": sqldec!(row.get::<_, Decimal>("executed_quantity")),
+                "created_at": row.get::<_, String>("created_at"),
+            })).collect())
+        },
+        Err(e) => {
+            Err(tradealgorithm::Error::DatabaseError(e.to_string()))
+        }
+    }
+}
+
+// Cancel an open order of an algorithm at the exchange and store its final status. Returns
+// None when the algorithm has no open order with this client order ID.
+pub async fn cancel(algorithm_id: &str, client_order_id: &str, api: Api, psql: Psql) -> Result<Option<Status>, tradealgorithm::Error> {
+    let query = psql.lock().await
+       .query("
+            SELECT
+                o.symbol, a.base_asset, a.quote_asset
+            FROM
+                orders o
+            LEFT JOIN
+                algorithms a ON a.id = o.algorithm_id AND a.symbol = o.symbol
+            WHERE
+                o.algorithm_id = $1
+            AND
+                o.client_order_id = $2
+            AND
+                o.status IN ('NEW', 'PARTIALLY_FILLED')
+        ", &[&algorithm_id, &client_order_id]).await;
+
+    let symbol: api::Symbol = match query {
+        Ok(q) => {
+            if q.is_empty() {
+                return Ok(None);
+            }
+            api::Symbol::from_stored(q[0].get("symbol"), q[0].get("base_asset"), q[0].get("quote_asset")).map_err(|e| tradealgorithm::Error::ParseError(e.into()))?
+        },
+        Err(e) => {
+            return Err(tradealgorithm::Error::DatabaseError(e.to_string()));
+        }
+    };
+
+    let order = api.cancel_order(&symbol, client_order_id).await?;
+    let order = with_fills(&symbol, order, api).await?;
+
+    apply(client_order_id, &order, psql).await.map(Some)
+}
+
+// Update the open orders of an algorithm with their status at the exchange. A pending order the
+// exchange doesn't know of was never placed.
+pub async fn sync(algorithm_id: &str, api: Api, psql: Psql) -> Result<(), tradealgorithm::Error> {
+    let query = psql.lock().await
+       .query("
+            SELECT
+                o.client_order_id, o.symbol, a.base_asset, a.quote_asset, o.status
+            FROM
+                orders o
+            LEFT JOIN
+                algorithms a ON a.id = o.algorithm_id AND a.symbol = o.symbol
+            WHERE
+                o.algorithm_id = $1
+            AND
+                o.status IN ('PENDING_NEW', 'NEW', 'PARTIALLY_FILLED')
+        ", &[&algorithm_id]).await;
+
+    let rows = match query {
+        Ok(q) => q,
+        Err(e) => {
+            return Err(tradealgorithm::Error::DatabaseError(e.to_string()));
+        }
+    };
+
+    for row in rows {
+        let client_order_id: String = row.get("client_order_id");
+        let symbol = api::Symbol::from_stored(row.get("symbol"), row.get("base_asset"), row.get("quote_asset")).map_err(|e| tradealgorithm::Error::ParseError(e.into()))?;
+
+        match api.order_status(&symbol, &client_order_id).await {
+            Ok(order) => {
+                match with_fills(&symbol, order, api.clone()).await {
+                    Ok(order) => {
+                        apply(&client_order_id, &order, psql.clone()).await?;
+                    },
+                    Err(e) => {
+                        eprintln!("{}", e);
+                    }
+                }
+            },
+            // Other errors e.g a timeout don't mean the order wasn't placed, it is checked
+            // again with the next sync.
+            Err(e @ api::Error::OrderNotFound(_)) if row.get::<_, &str>("status") == Status::PendingNew.to_string() => {
+                reject(&client_order_id, &e.to_string(), psql.clone()).await?;
+            },
+            Err(e) => {
+                eprintln!("{}", e);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+
+// Testing the statuses of orders.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status() {
+        let statuses = [Status::PendingNew, Status::New, Status::PartiallyFilled, Status::Filled, Status::Canceled, Status::Rejected, Status::Expired];
+        for status in statuses {
+            assert_eq!(status.to_string().parse::<Status>(), Ok(status));
+            assert_eq!(serde_json::to_string(&status).unwrap(), format!("\"{}\"", status));
+        }
+
+        assert_eq!("EXPIRED_IN_MATCH".parse::<Status>(), Ok(Status::Expired));
+
+        assert_eq!(amount(&serde_json::json!("0.00150000")), 0.0015);
+        assert_eq!(amount(&serde_json::Value::Null), 0f64);
+    }
+
+    #[test]
+    fn test_fills() {
+        let fills = serde_json::json!([
+            {"price": "40000.00", "qty": "0.001", "commission": "0.000001", "commissionAsset": "BTC"},
+            {"price": "40010.00", "qty": "0.002", "commission": "0.000002", "commissionAsset": "BTC"},
+            {"price": "40010.00", "qty": "0.001", "commission": "0.0001", "commissionAsset": "BNB"},
+        ]);
+        let (total, asset) = commission(&fills).unwrap();
+        assert!((total - 0.000003).abs() < 1e-12);
+        assert_eq!(asset, "BTC");
+        assert_eq!(commission(&serde_json::json!([])), None);
+
+        // The commission is paid in the asset that is received, or in another asset.
+        let symbol = api::Symbol::default();
+        assert_eq!(fill_amounts(&symbol, "BUY", 0.5, 20000.0, 0.001, Some("BTC")), (0.499, -20000.0));
+        assert_eq!(fill_amounts(&symbol, "SELL", 0.5, 20000.0, 20.0, Some("USDT")), (-0.5, 19980.0));
+        assert_eq!(fill_amounts(&symbol, "SELL", 0.5, 20000.0, 0.01, Some("BNB")), (-0.5, 20000.0));
+    }
+}
@@ -0,0 +1,586 @@
+// Protocol used on the UnixSocket between the Rust-server and PyExecutor.
+//
+// Every message is sent as a frame:
+// [version: u8][length: u32 big-endian][payload: JSON of Message]
+// The length prefix makes sure messages which are split over multiple reads, or multiple
+// messages which arrive in a single read, are handled correctly.
+//
+// This file is included in both binaries, each binary only uses a part of it.
+#![allow(dead_code)]
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use serde::{Serialize, Deserialize};
+
+// Version of the protocol. Frames with another version are rejected.
+pub const VERSION: u8 = 1;
+
+// Size of the header of a frame: version (1 byte) + length (4 bytes).
+pub const HEADER_LEN: usize = 5;
+
+// Maximum length of the payload of a frame.
+pub const MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+
+// Candlestick as sent over the socket.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Candle {
+    pub timestamp: u64,
+    pub open: f64,
+    pub close: f64,
+    pub high: f64,
+    pub low: f64,
+    pub volume: f64,
+    // False while the candle is still in progress.
+    pub closed: bool,
+}
+
+// Where a line of output of the Python code comes from.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogStream {
+    Stdout,
+    Stderr,
+    // The log() helper.
+    Log,
+}
+
+impl std::fmt::Display for LogStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LogStream::Stdout => write!(f, "stdout"),
+            LogStream::Stderr => write!(f, "stderr"),
+            LogStream::Log => write!(f, "log"),
+        }
+    }
+}
+
+// A line of output of the Python code. candle_timestamp is the open time of the candle func
+// was executed on, None for output while loading the code.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LogLine {
+    pub stream: LogStream,
+    pub message: String,
+    pub candle_timestamp: Option<u64>,
+}
+
+// Kind of error reported by the PyExecutor.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    // func raised an exception.
+    Exception,
+    // The code could not be loaded, or func returned an invalid signal or state.
+    Code,
+    // func did not return in time.
+    Timeout,
+    // A message of the server could not be read.
+    Protocol,
+    // Something went wrong in the PyExecutor itself.
+    Executor,
+}
+
+impl std::fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ErrorKind::Exception => write!(f, "exception"),
+            ErrorKind::Code => write!(f, "code"),
+            ErrorKind::Timeout => write!(f, "timeout"),
+            ErrorKind::Protocol => write!(f, "protocol"),
+            ErrorKind::Executor => write!(f, "executor"),
+        }
+    }
+}
+
+// An error of the PyExecutor. traceback is the Python traceback of an exception,
+// candle_timestamp the open time of the candle func was executed on.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ExecutorError {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub traceback: Option<String>,
+    pub candle_timestamp: Option<u64>,
+}
+
+impl std::fmt::Display for ExecutorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}: {}", self.kind, self.message)?;
+        if let Some(t) = self.candle_timestamp {
+            write!(f, " (candle {})", t)?;
+        }
+        if let Some(traceback) = &self.traceback {
+            write!(f, "\n{}", traceback)?;
+        }
+        Ok(())
+    }
+}
+
+// Side of an order.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+impl Side {
+    // Side of the order that closes a position opened with this side.
+    pub fn opposite(&self) -> Self {
+        match self {
+            Side::Buy => Side::Sell,
+            Side::Sell => Side::Buy,
+        }
+    }
+}
+
+impl std::str::FromStr for Side {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_uppercase().as_str() {
+            "BUY" => Ok(Side::Buy),
+            "SELL" => Ok(Side::Sell),
+            _ => Err("Parse error for Side."),
+        }
+    }
+}
+
+impl std::fmt::Display for Side {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Side::Buy => write!(f, "BUY"),
+            Side::Sell => write!(f, "SELL"),
+        }
+    }
+}
+
+// Order types an algorithm can use. The stop-limit types place a LIMIT order once the
+// stop_price is reached.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum OrderType {
+    Market,
+    Limit,
+    StopLossLimit,
+    TakeProfitLimit,
+}
+
+impl std::str::FromStr for OrderType {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_uppercase().as_str() {
+            "MARKET" => Ok(OrderType::Market),
+            "LIMIT" => Ok(OrderType::Limit),
+            "STOP_LOSS_LIMIT" => Ok(OrderType::StopLossLimit),
+            "TAKE_PROFIT_LIMIT" => Ok(OrderType::TakeProfitLimit),
+            _ => Err("Parse error for OrderType."),
+        }
+    }
+}
+
+impl std::fmt::Display for OrderType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            OrderType::Market => write!(f, "MARKET"),
+            OrderType::Limit => write!(f, "LIMIT"),
+            OrderType::StopLossLimit => write!(f, "STOP_LOSS_LIMIT"),
+            OrderType::TakeProfitLimit => write!(f, "TAKE_PROFIT_LIMIT"),
+        }
+    }
+}
+
+// How long a LIMIT order stays active.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum TimeInForce {
+    Gtc,
+    Ioc,
+    Fok,
+}
+
+impl std::str::FromStr for TimeInForce {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_uppercase().as_str() {
+            "GTC" => Ok(TimeInForce::Gtc),
+            "IOC" => Ok(TimeInForce::Ioc),
+            "FOK" => Ok(TimeInForce::Fok),
+            _ => Err("Parse error for TimeInForce."),
+        }
+    }
+}
+
+impl std::fmt::Display for TimeInForce {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TimeInForce::Gtc => write!(f, "GTC"),
+            TimeInForce::Ioc => write!(f, "IOC"),
+            TimeInForce::Fok => write!(f, "FOK"),
+        }
+    }
+}
+
+// Exits of a position which are watched by the server instead of the exchange, see
+// ordermanager.rs. trailing_percent is the distance of the trailing stop below the highest price
+// since the buy, stop and target are prices. Whichever is hit first sells the position.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Bracket {
+    pub trailing_percent: Option<f64>,
+    pub stop: Option<f64>,
+    pub target: Option<f64>,
+}
+
+// Order-signal returned by the Python function. Either quantity (base asset) or
+// quote_quantity (quote asset) is set. Stop-loss and take-profit are prices at which the
+// position opened by this order is closed again. stop_price is the trigger of the stop-limit
+// order types. A buy can have a bracket instead of a stop-loss and take-profit.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Signal {
+    pub side: Side,
+    pub quantity: Option<f64>,
+    pub quote_quantity: Option<f64>,
+    pub order_type: OrderType,
+    pub limit_price: Option<f64>,
+    pub stop_price: Option<f64>,
+    pub stop_loss: Option<f64>,
+    pub take_profit: Option<f64>,
+    pub time_in_force: Option<TimeInForce>,
+    pub tag: Option<String>,
+    pub bracket: Option<Bracket>,
+}
+
+impl Signal {
+    // Signal from a bare float: r > 0 buys r, r < 0 sells r and r == 0 does nothing.
+    pub fn from_f64(r: f64) -> Option<Self> {
+        if r == 0f64 || !r.is_finite() {
+            return None;
+        }
+
+        Some(Signal {
+            side: if r > 0f64 { Side::Buy } else { Side::Sell },
+            quantity: Some(r.abs()),
+            quote_quantity: None,
+            order_type: OrderType::Market,
+            limit_price: None,
+            stop_price: None,
+            stop_loss: None,
+            take_profit: None,
+            time_in_force: None,
+            tag: None,
+            bracket: None,
+        })
+    }
+
+    // Check if the signal describes an order that can be placed.
+    pub fn validate(&self) -> Result<(), String> {
+        fn positive(name: &str, value: Option<f64>) -> Result<(), String> {
+            match value {
+                Some(v) if !(v.is_finite() && v > 0f64) => Err(format!("{} must be a positive number.", name)),
+                _ => Ok(()),
+            }
+        }
+
+        positive("quantity", self.quantity)?;
+        positive("quote_quantity", self.quote_quantity)?;
+        positive("limit_price", self.limit_price)?;
+        positive("stop_price", self.stop_price)?;
+        positive("stop_loss", self.stop_loss)?;
+        positive("take_profit", self.take_profit)?;
+
+        if self.quantity.is_some() == self.quote_quantity.is_some() {
+            return Err("Exactly one of quantity and quote_quantity must be given.".into());
+        }
+
+        match self.order_type {
+            OrderType::Market => {
+                if self.limit_price.is_some() || self.time_in_force.is_some() {
+                    return Err("limit_price and time_in_force are only allowed for LIMIT orders.".into());
+                }
+            },
+            OrderType::Limit => {
+                if self.limit_price.is_none() {
+                    return Err("LIMIT orders require a limit_price.".into());
+                }
+            },
+            OrderType::StopLossLimit | OrderType::TakeProfitLimit => {
+                if self.limit_price.is_none() || self.stop_price.is_none() {
+                    return Err(format!("{} orders require a limit_price and a stop_price.", self.order_type));
+                }
+            },
+        }
+
+        if self.stop_price.is_some() && !matches!(self.order_type, OrderType::StopLossLimit | OrderType::TakeProfitLimit) {
+            return Err("stop_price is only allowed for STOP_LOSS_LIMIT and TAKE_PROFIT_LIMIT orders.".into());
+        }
+
+        // The stop-loss and take-profit close the position a buy opens, spot has no positions
+        // a sell opens.
+        if (self.stop_loss.is_some() || self.take_profit.is_some()) && self.side != Side::Buy {
+            return Err("stop_loss and take_profit are only allowed for BUY orders.".into());
+        }
+
+        // The stop-loss has to be below the position, the take-profit above.
+        let (low, high) = (self.stop_loss, self.take_profit);
+        if let (Some(low), Some(high)) = (low, high) {
+            if low >= high {
+                return Err("stop_loss and take_profit are on the wrong side of each other.".into());
+            }
+        }
+        if let Some(price) = self.limit_price {
+            if low.is_some_and(|l| l >= price) || high.is_some_and(|h| h <= price) {
+                return Err("stop_loss and take_profit must be on either side of the limit_price.".into());
+            }
+        }
+
+        if let Some(bracket) = &self.bracket {
+            positive("trailing_percent", bracket.trailing_percent)?;
+            positive("stop", bracket.stop)?;
+            positive("target", bracket.target)?;
+
+            if self.side != Side::Buy {
+                return Err("A bracket is only allowed for BUY orders.".into());
+            }
+            if self.stop_loss.is_some() || self.take_profit.is_some() {
+                return Err("A bracket can't be combined with stop_loss and take_profit.".into());
+            }
+            if *bracket == Bracket::default() {
+                return Err("A bracket requires a trailing_percent, stop or target.".into());
+            }
+            if bracket.trailing_percent.is_some_and(|t| t >= 100f64) {
+                return Err("trailing_percent must be below 100.".into());
+            }
+            if let (Some(stop), Some(target)) = (bracket.stop, bracket.target) {
+                if stop >= target {
+                    return Err("The stop of a bracket must be below its target.".into());
+                }
+            }
+        }
+
+        // The tag is used in the client order ID.
+        if let Some(tag) = &self.tag {
+            if tag.is_empty() || tag.len() > 16 || !tag.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+                return Err("tag must be 1-16 characters of [a-zA-Z0-9_-].".into());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data")]
+pub enum Message {
+    // Server -> PyExecutor: a new candlestick.
+    Candle(Candle),
+    // PyExecutor -> server: order-signal returned by the Python function. None means the
+    // algorithm does nothing on this candle.
+    Signal(Option<Signal>),
+    // PyExecutor -> server: something went wrong, the PyExecutor keeps running.
+    Error(ExecutorError),
+    // PyExecutor -> server: something went wrong and the PyExecutor stops.
+    Fatal(ExecutorError),
+    // PyExecutor -> server: a line of output of the Python code.
+    Log(LogLine),
+    // PyExecutor -> server: the executor is alive.
+    Heartbeat,
+    // PyExecutor -> server: the executor accepted the connection and is ready for candles.
+    Ready,
+    // Server -> PyExecutor: user-defined parameters of the algorithm, a JSON object.
+    Params(serde_json::Value),
+}
+
+// Encode a message into a frame.
+pub fn encode(message: &Message) -> Result<std::vec::Vec<u8>, Error> {
+    let payload = serde_json::to_vec(message)?;
+    if payload.len() > MAX_FRAME_LEN {
+        return Err(Error::FrameError(format!("Frame of {} bytes exceeds maximum length.", payload.len())));
+    }
+
+    let mut frame = std::vec::Vec::with_capacity(HEADER_LEN + payload.len());
+    frame.push(VERSION);
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&payload);
+
+    Ok(frame)
+}
+
+// Write a message as a single frame.
+pub async fn write_message<W: AsyncWrite + Unpin>(writer: &mut W, message: &Message) -> Result<(), Error> {
+    let frame = encode(message)?;
+    writer.write_all(&frame).await?;
+    writer.flush().await?;
+
+    Ok(())
+}
+
+// Read the next message. Returns None if the other side closed the connection between frames.
+pub async fn read_message<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Option<Message>, Error> {
+    let mut header = [0u8; HEADER_LEN];
+
+    // Read first byte separately so a clean close can be told apart from a broken frame.
+    if reader.read(&mut header[..1]).await? == 0 {
+        return Ok(None);
+    }
+    reader.read_exact(&mut header[1..]).await?;
+
+    if header[0] != VERSION {
+        return Err(Error::VersionError(format!("Expected protocol version {}, received {}.", VERSION, header[0])));
+    }
+
+    let len = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+    if len > MAX_FRAME_LEN {
+        return Err(Error::FrameError(format!("Frame of {} bytes exceeds maximum length.", len)));
+    }
+
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload).await?;
+
+    Ok(Some(serde_json::from_slice(&payload)?))
+}
+
+
+// Error type for the protocol.
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    VersionError(String),
+    FrameError(String),
+    ParseError(String),
+    StreamError(String),
+}
+
+impl std::error::Error for Error {}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::ParseError(e.to_string())
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::StreamError(e.to_string())
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::VersionError(error_msg) => write!(f, "\x1b[31m[Error] Protocol - VersionError: {}\x1b[0m", error_msg),
+            Error::FrameError(error_msg) => write!(f, "\x1b[31m[Error] Protocol - FrameError: {}\x1b[0m", error_msg),
+            Error::ParseError(error_msg) => write!(f, "\x1b[31m[Error] Protocol - ParseError: {}\x1b[0m", error_msg),
+            Error::StreamError(error_msg) => write!(f, "\x1b[31m[Error] Protocol - StreamError: {}\x1b[0m", error_msg),
+        }
+    }
+}
+
+
+// Testing the protocol.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candle(timestamp: u64) -> Message {
+        Message::Candle(Candle {
+            timestamp,
+            open: 3.0,
+            close: 6.0,
+            high: 6.0,
+            low: 2.5,
+            volume: 0.18006000,
+            closed: true,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_coalesced_messages() {
+        // Multiple frames arriving in a single read are all returned.
+        let mut bytes = std::vec::Vec::new();
+        bytes.extend(encode(&Message::Ready).unwrap());
+        bytes.extend(encode(&candle(1)).unwrap());
+        bytes.extend(encode(&Message::Signal(Signal::from_f64(-0.5))).unwrap());
+
+        let error = Message::Error(ExecutorError {
+            kind: ErrorKind::Exception,
+            message: "ZeroDivisionError: division by zero".into(),
+            traceback: Some("  line 3, in func".into()),
+            candle_timestamp: Some(1),
+        });
+        bytes.extend(encode(&error).unwrap());
+
+        let mut reader = &bytes[..];
+        assert_eq!(read_message(&mut reader).await, Ok(Some(Message::Ready)));
+        assert_eq!(read_message(&mut reader).await, Ok(Some(candle(1))));
+        assert_eq!(read_message(&mut reader).await, Ok(Some(Message::Signal(Signal::from_f64(-0.5)))));
+        assert_eq!(read_message(&mut reader).await, Ok(Some(error)));
+        assert_eq!(read_message(&mut reader).await, Ok(None));
+    }
+
+    #[tokio::test]
+    async fn test_split_messages() {
+        // A frame written in small pieces is reassembled.
+        let (mut client, mut server) = tokio::io::duplex(3);
+        let frame = encode(&candle(2)).unwrap();
+
+        let writer = tokio::spawn(async move {
+            for chunk in frame.chunks(2) {
+                client.write_all(chunk).await.unwrap();
+            }
+        });
+
+        assert_eq!(read_message(&mut server).await, Ok(Some(candle(2))));
+        writer.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_invalid_frames() {
+        let mut frame = encode(&Message::Heartbeat).unwrap();
+        frame[0] = VERSION + 1;
+        assert!(matches!(read_message(&mut &frame[..]).await, Err(Error::VersionError(_))));
+
+        let mut frame = vec![VERSION];
+        frame.extend_from_slice(&(MAX_FRAME_LEN as u32 + 1).to_be_bytes());
+        assert!(matches!(read_message(&mut &frame[..]).await, Err(Error::FrameError(_))));
+
+        let frame = encode(&candle(3)).unwrap();
+        assert!(matches!(read_message(&mut &frame[..frame.len() - 1]).await, Err(Error::StreamError(_))));
+    }
+
+    #[test]
+    fn test_validate_signal() {
+        let signal = Signal::from_f64(0.5).unwrap();
+        assert_eq!(signal.validate(), Ok(()));
+        assert_eq!(Signal::from_f64(0f64), None);
+
+        let limit = Signal {
+            order_type: OrderType::Limit,
+            limit_price: Some(100f64),
+            stop_loss: Some(90f64),
+            take_profit: Some(120f64),
+            ..signal.clone()
+        };
+        assert_eq!(limit.validate(), Ok(()));
+
+        // Invalid signals.
+        assert!(Signal { limit_price: None, ..limit.clone() }.validate().is_err());
+        assert!(Signal { quote_quantity: Some(10f64), ..signal.clone() }.validate().is_err());
+        assert!(Signal { quantity: Some(-1f64), ..signal.clone() }.validate().is_err());
+        assert!(Signal { side: Side::Sell, ..limit.clone() }.validate().is_err());
+        assert!(Signal { time_in_force: Some(TimeInForce::Gtc), ..signal.clone() }.validate().is_err());
+        assert!(Signal { tag: Some("a tag".into()), ..signal.clone() }.validate().is_err());
+
+        // Stop-limit orders need both prices, other orders no stop_price.
+        let stop_limit = Signal { order_type: OrderType::StopLossLimit, stop_price: Some(101f64), ..limit.clone() };
+        assert_eq!(stop_limit.validate(), Ok(()));
+        assert!(Signal { stop_price: None, ..stop_limit.clone() }.validate().is_err());
+        assert!(Signal { order_type: OrderType::Limit, ..stop_limit.clone() }.validate().is_err());
+        assert_eq!("take_profit_limit".parse::<OrderType>(), Ok(OrderType::TakeProfitLimit));
+
+        // Brackets are managed by the server and only protect buys.
+        let bracket = Bracket { trailing_percent: Some(2f64), stop: Some(90f64), target: Some(120f64) };
+        assert_eq!(Signal { bracket: Some(bracket), ..signal.clone() }.validate(), Ok(()));
+        assert!(Signal { bracket: Some(bracket), side: Side::Sell, ..signal.clone() }.validate().is_err());
+        assert!(Signal { bracket: Some(bracket), ..limit.clone() }.validate().is_err());
+        assert!(Signal { bracket: Some(Bracket::default()), ..signal.clone() }.validate().is_err());
+        assert!(Signal { bracket: Some(Bracket { trailing_percent: Some(100f64), ..bracket }), ..signal.clone() }.validate().is_err());
+    }
+}
@@ -1,12 +1,38 @@
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use serde::{Serialize, Deserialize};
 use tokio::net::UnixListener;
 use memmap2:: MmapOptions;
-use pyo3::types::PyList;
+use pyo3::types::PyDict;
 use tokio::sync::Mutex;
 use pyo3::prelude::*;
 use std::sync::Arc;
 
+#[path = "protocol.rs"]
+mod protocol;
+#[path = "python_executor/validator.rs"]
+mod validator;
+#[path = "python_executor/sandbox.rs"]
+mod sandbox;
+#[path = "python_executor/window.rs"]
+mod window;
+#[path = "python_executor/columns.rs"]
+mod columns;
+#[path = "python_executor/ta.rs"]
+mod ta;
+#[path = "python_executor/logs.rs"]
+mod logs;
+
+// Interval at which a heartbeat is sent to the server.
+const HEARTBEAT_INTERVAL_SEC: u64 = 5;
+
+// Default maximum wall-clock time of a single call of func.
+const DEFAULT_TIMEOUT_MS: u64 = 5000;
+
+// Default amount of consecutive timeouts after which the PyExecutor gives up.
+const DEFAULT_MAX_CONSECUTIVE_TIMEOUTS: u32 = 3;
+
+// Default maximum amount of candles passed to func.
+const DEFAULT_MAX_HISTORY: usize = 10000;
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[pyclass]
 pub struct CandleStick {
@@ -22,16 +48,66 @@ pub struct CandleStick {
     pub low: f64,
     #[pyo3(get, set, name = "v")]
     pub volume: f64,
+    // False while the candle is still in progress.
+    #[pyo3(get, set, name = "x")]
+    #[serde(default)]
+    pub closed: bool,
+}
+
+impl From<protocol::Candle> for CandleStick {
+    fn from(c: protocol::Candle) -> Self {
+        CandleStick {
+            timestamp: c.timestamp,
+            open: c.open,
+            close: c.close,
+            high: c.high,
+            low: c.low,
+            volume: c.volume,
+            closed: c.closed,
+        }
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
-    let args: std::vec::Vec<String> = std::env::args().collect();
+    // Options start with "--", the other arguments are positional.
+    let (options, args): (std::vec::Vec<String>, std::vec::Vec<String>) = std::env::args().partition(|a| a.starts_with("--"));
     let algorithm_id = args[1].to_string();
     let run_every_sec = args[2].parse::<u64>()?;
 
+    // Name of the shared memory and socket files. Defaults to the algorithm ID.
+    let channel = args.get(3).cloned().unwrap_or(algorithm_id.to_string());
+
+    // Timeout of a single call of func and the amount of consecutive timeouts allowed.
+    let timeout_ms = match args.get(4) {
+        Some(t) => t.parse::<u64>()?,
+        None => DEFAULT_TIMEOUT_MS,
+    };
+    let max_consecutive_timeouts = match args.get(5) {
+        Some(m) => m.parse::<u32>()?,
+        None => DEFAULT_MAX_CONSECUTIVE_TIMEOUTS,
+    };
+
+    // Limits of the sandbox. The sandbox can only be disabled explicitly, e.g for debugging.
+    let mut sandbox_enabled = true;
+    let mut limits = sandbox::Limits::default();
+    let mut max_history = DEFAULT_MAX_HISTORY;
+    let mut run_on_close = false;
+    let mut data_format = window::DataFormat::List;
+    for option in options.iter() {
+        match option.split_once('=') {
+            Some(("--memory-limit-mb", v)) => limits.memory_mb = v.parse::<u64>()?,
+            Some(("--cpu-limit-sec", v)) => limits.cpu_sec = v.parse::<u64>()?,
+            Some(("--max-history", v)) => max_history = v.parse::<usize>()?,
+            Some(("--data-format", v)) => data_format = v.parse().map_err(Error::ParseError)?,
+            None if option == "--no-sandbox" => sandbox_enabled = false,
+            None if option == "--run-on-close" => run_on_close = true,
+            _ => return Err(Error::ParseError(format!("Unknown option: {}", option))),
+        }
+    }
+
     // Retrieve data from shared memory.
-    let shmem_path = &*format!("tmp/shmem/{}.bin", algorithm_id);
+    let shmem_path = &*format!("tmp/shmem/{}.bin", channel);
     let memfile = std::fs::File::open(shmem_path).expect("Failed to open memfile.");
     let memfile_metadata = memfile.metadata().expect("Failed to get metadata memfile.");
     let mapped_data = unsafe {
@@ -46,7 +122,7 @@ async fn main() -> Result<(), Error> {
     let data : std::vec::Vec<CandleStick> = serde_json::from_slice(serialized_data).expect("Failed to deserialize.");
 
     // Create UnixSocket to receive data from algorithm and to send result back.
-    let unix_socket_path = &*format!("tmp/sockets/{}.sock", algorithm_id);
+    let unix_socket_path = &*format!("tmp/sockets/{}.sock", channel);
     std::fs::remove_file(unix_socket_path).unwrap_or_default();
     let listener = match UnixListener::bind(unix_socket_path) {
         Ok(lis) => lis,
@@ -55,6 +131,29 @@ async fn main() -> Result<(), Error> {
         }
      };
 
+    // Restrict the process before any Python code is loaded. Top-level code of the module is
+    // executed by Strategy::load.
+    if sandbox_enabled {
+        sandbox::apply(&limits).map_err(Error::SandboxError)?;
+    } else {
+        eprintln!("\x1b[33m[Warning] PyExecutor - Running without sandbox.\x1b[0m");
+    }
+
+    // Compile the Python code once. The module and the state of the strategy are kept for the
+    // lifetime of the process.
+    std::fs::create_dir_all("tmp/state")?;
+    let strategy = match Strategy::load(&algorithm_id, &format!("tmp/state/{}.json", channel)) {
+        Ok(s) => s.with_timeout(std::time::Duration::from_millis(timeout_ms)),
+        Err(e) => {
+            // Let the server know why the code could not be loaded before exiting.
+            if let Ok((mut stream, _)) = listener.accept().await {
+                protocol::write_message(&mut stream, &protocol::Message::Fatal(e.report(None))).await.unwrap_or_default();
+            }
+            return Err(e);
+        }
+    };
+    let strategy = Arc::new(strategy);
+
     // Make a separate thread to countdown run_every_sec.
     // If the counter is not null the algorithm will not execute.
     let counter = Arc::new(Mutex::new(run_every_sec));
@@ -72,57 +171,477 @@ async fn main() -> Result<(), Error> {
     });
 
     // Accept connection.
-    while let Ok((mut stream, _)) = listener.accept().await {
-        let mut data_clone = data.clone();
-        let algorithm_id_clone = algorithm_id.clone();
+    while let Ok((stream, _)) = listener.accept().await {
+        let mut window = match window::CandleWindow::new(data.clone(), max_history, data_format) {
+            Ok(w) => w,
+            Err(e) => {
+                eprintln!("{}", Error::PyExecutorError(e.to_string()));
+                continue;
+            }
+        };
+        let strategy_clone = strategy.clone();
         let counter_clone = counter.clone();
+
+        let (mut reader, writer) = stream.into_split();
+        let writer = Arc::new(Mutex::new(writer));
+
+        // Let the server know we are ready to receive candles.
+        if let Err(e) = protocol::write_message(&mut *writer.lock().await, &protocol::Message::Ready).await {
+            eprintln!("{}", e);
+            continue;
+        }
+
+        // Output of the code while it was loaded.
+        for line in strategy_clone.drain_logs(None) {
+            protocol::write_message(&mut *writer.lock().await, &protocol::Message::Log(line)).await.unwrap_or_default();
+        }
+
+        // Send heartbeats until the connection is closed.
+        let writer_clone = writer.clone();
+        let heartbeat_handle = tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(tokio::time::Duration::from_secs(HEARTBEAT_INTERVAL_SEC)).await;
+
+                if protocol::write_message(&mut *writer_clone.lock().await, &protocol::Message::Heartbeat).await.is_err() {
+                    break;
+                }
+            }
+        });
        
         // Don't close connection. Keep reading.
         tokio::spawn(async move {
+            let mut consecutive_timeouts = 0;
+
             loop {
-                let mut buffer = [0; 1024];
-                match stream.read(&mut buffer).await {
-                    Ok(0) => {
+                let candlestick : CandleStick = match protocol::read_message(&mut reader).await {
+                    Ok(Some(protocol::Message::Candle(c))) => c.into(),
+                    Ok(Some(protocol::Message::Params(params))) => {
+                        if let Err(e) = strategy_clone.set_params(&params) {
+                            let message = protocol::Message::Error(e.report(None));
+                            protocol::write_message(&mut *writer.lock().await, &message).await.unwrap_or_default();
+                        }
+                        continue;
+                    },
+                    Ok(Some(_)) => continue,
+                    Ok(None) => break,
+                    Err(e) => {
+                        // The stream can't be trusted anymore after a broken frame.
+                        let message = protocol::Message::Error(Error::from(e).report(None));
+                        protocol::write_message(&mut *writer.lock().await, &message).await.unwrap_or_default();
                         break;
+                    }
+                };
+
+                // Add received kline to the window of data. An update of the candle in progress
+                // replaces it.
+                let closed = candlestick.closed;
+                let timestamp = candlestick.timestamp;
+                if let Err(e) = Python::with_gil(|py| window.update(py, candlestick)) {
+                    let e = Error::PyExecutorError(format!("Could not add candle: {}", e));
+                    protocol::write_message(&mut *writer.lock().await, &protocol::Message::Fatal(e.report(Some(timestamp)))).await.unwrap_or_default();
+                    std::process::exit(1);
+                }
+
+                if run_on_close && !closed {
+                    continue;
+                }
+                
+                // Execute the Python code with the given data.
+                let execution = execute(&strategy_clone, &window, (counter_clone.clone(), run_every_sec)).await;
+
+                // Send the output of the execution, also when it failed.
+                for line in strategy_clone.drain_logs(Some(timestamp)) {
+                    protocol::write_message(&mut *writer.lock().await, &protocol::Message::Log(line)).await.unwrap_or_default();
+                }
+
+                let result = match execution {
+                    Ok(r) => {
+                        consecutive_timeouts = 0;
+                        r
                     },
-                    Ok(n) => {
-                        
-                        // kline received from UnixSocket.
-                        let received_data = String::from_utf8_lossy(&buffer[..n]);
-                        let candlestick : CandleStick = match serde_json::from_str(&received_data) {
-                            Ok(c) => c,
-                            Err(_) => continue,
-                        };
-
-                        // Add received kline to all data.
-                        data_clone.push(candlestick.clone());
-                        
-                        // Execute the Python code with the given data.
-                        let result = match execute(data_clone.clone(), algorithm_id_clone.to_string(), (counter_clone.clone(), run_every_sec)).await {
-                            Ok(r) => r,
-                            Err(e) => {
-                                match e {
-                                    Error::CounterError => continue,
-                                    _ => panic!("Could not execute PythonCode: {}", e),
+                    Err(e) => {
+                        match e {
+                            Error::CounterError => continue,
+                            Error::TimeoutError(_) => {
+                                consecutive_timeouts += 1;
+
+                                // Give up after too many timeouts in a row, the server stops the
+                                // algorithm when it receives a fatal error.
+                                let mut report = e.report(Some(timestamp));
+                                let message = if consecutive_timeouts >= max_consecutive_timeouts {
+                                    report.message = format!("{} ({} consecutive timeouts)", report.message, consecutive_timeouts);
+                                    protocol::Message::Fatal(report)
+                                } else {
+                                    protocol::Message::Error(report)
+                                };
+
+                                let fatal = matches!(message, protocol::Message::Fatal(_));
+                                protocol::write_message(&mut *writer.lock().await, &message).await.unwrap_or_default();
+                                if fatal {
+                                    std::process::exit(1);
                                 }
-                            }
-                        };
 
-                        // Write result of Python code back to UnixSocket.
-                        stream.write_all(result.to_string().as_bytes()).await.expect("Failed to send");
+                                continue;
+                            },
+                            _ => {
+                                // Errors of the code are reported and the next candle is
+                                // executed, the PyExecutor can't continue after its own errors.
+                                let report = e.report(Some(timestamp));
+                                let fatal = report.kind == protocol::ErrorKind::Executor;
+                                let message = if fatal { protocol::Message::Fatal(report) } else { protocol::Message::Error(report) };
+
+                                protocol::write_message(&mut *writer.lock().await, &message).await.unwrap_or_default();
+                                if fatal {
+                                    std::process::exit(1);
+                                }
 
-                    },
-                    Err(_) => break,
+                                continue;
+                            },
+                        }
+                    }
+                };
+
+                // Write result of Python code back to UnixSocket.
+                if protocol::write_message(&mut *writer.lock().await, &protocol::Message::Signal(result)).await.is_err() {
+                    break;
                 }
             }
+
+            heartbeat_handle.abort();
         });
     }
 
     Ok(())
 }
 
+// A compiled Python strategy. The module is compiled once when the PyExecutor starts.
+// Besides the data, func can take the following arguments by name:
+// - state: a dict which is passed to every call of func and survives between calls.
+// - params: a dict with the user-defined parameters of the algorithm.
+pub struct Strategy {
+    func: Py<PyAny>,
+    arguments: std::vec::Vec<String>,
+    state: Py<PyDict>,
+    params: std::sync::Mutex<Py<PyDict>>,
+    state_path: String,
+    timeout: std::time::Duration,
+    logs: logs::Logs,
+    // Lines of the code of the user and the amount of lines of imports added before it.
+    source: std::vec::Vec<String>,
+    header_lines: usize,
+}
+
+// File name of the compiled code, used to find the frames of the code in a traceback.
+const STRATEGY_FILE_NAME: &str = "strategy.py";
+
+// Arguments func can take after data.
+const STRATEGY_ARGUMENTS: [&str; 2] = ["state", "params"];
+
+// Libraries imported into the Python code.
+const ALLOWED_LIBRARIES: [&str; 3] = ["math", "numpy", "pandas"];
+
+impl Strategy {
+    // Read, check and compile the Python code of the algorithm. The state is restored from
+    // state_path if a snapshot exists.
+    pub fn load(python_file: &str, state_path: &str) -> Result<Self, Error> {
+        Strategy::load_with_libraries(python_file, state_path, &ALLOWED_LIBRARIES)
+    }
+
+    // Load the code with only the given libraries imported, see load.
+    pub fn load_with_libraries(python_file: &str, state_path: &str, libraries: &[&str]) -> Result<Self, Error> {
+
+        // Retrieve Python code from file.
+        let mut python_code = match std::fs::read_to_string(format!("trading_algos/{}.py", python_file)) {
+            Ok(code) => code,
+            Err(e) => {
+                return Err(Error::PyExecutorError(format!("Error reading Python code: {}", e)));
+            }
+        };
+
+        // Check if Python code only contains allowed elements.
+        if let Err(violations) = validator::validate(&python_code) {
+            let violations = violations.iter().map(|v| v.to_string()).collect::<std::vec::Vec<String>>();
+            return Err(Error::PythonCodeError(format!("Code contained unsafe elements: {}", violations.join(" "))));
+        }
+
+        // Import allowed libraries into Python code.
+        let source = python_code.lines().map(|l| l.to_string()).collect::<std::vec::Vec<String>>();
+        let lines_before = python_code.matches('\n').count();
+        import_allowed_libraries(&mut python_code, libraries);
+        let header_lines = python_code.matches('\n').count() - lines_before;
+
+        // Restore the snapshot of the state.
+        let snapshot = std::fs::read_to_string(state_path).ok();
+
+        pyo3::prepare_freethreaded_python();
+
+        Python::with_gil(|py| {
+
+            // Capture print() and log() of the code, also of the top-level code.
+            let logs = logs::Logs::install(py).map_err(|e| Error::PyExecutorError(format!("Error capturing output: {}", e)))?;
+
+            // Make the indicators available to the code.
+            ta::register(py).map_err(|e| Error::PyExecutorError(format!("Error registering indicators: {}", e)))?;
+
+            // Create PyModule. The code runs with the restricted builtins instead of all of them.
+            let create_module = || -> PyResult<&PyModule> {
+                let module = PyModule::new(py, "strategy")?;
+                module.dict().set_item("__builtins__", validator::restricted_builtins(py)?)?;
+
+                let builtins = py.import("builtins")?;
+                let code = builtins.getattr("compile")?.call1((&python_code, STRATEGY_FILE_NAME, "exec"))?;
+                builtins.getattr("exec")?.call1((code, module.dict()))?;
+
+                Ok(module)
+            };
+            let module = match create_module() {
+                Ok(m) => m,
+                Err(e) => {
+                    return Err(Error::PythonCodeError(format!("Error generating PyModule: {}", e)));
+                }
+            };
+
+            let func = match module.getattr("func") {
+                Ok(f) => f,
+                Err(e) => {
+                    return Err(Error::PythonCodeError(format!("Error finding func-binding in Python code: {}", e)));
+                }
+            };
+
+            // Names of the arguments of func. The first one is always the data, the others
+            // are passed by name.
+            let arguments = func
+                .getattr("__code__")
+                .and_then(|c| {
+                    let argcount = c.getattr("co_argcount")?.extract::<usize>()?;
+                    let varnames = c.getattr("co_varnames")?.extract::<std::vec::Vec<String>>()?;
+                    Ok(varnames.into_iter().take(argcount).collect::<std::vec::Vec<String>>())
+                })
+                .map_err(|e| Error::PythonCodeError(format!("func is not a function: {}", e)))?;
+
+            if arguments.is_empty() {
+                return Err(Error::PythonCodeError("func must take the data as first argument.".into()));
+            }
+
+            let arguments = arguments[1..].to_vec();
+            if let Some(a) = arguments.iter().find(|a| !STRATEGY_ARGUMENTS.contains(&a.as_str())) {
+                return Err(Error::PythonCodeError(format!("Unknown argument \"{}\" of func, allowed are: {}.", a, STRATEGY_ARGUMENTS.join(", "))));
+            }
+
+            let state = match snapshot {
+                Some(snapshot) => {
+                    let restored = py.import("json")
+                        .and_then(|json| json.call_method1("loads", (snapshot,)))
+                        .and_then(|s| s.extract::<&PyDict>());
+
+                    match restored {
+                        Ok(s) => s,
+                        Err(e) => {
+                            return Err(Error::ParseError(format!("Error restoring state: {}", e)));
+                        }
+                    }
+                },
+                None => PyDict::new(py),
+            };
+
+            Ok(Strategy {
+                func: func.into(),
+                arguments,
+                state: state.into(),
+                params: std::sync::Mutex::new(PyDict::new(py).into()),
+                state_path: state_path.to_string(),
+                timeout: std::time::Duration::from_millis(DEFAULT_TIMEOUT_MS),
+                logs,
+                source,
+                header_lines,
+            })
+        })
+    }
+
+    // Take the output of the code since the last call. candle_timestamp is the candle the
+    // output belongs to.
+    pub fn drain_logs(&self, candle_timestamp: Option<u64>) -> std::vec::Vec<protocol::LogLine> {
+        self.logs.drain(candle_timestamp)
+    }
+
+    // Replace the parameters passed to func. params has to be a JSON object.
+    pub fn set_params(&self, params: &serde_json::Value) -> Result<(), Error> {
+        if !params.is_object() {
+            return Err(Error::ParseError("Params must be a JSON object.".into()));
+        }
+
+        Python::with_gil(|py| {
+            let dict = py.import("json")
+                .and_then(|json| json.call_method1("loads", (params.to_string(),)))
+                .and_then(|p| p.extract::<&PyDict>());
+
+            match dict {
+                Ok(d) => {
+                    *self.params.lock().unwrap() = d.into();
+                    Ok(())
+                },
+                Err(e) => Err(Error::ParseError(format!("Error converting params: {}", e))),
+            }
+        })
+    }
+
+    // Set the maximum wall-clock time of a single call of func.
+    pub fn with_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    fn takes(&self, argument: &str) -> bool {
+        self.arguments.iter().any(|a| a == argument)
+    }
+
+    // Call func with the data and the state. After a successful call a snapshot of the state is
+    // written to disk.
+    fn call(&self, window: &window::CandleWindow) -> Result<Option<protocol::Signal>, Error> {
+        Python::with_gil(|py| {
+
+            // The candles of the window in the data format of the algorithm.
+            let py_data = window.data(py).map_err(|e| Error::PyExecutorError(format!("Error converting data: {}", e)))?;
+
+            // Call Python function.
+            let kwargs = PyDict::new(py);
+            if self.takes("state") {
+                kwargs.set_item("state", self.state.as_ref(py)).map_err(|e| Error::PyExecutorError(e.to_string()))?;
+            }
+            if self.takes("params") {
+                kwargs.set_item("params", self.params.lock().unwrap().as_ref(py)).map_err(|e| Error::PyExecutorError(e.to_string()))?;
+            }
+            // Guard the call with a watchdog which interrupts func when it takes too long.
+            let thread_id = py.import("threading")
+                .and_then(|t| t.call_method0("get_ident"))
+                .and_then(|i| i.extract::<u64>())
+                .map_err(|e| Error::PyExecutorError(e.to_string()))?;
+            let watchdog = Watchdog::start(thread_id, self.timeout);
+
+            let f = self.func.call(py, (py_data,), Some(kwargs));
+
+            // The watchdog may be waiting for the GIL, release it while stopping the watchdog.
+            let timed_out = py.allow_threads(|| watchdog.stop());
+            if timed_out {
+                // Clear the TimeoutError in case it was set after func already returned.
+                unsafe { pyo3::ffi::PyThreadState_SetAsyncExc(thread_id as std::os::raw::c_long, std::ptr::null_mut()); }
+                return Err(Error::TimeoutError(format!("func did not return within {} ms.", self.timeout.as_millis())));
+            }
+
+            let f = match f {
+                Ok(f) => f,
+                Err(e) => {
+                    return Err(Error::ExceptionError(e.to_string(), self.traceback(py, &e)));
+                }
+            };
+            let result = extract_signal(f.as_ref(py))?;
+
+            if self.takes("state") {
+                self.snapshot(py)?;
+            }
+
+            Ok(result)
+        })
+    }
+
+    // Format the traceback of an exception raised by func like Python does. Only frames of the
+    // code of the algorithm are kept, with the line numbers of the code as written by the user.
+    fn traceback(&self, py: Python, e: &PyErr) -> String {
+        let mut lines = vec!["Traceback (most recent call last):".to_string()];
+
+        let frames = e.traceback(py)
+            .map(|tb| py.import("traceback").and_then(|t| t.call_method1("extract_tb", (tb,))));
+
+        if let Some(Ok(frames)) = frames {
+            for frame in frames.iter().into_iter().flatten().flatten() {
+                let file_name = frame.getattr("filename").and_then(|f| f.extract::<String>()).unwrap_or_default();
+                let (Ok(lineno), Ok(name)) = (frame.getattr("lineno").and_then(|l| l.extract::<usize>()), frame.getattr("name").and_then(|n| n.extract::<String>())) else {
+                    continue;
+                };
+                if file_name != STRATEGY_FILE_NAME || lineno <= self.header_lines {
+                    continue;
+                }
+
+                let lineno = lineno - self.header_lines;
+                lines.push(format!("  line {}, in {}", lineno, name));
+                if let Some(code) = self.source.get(lineno - 1) {
+                    lines.push(format!("    {}", code.trim()));
+                }
+            }
+        }
+
+        lines.push(e.to_string());
+        lines.join("\n")
+    }
+
+    // Write the state as JSON to disk. The file is replaced atomically so a crash never leaves
+    // a half written snapshot.
+    fn snapshot(&self, py: Python) -> Result<(), Error> {
+        let serialized = match py.import("json").and_then(|json| json.call_method1("dumps", (self.state.as_ref(py),))) {
+            Ok(s) => s.extract::<String>().map_err(|e| Error::ParseError(e.to_string()))?,
+            Err(e) => {
+                return Err(Error::PythonCodeError(format!("State is not JSON serializable: {}", e)));
+            }
+        };
+
+        let tmp_path = format!("{}.tmp", self.state_path);
+        std::fs::write(&tmp_path, serialized)?;
+        std::fs::rename(&tmp_path, &self.state_path)?;
+
+        Ok(())
+    }
+}
+
+// Watchdog interrupting a call of func which exceeds the timeout. The interrupt is done by
+// raising a TimeoutError in the thread running func. Code running in a C-extension can't be
+// interrupted that way, if func still hasn't returned after a second timeout the whole
+// process is exited.
+struct Watchdog {
+    done: std::sync::mpsc::Sender<()>,
+    finished: Arc<std::sync::atomic::AtomicBool>,
+    handle: std::thread::JoinHandle<bool>,
+}
+
+impl Watchdog {
+    fn start(thread_id: u64, timeout: std::time::Duration) -> Self {
+        let (done, done_rx) = std::sync::mpsc::channel::<()>();
+        let finished = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let finished_clone = finished.clone();
+
+        let handle = std::thread::spawn(move || {
+            if done_rx.recv_timeout(timeout) != Err(std::sync::mpsc::RecvTimeoutError::Timeout) {
+                return false;
+            }
+
+            // Last resort when the interpreter doesn't get to handle the TimeoutError.
+            std::thread::spawn(move || {
+                std::thread::sleep(timeout);
+                if !finished_clone.load(std::sync::atomic::Ordering::SeqCst) {
+                    eprintln!("{}", Error::TimeoutError("func could not be interrupted, exiting.".into()));
+                    std::process::exit(1);
+                }
+            });
+
+            Python::with_gil(|_py| unsafe {
+                pyo3::ffi::PyThreadState_SetAsyncExc(thread_id as std::os::raw::c_long, pyo3::ffi::PyExc_TimeoutError);
+            });
+
+            true
+        });
+
+        Watchdog { done, finished, handle }
+    }
+
+    // Stop the watchdog. Returns whether the timeout was exceeded.
+    fn stop(self) -> bool {
+        self.finished.store(true, std::sync::atomic::Ordering::SeqCst);
+        self.done.send(()).unwrap_or_default();
+        self.handle.join().unwrap_or(false)
+    }
+}
+
 // Execute the Python code.
-async fn execute(data: std::vec::Vec<CandleStick>, python_file: String, counter: (Arc<Mutex<u64>>, u64)) -> Result<f64, Error> {
+async fn execute(strategy: &Strategy, window: &window::CandleWindow, counter: (Arc<Mutex<u64>>, u64)) -> Result<Option<protocol::Signal>, Error> {
 
     let mut counter_guard = counter.0.lock().await;
     if counter.1 > 5 && *counter_guard > 0 {
@@ -132,138 +651,118 @@ async fn execute(data: std::vec::Vec<CandleStick>, python_file: String, counter:
     }
     drop(counter_guard);
 
-    pyo3::prepare_freethreaded_python();
+    strategy.call(window)
+}
+    
+// Convert the value returned by func to an order-signal. func either returns a number
+// (r > 0 buys r, r < 0 sells r, 0 does nothing), None, or a dict like:
+// {"side": "BUY", "quantity": 0.01, "type": "LIMIT", "limit_price": 42000, "stop_loss": 41000}
+fn extract_signal(result: &PyAny) -> Result<Option<protocol::Signal>, Error> {
+    if result.is_none() {
+        return Ok(None);
+    }
 
-    // Retrieve Python code from file.
-    let mut python_code = match std::fs::read_to_string(format!("trading_algos/{}.py", python_file)) {
-        Ok(code) => code,
-        Err(e) => {
-            return Err(Error::PyExecutorError(format!("Error reading Python code: {}", e)));
+    let dict = match result.downcast::<PyDict>() {
+        Ok(d) => d,
+        Err(_) => {
+            return match result.extract::<f64>() {
+                Ok(r) => Ok(protocol::Signal::from_f64(r)),
+                Err(e) => Err(Error::PythonCodeError(format!("Error extracting result from Python func: {}", e))),
+            };
         }
     };
 
-    // Check if Python code contains blacklisted keywords.
-    if !arbitrary_code_is_secure(&python_code) {
-        return Err(Error::PythonCodeError("Code contained unsafe elements.".into()));
+    fn invalid(key: &str, e: impl std::fmt::Display) -> Error {
+        Error::PythonCodeError(format!("Invalid value for \"{}\" in signal: {}", key, e))
     }
 
-    // Import allowed libraries into Python code.
-    import_allowed_libraries(&mut python_code);
-
-    // Call Python function func.
-    let result = Python::with_gil(|py| {
+    let mut side = None;
+    let mut signal = protocol::Signal {
+        side: protocol::Side::Buy,
+        quantity: None,
+        quote_quantity: None,
+        order_type: protocol::OrderType::Market,
+        limit_price: None,
+        stop_price: None,
+        stop_loss: None,
+        take_profit: None,
+        time_in_force: None,
+        tag: None,
+        bracket: None,
+    };
 
-        // Convert data of klines to a PyList so it can be passed to Python code.
-        let py_candlesticks: Vec<Py<CandleStick>> = data.into_iter().map(|candlestick| {
-            Py::new(py, candlestick.clone()).unwrap()
-        }).collect();
-        let py_list = PyList::new(py, py_candlesticks);
-        
-        // Create PyModule.
-        let fun: Py<PyAny> = match PyModule::from_code(
-            py,
-            &*python_code,
-            "",
-            "",
-        ) {
-            Ok(f) => {
-                match f.getattr("func") {
-                    Ok(a) => a.into(),
-                    Err(e) => {
-                        return Err(Error::PythonCodeError(format!("Error finding func-binding in Python code: {}", e)));
-                    }
-                }
-            },
-            Err(e) => {
-                return Err(Error::PythonCodeError(format!("Error generating PyModule: {}", e)));
-            }
-        };
+    for (key, value) in dict.iter() {
+        let key = key.extract::<String>().map_err(|e| invalid("key", e))?;
+        if value.is_none() {
+            continue;
+        }
 
-        // Call Python function.
-        let f = match fun.call1(py, (py_list,)) {
-            Ok(f) => f,
-            Err(e) => {
-                return Err(Error::PythonCodeError(format!("Error calling Python func: {}", e)));
+        match &*key {
+            "side" => side = Some(value.extract::<String>().map_err(|e| invalid(&key, e))?.parse().map_err(|e| invalid(&key, e))?),
+            "quantity" => signal.quantity = Some(value.extract::<f64>().map_err(|e| invalid(&key, e))?),
+            "quote_quantity" => signal.quote_quantity = Some(value.extract::<f64>().map_err(|e| invalid(&key, e))?),
+            "type" => signal.order_type = value.extract::<String>().map_err(|e| invalid(&key, e))?.parse().map_err(|e| invalid(&key, e))?,
+            "limit_price" => signal.limit_price = Some(value.extract::<f64>().map_err(|e| invalid(&key, e))?),
+            "stop_price" => signal.stop_price = Some(value.extract::<f64>().map_err(|e| invalid(&key, e))?),
+            "stop_loss" => signal.stop_loss = Some(value.extract::<f64>().map_err(|e| invalid(&key, e))?),
+            "take_profit" => signal.take_profit = Some(value.extract::<f64>().map_err(|e| invalid(&key, e))?),
+            "time_in_force" => signal.time_in_force = Some(value.extract::<String>().map_err(|e| invalid(&key, e))?.parse().map_err(|e| invalid(&key, e))?),
+            "tag" => signal.tag = Some(value.extract::<String>().map_err(|e| invalid(&key, e))?),
+            "bracket" => signal.bracket = Some(extract_bracket(value).map_err(|e| invalid(&key, e))?),
+            _ => {
+                return Err(Error::PythonCodeError(format!("Unknown key \"{}\" in signal.", key)));
             }
-        };
-        let result = match f.extract::<f64>(py) {
-            Ok(r) => r,
-            Err(e) => {
-                return Err(Error::PythonCodeError(format!("Error extracting result from Python func: {}", e)));
-            }
-        };
-        
-        Ok(result)
-    });
+        }
+    }
 
-    let result = match result {
-        Ok(r) => r,
-        Err(e) => {
-            return Err(Error::PythonCodeError(format!("Error getting result from Python code: {}", e)));
+    signal.side = match side {
+        Some(s) => s,
+        None => {
+            return Err(Error::PythonCodeError("Signal requires a side.".into()));
         }
     };
 
-    Ok(result)
-}
-    
-// Import allowed libraries into the Python code.
-fn import_allowed_libraries(code: &mut String) {
-    let allowed_libraries = vec![
-        "math",
-        "numpy",
-        "pandas",
-    ];
-
-    for lib in allowed_libraries {
-        code.insert_str(0, &*format!("import {}\n", lib));
+    match signal.validate() {
+        Ok(_) => Ok(Some(signal)),
+        Err(e) => Err(Error::PythonCodeError(format!("Invalid signal: {}", e))),
     }
 }
 
-// We execute arbitrary Python scripts. Even if this is supposed to be a internal
-// application we need to secure this somehow. We blacklist specific Python keywords like
-// "import", "exec", "os",... so no dangerous code can be executed.
-// We import popular mathematical libraries so those can be used.
-fn arbitrary_code_is_secure(code: &String) -> bool {
-    let blacklist = vec![
-        "import",
-        "read",
-        "write",
-        "file",
-        "exec",
-        "eval",
-        "socket",
-        "http",
-        "requests",
-        "urllib",
-        "sys",
-        "traceback",
-        "system",
-        "locals",
-        "vars",
-        "__",
-    ];
-
-    let re_find_commented_lines = regex::RegexBuilder::new(r"^\s*#.*\n?").multi_line(true).build().unwrap();
-    //let code_without_comments = re_find_commented_lines.replace_all(code, "");
-
-    let code_without_comments = re_find_commented_lines.replace_all(code, |caps: &regex::Captures| {
-        if caps.get(0).unwrap().as_str().contains('\'') {
-            caps.get(0).unwrap().as_str().to_string()
-        } else {
-            "".to_string()
+// Convert the bracket of a signal, a dict like:
+// {"trailing_percent": 2, "stop": 41000, "target": 45000}
+fn extract_bracket(value: &PyAny) -> Result<protocol::Bracket, String> {
+    let dict = value.downcast::<PyDict>().map_err(|e| e.to_string())?;
+
+    let mut bracket = protocol::Bracket::default();
+    for (key, value) in dict.iter() {
+        let key = key.extract::<String>().map_err(|e| e.to_string())?;
+        if value.is_none() {
+            continue;
         }
-    });
 
-    for keyword in blacklist {
-        match code_without_comments.find(keyword) {
-            Some(_) => { return false; },
-            None => (),
+        let value = Some(value.extract::<f64>().map_err(|e| e.to_string())?);
+        match &*key {
+            "trailing_percent" => bracket.trailing_percent = value,
+            "stop" => bracket.stop = value,
+            "target" => bracket.target = value,
+            _ => {
+                return Err(format!("Unknown key \"{}\" in bracket.", key));
+            }
         }
     }
 
-    true
+    Ok(bracket)
 }
 
+// Import allowed libraries into the Python code.
+fn import_allowed_libraries(code: &mut String, libraries: &[&str]) {
+    for lib in libraries {
+        code.insert_str(0, &*format!("import {}\n", lib));
+    }
+
+    // Indicators implemented in Rust.
+    code.insert_str(0, "from algonline import ta\n");
+}
 
 // Error type for PyExecutor.
 #[derive(Debug, PartialEq)]
@@ -273,10 +772,35 @@ pub enum Error {
     ParseError(String),
     PythonCodeError(String),
     StreamError(String),
+    TimeoutError(String),
+    SandboxError(String),
+    // An exception raised by func, with its traceback.
+    ExceptionError(String, String),
 }
 
 impl std::error::Error for Error {}
 
+impl Error {
+    // The error as reported to the server.
+    fn report(&self, candle_timestamp: Option<u64>) -> protocol::ExecutorError {
+        let (kind, message, traceback) = match self {
+            Error::ExceptionError(message, traceback) => (protocol::ErrorKind::Exception, message, Some(traceback.to_string())),
+            Error::PythonCodeError(message) | Error::ParseError(message) => (protocol::ErrorKind::Code, message, None),
+            Error::TimeoutError(message) => (protocol::ErrorKind::Timeout, message, None),
+            Error::StreamError(message) => (protocol::ErrorKind::Protocol, message, None),
+            Error::PyExecutorError(message) | Error::SandboxError(message) => (protocol::ErrorKind::Executor, message, None),
+            Error::CounterError => (protocol::ErrorKind::Executor, &String::new(), None),
+        };
+
+        protocol::ExecutorError {
+            kind,
+            message: message.to_string(),
+            traceback,
+            candle_timestamp,
+        }
+    }
+}
+
 impl From<serde_json::Error> for Error {
     fn from(e: serde_json::Error) -> Self {
         Error::ParseError(e.to_string())
@@ -289,6 +813,12 @@ impl From<std::io::Error> for Error {
     }
 }
 
+impl From<protocol::Error> for Error {
+    fn from(e: protocol::Error) -> Self {
+        Error::StreamError(e.to_string())
+    }
+}
+
 impl From<std::num::ParseFloatError> for Error {
     fn from(e: std::num::ParseFloatError) -> Self {
         Error::ParseError(format!("line: {} {}", line!(), e.to_string()))
@@ -309,6 +839,9 @@ impl std::fmt::Display for Error {
             Error::ParseError(error_msg) => write!(f, "\x1b[31m[Error] PyExecutor - ParseError: {}\x1b[0m", error_msg),
             Error::PythonCodeError(error_msg) => write!(f, "\x1b[31m[Error] PyExecutor - PythonCodeError: {}\x1b[0m", error_msg),
             Error::StreamError(error_msg) => write!(f, "\x1b[31m[Error] PyExecutor - StreamError: {}\x1b[0m", error_msg),
+            Error::TimeoutError(error_msg) => write!(f, "\x1b[31m[Error] PyExecutor - TimeoutError: {}\x1b[0m", error_msg),
+            Error::SandboxError(error_msg) => write!(f, "\x1b[31m[Error] PyExecutor - SandboxError: {}\x1b[0m", error_msg),
+            Error::ExceptionError(error_msg, traceback) => write!(f, "\x1b[31m[Error] PyExecutor - ExceptionError: {}\n{}\x1b[0m", error_msg, traceback),
         }
     }
 }
@@ -319,27 +852,10 @@ impl std::fmt::Display for Error {
 mod tests {
     use crate::*;
 
-    #[test]
-    fn test_arbitrary_code_is_secure() {
-        let safe_code = r#"
-def func(data):
-    return 0
-            "#;
-
-        let unsafe_code = r#"
-import os
-def func(data):
-    return 0
-            "#;
+    type Fixture = (window::CandleWindow, (Arc<Mutex<u64>>, u64), String);
 
-        let test1 = arbitrary_code_is_secure(&safe_code.to_string());
-        let test2 = arbitrary_code_is_secure(&unsafe_code.to_string());
-        assert_eq!(test1, true);
-        assert_eq!(test2, false);
-    }
-
-    #[tokio::test]
-    async fn test_execute() {
+    // The candles, the counter and a removed state file of a test.
+    fn fixture(test: &str, candles: usize) -> Fixture {
         let data = vec![
             CandleStick {
                 timestamp: 1707524880000,
@@ -348,6 +864,7 @@ def func(data):
                 high: 6.0,
                 low: 2.5,
                 volume: 0.18006000,
+                closed: true,
             },
             CandleStick {
                 timestamp: 1707524940000,
@@ -356,6 +873,7 @@ def func(data):
                 high: 10.0,
                 low: 1.6,
                 volume: 0.04270000,
+                closed: true,
             },
             CandleStick {
                 timestamp: 1707525000000,
@@ -364,15 +882,126 @@ def func(data):
                 high: 5.0,
                 low: 2.0,
                 volume: 0.29519000,
+                closed: true,
             },
         ];
+        let window = window::CandleWindow::new(data.into_iter().take(candles).collect(), DEFAULT_MAX_HISTORY, window::DataFormat::List).unwrap();
+
+        let state_path = std::env::temp_dir().join(format!("{}_{}.json", test, std::process::id())).to_str().unwrap().to_string();
+        std::fs::remove_file(&state_path).unwrap_or_default();
+
+        (window, (Arc::new(Mutex::new(0)), 0), state_path)
+    }
 
-        let counter_param = (Arc::new(Mutex::new(0)), 0);
+    // Load a test strategy without numpy and pandas, the test strategies don't use them so the
+    // tests run without them installed.
+    fn load(python_file: &str, state_path: &str) -> Result<Strategy, Error> {
+        Strategy::load_with_libraries(python_file, state_path, &["math"])
+    }
+
+    #[tokio::test]
+    async fn test_execute() {
+        let (window, counter_param, state_path) = fixture("test_execute", 3);
         
-        let test1 = execute(data.clone(), "test/test1".into(), counter_param.clone()).await;
-        let test2 = execute(data.clone(), "test/test2".into(), counter_param.clone()).await;
+        let strategy = load("test/test1", &state_path).unwrap();
+        let test1 = execute(&strategy, &window, counter_param.clone()).await;
+        let test2 = load("test/test2", &state_path).err();
         
-        assert_eq!(test1, Ok(0f64));
-        assert_eq!(test2, Err(Error::PythonCodeError("Code contained unsafe elements.".into())));
+        assert_eq!(test1, Ok(None));
+        assert_eq!(test2, Some(Error::PythonCodeError("Code contained unsafe elements: line 1, column 0: Import is not allowed.".into())));
+    }
+
+    #[tokio::test]
+    async fn test_state() {
+        let (window, counter_param, state_path) = fixture("test_state", 1);
+
+        // The state is kept between calls.
+        let strategy = load("test/test3", &state_path).unwrap();
+        assert_eq!(execute(&strategy, &window, counter_param.clone()).await, Ok(protocol::Signal::from_f64(1f64)));
+        assert_eq!(execute(&strategy, &window, counter_param.clone()).await, Ok(protocol::Signal::from_f64(2f64)));
+
+        // The state is restored from the snapshot.
+        let strategy = load("test/test3", &state_path).unwrap();
+        assert_eq!(execute(&strategy, &window, counter_param.clone()).await, Ok(protocol::Signal::from_f64(3f64)));
+
+        std::fs::remove_file(&state_path).unwrap_or_default();
+    }
+
+    #[test]
+    fn test_extract_signal() {
+        pyo3::prepare_freethreaded_python();
+
+        Python::with_gil(|py| {
+            let eval = |code: &str| extract_signal(py.eval(code, None, None).unwrap());
+
+            assert_eq!(eval("-0.5"), Ok(protocol::Signal::from_f64(-0.5)));
+            assert_eq!(eval("None"), Ok(None));
+
+            let signal = eval("{'side': 'buy', 'quote_quantity': 100, 'type': 'LIMIT', 'limit_price': 40000, 'take_profit': 42000, 'tag': 'breakout'}").unwrap().unwrap();
+            assert_eq!(signal.side, protocol::Side::Buy);
+            assert_eq!(signal.quote_quantity, Some(100f64));
+            assert_eq!(signal.order_type, protocol::OrderType::Limit);
+            assert_eq!(signal.take_profit, Some(42000f64));
+
+            let signal = eval("{'side': 'BUY', 'quantity': 1, 'bracket': {'trailing_percent': 2.5, 'target': 45000}}").unwrap().unwrap();
+            assert_eq!(signal.bracket, Some(protocol::Bracket { trailing_percent: Some(2.5), stop: None, target: Some(45000f64) }));
+            assert!(eval("{'side': 'BUY', 'quantity': 1, 'bracket': {'trailing': 2}}").is_err());
+
+            // Exits only close the position of a buy.
+            assert!(eval("{'side': 'SELL', 'quantity': 1, 'bracket': {'trailing_percent': 2.5}}").is_err());
+            assert!(eval("{'side': 'SELL', 'quantity': 1, 'stop_loss': 45000}").is_err());
+
+            assert!(eval("{'quantity': 1}").is_err());
+            assert!(eval("{'side': 'SELL', 'quantity': 1, 'price': 5}").is_err());
+            assert!(eval("{'side': 'SELL', 'quantity': 1, 'type': 'LIMIT'}").is_err());
+            assert!(eval("'buy'").is_err());
+        });
+    }
+
+    #[tokio::test]
+    async fn test_params() {
+        let (window, counter_param, state_path) = fixture("test_params", 1);
+
+        let strategy = load("test/test4", &state_path).unwrap();
+        assert_eq!(execute(&strategy, &window, counter_param.clone()).await, Ok(protocol::Signal::from_f64(1f64)));
+
+        strategy.set_params(&serde_json::json!({"amount": 0.25})).unwrap();
+        assert_eq!(execute(&strategy, &window, counter_param.clone()).await, Ok(protocol::Signal::from_f64(0.25)));
+        assert!(strategy.set_params(&serde_json::json!([1, 2])).is_err());
+
+        std::fs::remove_file(&state_path).unwrap_or_default();
+    }
+
+    #[tokio::test]
+    async fn test_timeout() {
+        let (window, counter_param, state_path) = fixture("test_timeout", 1);
+
+        // An endless loop is interrupted and the strategy can be called again afterwards.
+        let strategy = load("test/test5", &state_path).unwrap()
+            .with_timeout(std::time::Duration::from_millis(200));
+        let test1 = execute(&strategy, &window, counter_param.clone()).await;
+        let test2 = execute(&strategy, &window, counter_param.clone()).await;
+
+        assert!(matches!(test1, Err(Error::TimeoutError(_))));
+        assert!(matches!(test2, Err(Error::TimeoutError(_))));
+
+        let strategy = load("test/test1", &state_path).unwrap()
+            .with_timeout(std::time::Duration::from_millis(200));
+        assert_eq!(execute(&strategy, &window, counter_param.clone()).await, Ok(None));
+    }
+
+    #[tokio::test]
+    async fn test_exception() {
+        let (window, counter_param, state_path) = fixture("test_exception", 1);
+
+        // The traceback points to the lines of the code as written by the user.
+        let strategy = load("test/test6", &state_path).unwrap();
+        let error = execute(&strategy, &window, counter_param.clone()).await.unwrap_err();
+        let report = error.report(Some(1707524880000));
+
+        assert_eq!(report.kind, protocol::ErrorKind::Exception);
+        assert_eq!(report.message, "ZeroDivisionError: float division by zero");
+        assert_eq!(report.candle_timestamp, Some(1707524880000));
+        assert_eq!(report.traceback.as_deref(), Some("Traceback (most recent call last):\n  line 5, in func\n    return ratio(data[-1].c, 0)\n  line 2, in ratio\n    return a / b\nZeroDivisionError: float division by zero"));
     }
 }
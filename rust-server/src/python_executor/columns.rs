@@ -0,0 +1,273 @@
+// Columnar storage of the candles for the numpy and pandas data formats. Every field of the
+// candles is stored in a contiguous column, the columns are exported to Python with the buffer
+// protocol so numpy arrays and pandas DataFrames can be built without copying the candles.
+//
+// The storage is allocated once with room for twice max_history candles and is never
+// reallocated, so exported buffers always point to valid memory. When the end of the storage
+// is reached the last max_history candles are moved to the front. An exported buffer is a view
+// of the candles at the moment it was created, arrays kept after a call of func can show other
+// candles afterwards.
+
+use pyo3::prelude::*;
+use pyo3::exceptions::PyBufferError;
+use pyo3::ffi;
+use std::ffi::CString;
+use std::os::raw::{c_int, c_void};
+
+use crate::CandleStick;
+
+// Names of the value columns, in the order they are stored.
+pub const VALUE_COLUMNS: [&str; 5] = ["o", "h", "l", "c", "v"];
+
+#[pyclass]
+pub struct Columns {
+    timestamps: Box<[i64]>,
+    values: Box<[f64]>,
+    capacity: usize,
+    max_history: usize,
+    start: usize,
+    len: usize,
+}
+
+// Part of the columns exported by a View.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Column {
+    Timestamps,
+    Value(usize),
+    // All value columns as a 2D buffer of candles x columns.
+    Values,
+}
+
+impl Columns {
+    pub fn new(data: std::vec::Vec<CandleStick>, max_history: usize) -> Self {
+        let max_history = max_history.max(1);
+        let capacity = max_history * 2;
+
+        let mut columns = Columns {
+            timestamps: vec![0; capacity].into_boxed_slice(),
+            values: vec![0f64; capacity * VALUE_COLUMNS.len()].into_boxed_slice(),
+            capacity,
+            max_history,
+            start: 0,
+            len: 0,
+        };
+
+        let skip = data.len().saturating_sub(max_history);
+        for candle in data.iter().skip(skip) {
+            columns.update(candle);
+        }
+
+        columns
+    }
+
+    // Add a candle. A candle with the same open time as the last candle is an update of the
+    // candle in progress and replaces it. Updates of older candles are ignored.
+    pub fn update(&mut self, candle: &CandleStick) {
+        if self.len > 0 {
+            let last = self.timestamps[self.start + self.len - 1];
+            if candle.timestamp as i64 == last {
+                self.write(self.start + self.len - 1, candle);
+                return;
+            }
+            if (candle.timestamp as i64) < last {
+                return;
+            }
+        }
+
+        // Move the candles to the front when the end of the storage is reached.
+        if self.start + self.len == self.capacity {
+            let (start, end) = (self.start, self.start + self.len);
+            self.timestamps.copy_within(start..end, 0);
+            for i in 0..VALUE_COLUMNS.len() {
+                self.values.copy_within(i * self.capacity + start..i * self.capacity + end, i * self.capacity);
+            }
+            self.start = 0;
+        }
+
+        self.write(self.start + self.len, candle);
+        self.len += 1;
+
+        if self.len > self.max_history {
+            self.start += 1;
+            self.len -= 1;
+        }
+    }
+
+    fn write(&mut self, index: usize, candle: &CandleStick) {
+        self.timestamps[index] = candle.timestamp as i64;
+
+        let values = [candle.open, candle.high, candle.low, candle.close, candle.volume];
+        for (i, value) in values.into_iter().enumerate() {
+            self.values[i * self.capacity + index] = value;
+        }
+    }
+
+    // Create a view of a column of the current candles.
+    pub fn view(columns: &Py<Columns>, py: Python, column: Column) -> PyResult<Py<View>> {
+        let c = columns.borrow(py);
+
+        Py::new(py, View {
+            columns: columns.clone_ref(py),
+            column,
+            start: c.start,
+            len: c.len,
+        })
+    }
+}
+
+// A view of a column which can be exported with the buffer protocol.
+#[pyclass]
+pub struct View {
+    columns: Py<Columns>,
+    column: Column,
+    start: usize,
+    len: usize,
+}
+
+#[pymethods]
+impl View {
+    unsafe fn __getbuffer__(slf: &PyCell<Self>, view: *mut ffi::Py_buffer, flags: c_int) -> PyResult<()> {
+        if view.is_null() {
+            return Err(PyBufferError::new_err("View is null"));
+        }
+
+        if (flags & ffi::PyBUF_WRITABLE) == ffi::PyBUF_WRITABLE {
+            return Err(PyBufferError::new_err("Candles are read-only"));
+        }
+
+        let this = slf.borrow();
+        let columns = this.columns.borrow(slf.py());
+
+        // Shape and strides are freed in __releasebuffer__.
+        let (buf, format, ndim, shape, strides) = match this.column {
+            Column::Timestamps => (
+                columns.timestamps.as_ptr().add(this.start) as *mut c_void,
+                "q",
+                1,
+                vec![this.len as isize],
+                vec![std::mem::size_of::<i64>() as isize],
+            ),
+            Column::Value(i) => (
+                columns.values.as_ptr().add(i * columns.capacity + this.start) as *mut c_void,
+                "d",
+                1,
+                vec![this.len as isize],
+                vec![std::mem::size_of::<f64>() as isize],
+            ),
+            Column::Values => {
+                // The values of a candle are not next to each other.
+                if (flags & ffi::PyBUF_STRIDES) != ffi::PyBUF_STRIDES {
+                    return Err(PyBufferError::new_err("Values can only be exported with strides"));
+                }
+
+                (
+                    columns.values.as_ptr().add(this.start) as *mut c_void,
+                    "d",
+                    2,
+                    vec![this.len as isize, VALUE_COLUMNS.len() as isize],
+                    vec![std::mem::size_of::<f64>() as isize, (columns.capacity * std::mem::size_of::<f64>()) as isize],
+                )
+            },
+        };
+
+        let itemsize = 8;
+        let mut shape_and_strides = shape.into_iter().chain(strides).collect::<std::vec::Vec<isize>>().into_boxed_slice();
+
+        (*view).obj = ffi::_Py_NewRef(slf.as_ptr());
+        (*view).buf = buf;
+        (*view).len = shape_and_strides[..ndim].iter().product::<isize>() * itemsize;
+        (*view).readonly = 1;
+        (*view).itemsize = itemsize;
+        (*view).format = if (flags & ffi::PyBUF_FORMAT) == ffi::PyBUF_FORMAT {
+            CString::new(format).unwrap().into_raw()
+        } else {
+            std::ptr::null_mut()
+        };
+        (*view).ndim = ndim as c_int;
+        (*view).shape = if (flags & ffi::PyBUF_ND) == ffi::PyBUF_ND {
+            shape_and_strides.as_mut_ptr()
+        } else {
+            std::ptr::null_mut()
+        };
+        (*view).strides = if (flags & ffi::PyBUF_STRIDES) == ffi::PyBUF_STRIDES {
+            shape_and_strides.as_mut_ptr().add(ndim)
+        } else {
+            std::ptr::null_mut()
+        };
+        (*view).suboffsets = std::ptr::null_mut();
+        (*view).internal = Box::into_raw(Box::new(shape_and_strides)) as *mut c_void;
+
+        Ok(())
+    }
+
+    unsafe fn __releasebuffer__(&self, view: *mut ffi::Py_buffer) {
+        if !(*view).format.is_null() {
+            drop(CString::from_raw((*view).format));
+        }
+        drop(Box::from_raw((*view).internal as *mut Box<[isize]>));
+    }
+
+    fn __len__(&self) -> usize {
+        self.len
+    }
+}
+
+
+// Testing the columns.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candle(timestamp: u64) -> CandleStick {
+        CandleStick {
+            timestamp,
+            open: timestamp as f64,
+            close: timestamp as f64 + 0.5,
+            high: timestamp as f64 + 1.0,
+            low: timestamp as f64 - 1.0,
+            volume: 10.0,
+            closed: true,
+        }
+    }
+
+    fn to_list(py: Python, columns: &Py<Columns>, column: Column) -> String {
+        let view = Columns::view(columns, py, column).unwrap();
+        let memoryview = py.eval("memoryview", None, None).unwrap().call1((view,)).unwrap();
+        memoryview.call_method0("tolist").unwrap().to_string()
+    }
+
+    #[test]
+    fn test_columns() {
+        pyo3::prepare_freethreaded_python();
+
+        Python::with_gil(|py| {
+            let columns = Py::new(py, Columns::new((0..5).map(candle).collect(), 3)).unwrap();
+            assert_eq!(to_list(py, &columns, Column::Timestamps), "[2, 3, 4]");
+            assert_eq!(to_list(py, &columns, Column::Value(3)), "[2.5, 3.5, 4.5]");
+            assert_eq!(to_list(py, &columns, Column::Values), "[[2.0, 3.0, 1.0, 2.5, 10.0], [3.0, 4.0, 2.0, 3.5, 10.0], [4.0, 5.0, 3.0, 4.5, 10.0]]");
+
+            // The candle in progress is replaced, the storage is compacted when it is full.
+            columns.borrow_mut(py).update(&CandleStick { close: 7.0, ..candle(4) });
+            for t in 5..9 {
+                columns.borrow_mut(py).update(&candle(t));
+            }
+            columns.borrow_mut(py).update(&candle(1));
+            assert_eq!(to_list(py, &columns, Column::Timestamps), "[6, 7, 8]");
+            assert_eq!(to_list(py, &columns, Column::Value(0)), "[6.0, 7.0, 8.0]");
+        });
+    }
+
+    #[test]
+    fn test_read_only() {
+        pyo3::prepare_freethreaded_python();
+
+        Python::with_gil(|py| {
+            let columns = Py::new(py, Columns::new((0..2).map(candle).collect(), 3)).unwrap();
+            let view = Columns::view(&columns, py, Column::Value(0)).unwrap();
+            let memoryview = py.eval("memoryview", None, None).unwrap().call1((view,)).unwrap();
+
+            assert!(memoryview.getattr("readonly").unwrap().is_true().unwrap());
+            assert!(memoryview.set_item(0, 1.0).is_err());
+        });
+    }
+}
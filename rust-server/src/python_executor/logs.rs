@@ -0,0 +1,176 @@
+// Capture of the output of the Python code. sys.stdout and sys.stderr are replaced by writers
+// collecting the lines, and the helper log(*values) is added to the builtins. The collected
+// lines are sent to the server after every execution of func.
+//
+// To protect the server lines are truncated to MAX_LINE_LEN characters and at most
+// MAX_LINES lines are kept between two executions, the other lines are counted and reported
+// as dropped.
+
+use pyo3::prelude::*;
+use pyo3::types::PyTuple;
+use std::sync::{Arc, Mutex};
+
+use crate::protocol::{LogLine, LogStream};
+
+const MAX_LINE_LEN: usize = 1000;
+const MAX_LINES: usize = 100;
+
+#[derive(Default)]
+struct Buffer {
+    lines: std::vec::Vec<(LogStream, String)>,
+    // Output of stdout and stderr which doesn't end with a newline yet.
+    partial: [String; 2],
+    dropped: usize,
+}
+
+impl Buffer {
+    fn push(&mut self, stream: LogStream, line: &str) {
+        if self.lines.len() >= MAX_LINES {
+            self.dropped += 1;
+            return;
+        }
+
+        let line = match line.char_indices().nth(MAX_LINE_LEN) {
+            Some((i, _)) => format!("{}...", &line[..i]),
+            None => line.to_string(),
+        };
+        self.lines.push((stream, line));
+    }
+
+    fn write(&mut self, stream: LogStream, text: &str) {
+        let index = if stream == LogStream::Stderr { 1 } else { 0 };
+        let mut text = std::mem::take(&mut self.partial[index]) + text;
+
+        while let Some(newline) = text.find('\n') {
+            let line = text[..newline].trim_end_matches('\r').to_string();
+            self.push(stream, &line);
+            text.drain(..=newline);
+        }
+
+        // Don't let a line without newline grow without bound, one character more is kept so
+        // the line is marked as truncated.
+        text.truncate(text.char_indices().nth(MAX_LINE_LEN + 1).map(|(i, _)| i).unwrap_or(text.len()));
+        self.partial[index] = text;
+    }
+}
+
+// Replacement of sys.stdout and sys.stderr.
+#[pyclass]
+struct Writer {
+    stream: LogStream,
+    buffer: Arc<Mutex<Buffer>>,
+}
+
+#[pymethods]
+impl Writer {
+    fn write(&self, text: &str) -> usize {
+        self.buffer.lock().unwrap().write(self.stream, text);
+        text.chars().count()
+    }
+
+    fn flush(&self) {}
+}
+
+// The log() helper, works like print().
+#[pyclass]
+struct Log {
+    buffer: Arc<Mutex<Buffer>>,
+}
+
+#[pymethods]
+impl Log {
+    #[pyo3(signature = (*values))]
+    fn __call__(&self, values: &PyTuple) -> PyResult<()> {
+        let line = values.iter().map(|v| v.str().map(|s| s.to_string())).collect::<PyResult<std::vec::Vec<String>>>()?.join(" ");
+
+        // A message over multiple lines is kept together.
+        self.buffer.lock().unwrap().push(LogStream::Log, &line);
+        Ok(())
+    }
+}
+
+pub struct Logs {
+    buffer: Arc<Mutex<Buffer>>,
+}
+
+impl Logs {
+    // Start capturing the output of the Python code.
+    // Installing again shares the buffer of the installed writers.
+    pub fn install(py: Python) -> PyResult<Self> {
+        let sys = py.import("sys")?;
+
+        let buffer = match sys.getattr("stdout")?.extract::<PyRef<Writer>>() {
+            Ok(writer) => writer.buffer.clone(),
+            Err(_) => Arc::new(Mutex::new(Buffer::default())),
+        };
+
+        sys.setattr("stdout", Py::new(py, Writer { stream: LogStream::Stdout, buffer: buffer.clone() })?)?;
+        sys.setattr("stderr", Py::new(py, Writer { stream: LogStream::Stderr, buffer: buffer.clone() })?)?;
+        py.import("builtins")?.setattr("log", Py::new(py, Log { buffer: buffer.clone() })?)?;
+
+        Ok(Logs { buffer })
+    }
+
+    // Take the collected lines. Output without newline is included.
+    pub fn drain(&self, candle_timestamp: Option<u64>) -> std::vec::Vec<LogLine> {
+        let mut buffer = self.buffer.lock().unwrap();
+
+        for (index, stream) in [LogStream::Stdout, LogStream::Stderr].into_iter().enumerate() {
+            let partial = std::mem::take(&mut buffer.partial[index]);
+            if !partial.is_empty() {
+                buffer.push(stream, &partial);
+            }
+        }
+
+        let mut lines = std::mem::take(&mut buffer.lines).into_iter()
+            .map(|(stream, message)| LogLine { stream, message, candle_timestamp })
+            .collect::<std::vec::Vec<LogLine>>();
+
+        if buffer.dropped > 0 {
+            lines.push(LogLine {
+                stream: LogStream::Stderr,
+                message: format!("{} lines of output dropped, at most {} lines are kept per execution.", buffer.dropped, MAX_LINES),
+                candle_timestamp,
+            });
+            buffer.dropped = 0;
+        }
+
+        lines
+    }
+}
+
+
+// Testing the capture of output.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_logs() {
+        pyo3::prepare_freethreaded_python();
+
+        Python::with_gil(|py| {
+            let logs = Logs::install(py).unwrap();
+
+            py.run("print('a', 1)\nprint('b', end='')\nlog('c', 2.5)\nimport sys\nsys.stderr.write('d\\r\\ne')", None, None).unwrap();
+            let lines = logs.drain(Some(42));
+
+            let lines = lines.iter().map(|l| (l.stream, l.message.as_str())).collect::<std::vec::Vec<_>>();
+            assert_eq!(lines, vec![
+                (LogStream::Stdout, "a 1"),
+                (LogStream::Log, "c 2.5"),
+                (LogStream::Stderr, "d"),
+                (LogStream::Stdout, "b"),
+                (LogStream::Stderr, "e"),
+            ]);
+
+            // Output is limited.
+            py.run("for i in range(150):\n    print('x' * 2000)", None, None).unwrap();
+            let lines = logs.drain(None);
+            assert_eq!(lines.len(), MAX_LINES + 1);
+            assert_eq!(lines[0].message.chars().count(), MAX_LINE_LEN + 3);
+            assert!(lines[MAX_LINES].message.starts_with("50 lines"));
+            assert!(logs.drain(None).is_empty());
+        });
+    }
+}
@@ -0,0 +1,236 @@
+// Sandbox of the PyExecutor. Besides the AppArmor profile and cgroups configured on the
+// server, the PyExecutor restricts itself before any Python code is executed:
+// - Resource limits on CPU time, address space and open files.
+// - A seccomp-bpf filter allowing only the syscalls needed to run the interpreter. Creating
+//   processes, executing programs and opening new sockets is denied.
+// The filter is only available on Linux x86_64 and aarch64, other platforms only get the
+// resource limits. There is no RLIMIT_NPROC: it counts the threads of every process of the user,
+// not only the PyExecutor, and the filter already denies creating processes.
+
+// Limits applied to the PyExecutor.
+#[derive(Clone, Debug)]
+pub struct Limits {
+    pub cpu_sec: u64,
+    pub memory_mb: u64,
+    pub open_files: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            cpu_sec: 60 * 60 * 24,
+            memory_mb: 2048,
+            open_files: 64,
+        }
+    }
+}
+
+// Apply the resource limits and the syscall filter to the whole process.
+pub fn apply(limits: &Limits) -> Result<(), String> {
+    set_rlimit(libc::RLIMIT_CPU, limits.cpu_sec)?;
+    set_rlimit(libc::RLIMIT_AS, limits.memory_mb * 1024 * 1024)?;
+    set_rlimit(libc::RLIMIT_NOFILE, limits.open_files)?;
+
+    install_filter(true)
+}
+
+// The type of the resource differs between the C libraries.
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+type Resource = libc::__rlimit_resource_t;
+#[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+type Resource = libc::c_int;
+
+fn set_rlimit(resource: Resource, limit: u64) -> Result<(), String> {
+    let rlimit = libc::rlimit {
+        rlim_cur: limit as libc::rlim_t,
+        rlim_max: limit as libc::rlim_t,
+    };
+
+    if unsafe { libc::setrlimit(resource, &rlimit) } != 0 {
+        return Err(format!("Could not set resource limit {}: {}", resource, std::io::Error::last_os_error()));
+    }
+
+    Ok(())
+}
+
+#[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod filter {
+    // Values from linux/seccomp.h, linux/filter.h and linux/audit.h.
+    pub const SECCOMP_SET_MODE_FILTER: libc::c_long = 1;
+    pub const SECCOMP_FILTER_FLAG_TSYNC: libc::c_long = 1;
+    pub const SECCOMP_RET_KILL_PROCESS: u32 = 0x8000_0000;
+    pub const SECCOMP_RET_ERRNO: u32 = 0x0005_0000;
+    pub const SECCOMP_RET_ALLOW: u32 = 0x7fff_0000;
+
+    // Instructions, BPF_LD | BPF_W | BPF_ABS, BPF_JMP | BPF_JEQ | BPF_K,...
+    pub const BPF_LD_W_ABS: u16 = 0x20;
+    pub const BPF_JEQ_K: u16 = 0x15;
+    pub const BPF_JSET_K: u16 = 0x45;
+    pub const BPF_RET_K: u16 = 0x06;
+
+    // Offsets in struct seccomp_data.
+    pub const OFFSET_NR: u32 = 0;
+    pub const OFFSET_ARCH: u32 = 4;
+    pub const OFFSET_ARG0: u32 = 16;
+
+    #[cfg(target_arch = "x86_64")]
+    pub const AUDIT_ARCH: u32 = 0xc000_003e;
+    #[cfg(target_arch = "aarch64")]
+    pub const AUDIT_ARCH: u32 = 0xc000_00b7;
+
+    // Syscalls the interpreter, the tokio runtime and the UnixSocket need.
+    pub const ALLOWED_SYSCALLS: &[libc::c_long] = &[
+        // Memory.
+        libc::SYS_brk, libc::SYS_mmap, libc::SYS_munmap, libc::SYS_mremap, libc::SYS_mprotect,
+        libc::SYS_madvise, libc::SYS_membarrier,
+        // Files. Access to paths is restricted by AppArmor.
+        libc::SYS_openat, libc::SYS_close, libc::SYS_read, libc::SYS_write, libc::SYS_readv,
+        libc::SYS_writev, libc::SYS_pread64, libc::SYS_pwrite64, libc::SYS_lseek, libc::SYS_fstat,
+        libc::SYS_newfstatat, libc::SYS_statx, libc::SYS_getdents64, libc::SYS_readlinkat,
+        libc::SYS_faccessat, libc::SYS_faccessat2, libc::SYS_renameat, libc::SYS_renameat2,
+        libc::SYS_unlinkat, libc::SYS_mkdirat, libc::SYS_fcntl, libc::SYS_ioctl, libc::SYS_dup,
+        libc::SYS_dup3, libc::SYS_pipe2, libc::SYS_getcwd, libc::SYS_fsync, libc::SYS_ftruncate,
+        // The existing UnixSocket. Creating new sockets is denied.
+        libc::SYS_accept, libc::SYS_accept4, libc::SYS_recvfrom, libc::SYS_sendto,
+        libc::SYS_recvmsg, libc::SYS_sendmsg, libc::SYS_shutdown, libc::SYS_getsockname,
+        libc::SYS_getpeername, libc::SYS_getsockopt, libc::SYS_setsockopt,
+        // Polling.
+        libc::SYS_epoll_create1, libc::SYS_epoll_ctl, libc::SYS_epoll_pwait, libc::SYS_ppoll,
+        libc::SYS_pselect6, libc::SYS_eventfd2,
+        // Threads and synchronisation.
+        libc::SYS_futex, libc::SYS_set_robust_list, libc::SYS_get_robust_list, libc::SYS_rseq,
+        libc::SYS_set_tid_address, libc::SYS_sched_yield, libc::SYS_sched_getaffinity,
+        libc::SYS_prctl, libc::SYS_gettid, libc::SYS_tgkill,
+        // Signals.
+        libc::SYS_rt_sigaction, libc::SYS_rt_sigprocmask, libc::SYS_rt_sigreturn,
+        libc::SYS_sigaltstack, libc::SYS_restart_syscall,
+        // Time.
+        libc::SYS_clock_gettime, libc::SYS_clock_getres, libc::SYS_clock_nanosleep,
+        libc::SYS_nanosleep, libc::SYS_gettimeofday,
+        // Process information.
+        libc::SYS_getpid, libc::SYS_getppid, libc::SYS_getuid, libc::SYS_geteuid,
+        libc::SYS_getgid, libc::SYS_getegid, libc::SYS_getrandom, libc::SYS_uname,
+        libc::SYS_sysinfo, libc::SYS_getrusage, libc::SYS_times, libc::SYS_prlimit64,
+        // Exit.
+        libc::SYS_exit, libc::SYS_exit_group,
+    ];
+
+    // Syscalls which only exist on x86_64.
+    #[cfg(target_arch = "x86_64")]
+    pub const ALLOWED_ARCH_SYSCALLS: &[libc::c_long] = &[
+        libc::SYS_open, libc::SYS_stat, libc::SYS_lstat, libc::SYS_access, libc::SYS_readlink,
+        libc::SYS_rename, libc::SYS_unlink, libc::SYS_mkdir, libc::SYS_getdents, libc::SYS_poll,
+        libc::SYS_select, libc::SYS_pipe, libc::SYS_dup2, libc::SYS_epoll_wait,
+        libc::SYS_arch_prctl, libc::SYS_time,
+    ];
+    #[cfg(target_arch = "aarch64")]
+    pub const ALLOWED_ARCH_SYSCALLS: &[libc::c_long] = &[];
+}
+
+#[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn build_filter() -> std::vec::Vec<libc::sock_filter> {
+    use filter::*;
+
+    fn stmt(code: u16, k: u32) -> libc::sock_filter {
+        libc::sock_filter { code, jt: 0, jf: 0, k }
+    }
+
+    fn jump(code: u16, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+        libc::sock_filter { code, jt, jf, k }
+    }
+
+    let mut program = vec![
+        // Kill the process if the syscall is made with another calling convention.
+        stmt(BPF_LD_W_ABS, OFFSET_ARCH),
+        jump(BPF_JEQ_K, AUDIT_ARCH, 1, 0),
+        stmt(BPF_RET_K, SECCOMP_RET_KILL_PROCESS),
+        stmt(BPF_LD_W_ABS, OFFSET_NR),
+
+        // clone is only allowed to create threads, not processes.
+        jump(BPF_JEQ_K, libc::SYS_clone as u32, 0, 4),
+        stmt(BPF_LD_W_ABS, OFFSET_ARG0),
+        jump(BPF_JSET_K, libc::CLONE_THREAD as u32, 0, 1),
+        stmt(BPF_RET_K, SECCOMP_RET_ALLOW),
+        stmt(BPF_RET_K, SECCOMP_RET_ERRNO | libc::EPERM as u32),
+
+        // The flags of clone3 are behind a pointer and can't be checked. Returning ENOSYS makes
+        // the C library fall back to clone.
+        jump(BPF_JEQ_K, libc::SYS_clone3 as u32, 0, 1),
+        stmt(BPF_RET_K, SECCOMP_RET_ERRNO | libc::ENOSYS as u32),
+    ];
+
+    for syscall in ALLOWED_SYSCALLS.iter().chain(ALLOWED_ARCH_SYSCALLS.iter()) {
+        program.push(jump(BPF_JEQ_K, *syscall as u32, 0, 1));
+        program.push(stmt(BPF_RET_K, SECCOMP_RET_ALLOW));
+    }
+
+    // Every other syscall fails.
+    program.push(stmt(BPF_RET_K, SECCOMP_RET_ERRNO | libc::EPERM as u32));
+
+    program
+}
+
+// Install the syscall filter. With all_threads the filter is applied to every thread of the
+// process, otherwise only to the calling thread.
+#[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+pub fn install_filter(all_threads: bool) -> Result<(), String> {
+    let mut program = build_filter();
+    let fprog = libc::sock_fprog {
+        len: program.len() as libc::c_ushort,
+        filter: program.as_mut_ptr(),
+    };
+
+    // Required to install a filter without privileges, also prevents gaining privileges
+    // through setuid binaries.
+    if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } != 0 {
+        return Err(format!("Could not set no_new_privs: {}", std::io::Error::last_os_error()));
+    }
+
+    let flags = if all_threads { filter::SECCOMP_FILTER_FLAG_TSYNC } else { 0 };
+    let result = unsafe { libc::syscall(libc::SYS_seccomp, filter::SECCOMP_SET_MODE_FILTER, flags, &fprog as *const libc::sock_fprog) };
+    if result != 0 {
+        return Err(format!("Could not install seccomp filter: {}", std::io::Error::last_os_error()));
+    }
+
+    Ok(())
+}
+
+#[cfg(not(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64"))))]
+pub fn install_filter(_all_threads: bool) -> Result<(), String> {
+    eprintln!("\x1b[33m[Warning] PyExecutor - seccomp filter is not supported on this platform.\x1b[0m");
+    Ok(())
+}
+
+
+// Testing the sandbox.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter() {
+        // The filter is installed on a separate thread so the other tests are not affected.
+        let handle = std::thread::spawn(|| {
+            install_filter(false).unwrap();
+
+            let fork = unsafe { libc::fork() };
+            let fork_error = std::io::Error::last_os_error().raw_os_error();
+            let socket = unsafe { libc::socket(libc::AF_INET, libc::SOCK_STREAM, 0) };
+            let socket_error = std::io::Error::last_os_error().raw_os_error();
+            let exec = std::process::Command::new("/bin/true").status();
+
+            // Threads and allowed syscalls keep working.
+            let thread = std::thread::spawn(|| 1 + 1).join();
+            let pid = unsafe { libc::getpid() };
+
+            (fork, fork_error, socket, socket_error, exec.is_err(), thread.ok(), pid)
+        });
+
+        let (fork, fork_error, socket, socket_error, exec_failed, thread, pid) = handle.join().unwrap();
+        assert_eq!((fork, fork_error), (-1, Some(libc::EPERM)));
+        assert_eq!((socket, socket_error), (-1, Some(libc::EPERM)));
+        assert!(exec_failed);
+        assert_eq!(thread, Some(2));
+        assert!(pid > 0);
+    }
+}
@@ -0,0 +1,352 @@
+// Technical analysis indicators implemented in Rust. The functions are available in the
+// Python code as the module algonline.ta, which is imported as ta, e.g:
+//   closes_sma = ta.sma(data, 20)
+//   macd, signal, histogram = ta.macd(data)
+// Indicators on a single series take the candles (the close prices are used) or a list or
+// numpy array of numbers. ATR and VWAP take the candles. The candles can be in any data format.
+// The result has the same length as the input, values which can't be calculated yet are NaN.
+
+use pyo3::prelude::*;
+use pyo3::buffer::PyBuffer;
+use pyo3::types::{PyDict, PyList};
+
+use crate::CandleStick;
+
+// Register the module algonline.ta so it can be imported by the Python code.
+pub fn register(py: Python) -> PyResult<()> {
+    let algonline = PyModule::new(py, "algonline")?;
+    let ta = PyModule::new(py, "ta")?;
+
+    ta.add_function(wrap_pyfunction!(py_sma, ta)?)?;
+    ta.add_function(wrap_pyfunction!(py_ema, ta)?)?;
+    ta.add_function(wrap_pyfunction!(py_rsi, ta)?)?;
+    ta.add_function(wrap_pyfunction!(py_macd, ta)?)?;
+    ta.add_function(wrap_pyfunction!(py_bollinger, ta)?)?;
+    ta.add_function(wrap_pyfunction!(py_atr, ta)?)?;
+    ta.add_function(wrap_pyfunction!(py_vwap, ta)?)?;
+    algonline.add_submodule(ta)?;
+
+    let modules = py.import("sys")?.getattr("modules")?;
+    modules.set_item("algonline", algonline)?;
+    modules.set_item("algonline.ta", ta)?;
+
+    Ok(())
+}
+
+// Simple moving average.
+pub fn sma(values: &[f64], period: usize) -> std::vec::Vec<f64> {
+    let mut result = vec![f64::NAN; values.len()];
+    if period == 0 {
+        return result;
+    }
+
+    let mut sum = 0f64;
+    for i in 0..values.len() {
+        sum += values[i];
+        if i >= period {
+            sum -= values[i - period];
+        }
+        if i + 1 >= period {
+            result[i] = sum / period as f64;
+        }
+    }
+
+    result
+}
+
+// Exponential moving average, starting with the SMA of the first period values.
+pub fn ema(values: &[f64], period: usize) -> std::vec::Vec<f64> {
+    let mut result = vec![f64::NAN; values.len()];
+    if period == 0 || values.len() < period {
+        return result;
+    }
+
+    let alpha = 2f64 / (period as f64 + 1f64);
+    let mut ema = values[..period].iter().sum::<f64>() / period as f64;
+    result[period - 1] = ema;
+
+    for i in period..values.len() {
+        ema = values[i] * alpha + ema * (1f64 - alpha);
+        result[i] = ema;
+    }
+
+    result
+}
+
+// Relative strength index with Wilder's smoothing.
+pub fn rsi(values: &[f64], period: usize) -> std::vec::Vec<f64> {
+    let mut result = vec![f64::NAN; values.len()];
+    if period == 0 || values.len() <= period {
+        return result;
+    }
+
+    let rsi = |gain: f64, loss: f64| {
+        if loss == 0f64 {
+            if gain == 0f64 { 50f64 } else { 100f64 }
+        } else {
+            100f64 - 100f64 / (1f64 + gain / loss)
+        }
+    };
+
+    let (mut gain, mut loss) = (0f64, 0f64);
+    for i in 1..=period {
+        let change = values[i] - values[i - 1];
+        gain += change.max(0f64);
+        loss += (-change).max(0f64);
+    }
+    gain /= period as f64;
+    loss /= period as f64;
+    result[period] = rsi(gain, loss);
+
+    for i in period + 1..values.len() {
+        let change = values[i] - values[i - 1];
+        gain = (gain * (period - 1) as f64 + change.max(0f64)) / period as f64;
+        loss = (loss * (period - 1) as f64 + (-change).max(0f64)) / period as f64;
+        result[i] = rsi(gain, loss);
+    }
+
+    result
+}
+
+// Moving average convergence divergence. Returns the MACD line, the signal line and the
+// histogram.
+pub fn macd(values: &[f64], fast: usize, slow: usize, signal: usize) -> (std::vec::Vec<f64>, std::vec::Vec<f64>, std::vec::Vec<f64>) {
+    let fast = ema(values, fast);
+    let slow = ema(values, slow);
+    let line = fast.iter().zip(slow.iter()).map(|(f, s)| f - s).collect::<std::vec::Vec<f64>>();
+
+    // The signal line is the EMA of the part of the MACD line which is calculated.
+    let start = line.iter().position(|v| !v.is_nan()).unwrap_or(line.len());
+    let mut signal_line = vec![f64::NAN; start];
+    signal_line.extend(ema(&line[start..], signal));
+
+    let histogram = line.iter().zip(signal_line.iter()).map(|(l, s)| l - s).collect();
+
+    (line, signal_line, histogram)
+}
+
+// Bollinger bands. Returns the upper band, the middle band (SMA) and the lower band.
+pub fn bollinger(values: &[f64], period: usize, k: f64) -> (std::vec::Vec<f64>, std::vec::Vec<f64>, std::vec::Vec<f64>) {
+    let middle = sma(values, period);
+    let mut upper = vec![f64::NAN; values.len()];
+    let mut lower = vec![f64::NAN; values.len()];
+
+    for i in 0..values.len() {
+        if middle[i].is_nan() {
+            continue;
+        }
+
+        let window = &values[i + 1 - period..=i];
+        let variance = window.iter().map(|v| (v - middle[i]).powi(2)).sum::<f64>() / period as f64;
+        upper[i] = middle[i] + k * variance.sqrt();
+        lower[i] = middle[i] - k * variance.sqrt();
+    }
+
+    (upper, middle, lower)
+}
+
+// Average true range with Wilder's smoothing.
+pub fn atr(high: &[f64], low: &[f64], close: &[f64], period: usize) -> std::vec::Vec<f64> {
+    let len = high.len().min(low.len()).min(close.len());
+    let mut result = vec![f64::NAN; len];
+    if period == 0 || len < period {
+        return result;
+    }
+
+    let true_range = (0..len).map(|i| {
+        let range = high[i] - low[i];
+        match i {
+            0 => range,
+            _ => range.max((high[i] - close[i - 1]).abs()).max((low[i] - close[i - 1]).abs()),
+        }
+    }).collect::<std::vec::Vec<f64>>();
+
+    let mut atr = true_range[..period].iter().sum::<f64>() / period as f64;
+    result[period - 1] = atr;
+
+    for i in period..len {
+        atr = (atr * (period - 1) as f64 + true_range[i]) / period as f64;
+        result[i] = atr;
+    }
+
+    result
+}
+
+// Volume weighted average price of the typical price (high + low + close) / 3, cumulative from
+// the first candle.
+pub fn vwap(high: &[f64], low: &[f64], close: &[f64], volume: &[f64]) -> std::vec::Vec<f64> {
+    let len = high.len().min(low.len()).min(close.len()).min(volume.len());
+    let (mut weighted, mut total) = (0f64, 0f64);
+
+    (0..len).map(|i| {
+        weighted += (high[i] + low[i] + close[i]) / 3f64 * volume[i];
+        total += volume[i];
+        if total > 0f64 { weighted / total } else { f64::NAN }
+    }).collect()
+}
+
+// Get the values of a field of the candles: a list of CandleStick objects, a dict of numpy
+// arrays or a DataFrame.
+fn field(data: &PyAny, name: &str) -> PyResult<std::vec::Vec<f64>> {
+    if let Ok(list) = data.downcast::<PyList>() {
+        return list.iter().map(|item| {
+            let candle = item.downcast::<PyCell<CandleStick>>()?.borrow();
+            Ok(match name {
+                "o" => candle.open,
+                "h" => candle.high,
+                "l" => candle.low,
+                "v" => candle.volume,
+                _ => candle.close,
+            })
+        }).collect();
+    }
+
+    numbers(data.get_item(name)?)
+}
+
+// Get the series of an indicator: the close prices of candles or a list or array of numbers.
+fn series(values: &PyAny) -> PyResult<std::vec::Vec<f64>> {
+    let candles = match values.downcast::<PyList>() {
+        Ok(list) => list.get_item(0).map(|i| i.is_instance_of::<CandleStick>()).unwrap_or(false),
+        Err(_) => values.is_instance_of::<PyDict>() || values.hasattr("columns")?,
+    };
+
+    if candles {
+        field(values, "c")
+    } else {
+        numbers(values)
+    }
+}
+
+fn numbers(values: &PyAny) -> PyResult<std::vec::Vec<f64>> {
+    // Read numpy arrays of floats directly from their buffer.
+    if let Ok(buffer) = PyBuffer::<f64>::get(values) {
+        return buffer.to_vec(values.py());
+    }
+
+    match values.extract::<std::vec::Vec<f64>>() {
+        Ok(v) => Ok(v),
+        Err(_) => values.iter()?.map(|v| v?.extract::<f64>()).collect(),
+    }
+}
+
+#[pyfunction]
+#[pyo3(name = "sma")]
+fn py_sma(values: &PyAny, period: usize) -> PyResult<std::vec::Vec<f64>> {
+    Ok(sma(&series(values)?, period))
+}
+
+#[pyfunction]
+#[pyo3(name = "ema")]
+fn py_ema(values: &PyAny, period: usize) -> PyResult<std::vec::Vec<f64>> {
+    Ok(ema(&series(values)?, period))
+}
+
+#[pyfunction]
+#[pyo3(name = "rsi", signature = (values, period = 14))]
+fn py_rsi(values: &PyAny, period: usize) -> PyResult<std::vec::Vec<f64>> {
+    Ok(rsi(&series(values)?, period))
+}
+
+#[pyfunction]
+#[pyo3(name = "macd", signature = (values, fast = 12, slow = 26, signal = 9))]
+fn py_macd(values: &PyAny, fast: usize, slow: usize, signal: usize) -> PyResult<(std::vec::Vec<f64>, std::vec::Vec<f64>, std::vec::Vec<f64>)> {
+    Ok(macd(&series(values)?, fast, slow, signal))
+}
+
+#[pyfunction]
+#[pyo3(name = "bollinger", signature = (values, period = 20, k = 2.0))]
+fn py_bollinger(values: &PyAny, period: usize, k: f64) -> PyResult<(std::vec::Vec<f64>, std::vec::Vec<f64>, std::vec::Vec<f64>)> {
+    Ok(bollinger(&series(values)?, period, k))
+}
+
+#[pyfunction]
+#[pyo3(name = "atr", signature = (data, period = 14))]
+fn py_atr(data: &PyAny, period: usize) -> PyResult<std::vec::Vec<f64>> {
+    Ok(atr(&field(data, "h")?, &field(data, "l")?, &field(data, "c")?, period))
+}
+
+#[pyfunction]
+#[pyo3(name = "vwap")]
+fn py_vwap(data: &PyAny) -> PyResult<std::vec::Vec<f64>> {
+    Ok(vwap(&field(data, "h")?, &field(data, "l")?, &field(data, "c")?, &field(data, "v")?))
+}
+
+
+// Testing the indicators.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(result: &[f64], expected: &[f64]) {
+        assert_eq!(result.len(), expected.len());
+        for (r, e) in result.iter().zip(expected.iter()) {
+            assert!((r.is_nan() && e.is_nan()) || (r - e).abs() < 1e-9, "{:?} != {:?}", result, expected);
+        }
+    }
+
+    const NAN: f64 = f64::NAN;
+
+    #[test]
+    fn test_moving_averages() {
+        let values = [1.0, 2.0, 3.0, 4.0, 5.0];
+
+        assert_close(&sma(&values, 3), &[NAN, NAN, 2.0, 3.0, 4.0]);
+        assert_close(&ema(&values, 3), &[NAN, NAN, 2.0, 3.0, 4.0]);
+        assert_close(&ema(&[2.0, 4.0, 6.0, 2.0], 2), &[NAN, 3.0, 5.0, 3.0]);
+        assert_close(&sma(&values, 6), &[NAN; 5]);
+
+        let (line, signal, histogram) = macd(&values, 2, 3, 2);
+        assert_close(&line, &[NAN, NAN, 0.5, 0.5, 0.5]);
+        assert_close(&signal, &[NAN, NAN, NAN, 0.5, 0.5]);
+        assert_close(&histogram, &[NAN, NAN, NAN, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_oscillators() {
+        assert_close(&rsi(&[1.0, 2.0, 3.0, 4.0], 2), &[NAN, NAN, 100.0, 100.0]);
+        assert_close(&rsi(&[1.0, 2.0, 1.0, 2.0], 2), &[NAN, NAN, 50.0, 75.0]);
+
+        let (upper, middle, lower) = bollinger(&[1.0, 3.0, 1.0, 3.0], 2, 2.0);
+        assert_close(&upper, &[NAN, 4.0, 4.0, 4.0]);
+        assert_close(&middle, &[NAN, 2.0, 2.0, 2.0]);
+        assert_close(&lower, &[NAN, 0.0, 0.0, 0.0]);
+
+        let high = [3.0, 4.0, 6.0];
+        let low = [1.0, 2.0, 5.0];
+        let close = [2.0, 3.0, 5.5];
+        assert_close(&atr(&high, &low, &close, 2), &[NAN, 2.0, 2.5]);
+        assert_close(&vwap(&high, &low, &close, &[1.0, 0.0, 1.0]), &[2.0, 2.0, 3.75]);
+    }
+
+    #[test]
+    fn test_module() {
+        pyo3::prepare_freethreaded_python();
+
+        Python::with_gil(|py| {
+            register(py).unwrap();
+
+            let candles = (1..=5).map(|i| CandleStick {
+                timestamp: i,
+                open: i as f64,
+                close: i as f64,
+                high: i as f64 + 1.0,
+                low: i as f64 - 1.0,
+                volume: 1.0,
+                closed: true,
+            }).map(|c| Py::new(py, c).unwrap()).collect::<std::vec::Vec<Py<CandleStick>>>();
+
+            let locals = PyDict::new(py);
+            locals.set_item("data", PyList::new(py, candles)).unwrap();
+            py.run("from algonline import ta", None, Some(locals)).unwrap();
+
+            let result = py.eval("ta.sma(data, 3)[-1], ta.sma([1, 2, 3], 3)[-1], ta.vwap(data)[-1], ta.macd(data)[0][-1]", None, Some(locals)).unwrap();
+            let (sma_candles, sma_list, vwap, macd) = result.extract::<(f64, f64, f64, f64)>().unwrap();
+            assert_eq!((sma_candles, sma_list, vwap), (4.0, 2.0, 3.0));
+            assert!(macd.is_nan());
+
+            let dict = py.eval("ta.atr({'h': [3, 4, 6], 'l': [1, 2, 5], 'c': [2, 3, 5.5]}, 2)", None, Some(locals)).unwrap();
+            assert_close(&dict.extract::<std::vec::Vec<f64>>().unwrap(), &[NAN, 2.0, 2.5]);
+        });
+    }
+}
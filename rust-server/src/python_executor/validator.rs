@@ -0,0 +1,359 @@
+// Validation of the uploaded Python code. The code is parsed with the ast module of Python and
+// every node of the tree is checked:
+// - Only whitelisted node types are allowed, e.g no imports, classes, with-statements,...
+// - Only names defined in the code itself or whitelisted builtins and libraries can be used.
+//   Builtins and whitelisted names can't be assigned or deleted, so they can't be shadowed.
+// - Private and dunder attributes, and attributes giving access to files, frames or
+//   executing code are not allowed.
+// Each violation is reported with the line and column where it occurs.
+
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+// Node types which are allowed in the code.
+const ALLOWED_NODES: &[&str] = &[
+    // Module and statements.
+    "Module", "FunctionDef", "Return", "Assign", "AugAssign", "AnnAssign", "For", "While", "If",
+    "Break", "Continue", "Pass", "Expr", "Try", "ExceptHandler", "Raise", "Assert", "Delete",
+    // Expressions.
+    "BoolOp", "BinOp", "UnaryOp", "Compare", "Call", "IfExp", "Lambda", "NamedExpr", "Dict", "List",
+    "Tuple", "Set", "ListComp", "SetComp", "DictComp", "GeneratorExp", "comprehension", "Constant",
+    "Name", "Attribute", "Subscript", "Slice", "Starred", "keyword", "JoinedStr", "FormattedValue",
+    "arguments", "arg", "Index",
+    // Contexts.
+    "Load", "Store", "Del",
+    // Operators.
+    "And", "Or", "Add", "Sub", "Mult", "MatMult", "Div", "Mod", "Pow", "LShift", "RShift", "BitOr",
+    "BitXor", "BitAnd", "FloorDiv", "Invert", "Not", "UAdd", "USub", "Eq", "NotEq", "Lt", "LtE",
+    "Gt", "GtE", "Is", "IsNot", "In", "NotIn",
+];
+
+// Names which can be used without being defined in the code.
+const ALLOWED_NAMES: &[&str] = &[
+    // Libraries imported by the PyExecutor.
+    "math", "numpy", "pandas", "ta",
+    // Builtins.
+    "abs", "all", "any", "bool", "dict", "divmod", "enumerate", "filter", "float", "int",
+    "isinstance", "len", "list", "log", "map", "max", "min", "pow", "print", "range", "reversed", "round",
+    "set", "slice", "sorted", "str", "sum", "tuple", "zip", "True", "False", "None",
+    // Exceptions.
+    "Exception", "ArithmeticError", "ZeroDivisionError", "ValueError", "TypeError", "KeyError",
+    "IndexError", "OverflowError", "StopIteration", "AssertionError",
+];
+
+// Libraries imported by the header of the code, the only imports of the restricted builtins.
+const ALLOWED_IMPORTS: &[&str] = &["math", "numpy", "pandas", "algonline"];
+
+// Import of the restricted builtins, the code itself can't import since Import nodes and
+// __import__ are not allowed.
+const GUARDED_IMPORT: &str = r#"
+def guarded_import(name, globals=None, locals=None, fromlist=(), level=0):
+    if level != 0 or name not in allowed_imports:
+        raise ImportError("Import of " + name + " is not allowed.")
+    return __import__(name, globals, locals, fromlist, level)
+"#;
+
+// Attributes which can't be accessed. Private and dunder attributes are never allowed.
+const DENIED_ATTRIBUTES: &[&str] = &[
+    // Introspection of frames, functions and generators.
+    "f_globals", "f_locals", "f_builtins", "f_back", "f_code", "gi_frame", "gi_code", "cr_frame",
+    "cr_code", "ag_frame", "tb_frame", "tb_next", "func_globals", "mro",
+    // Formatting can access attributes by name, e.g "{0.__class__}".format(x).
+    "format", "format_map",
+    // Executing code.
+    "eval", "query", "system", "popen", "spawn", "exec",
+    // Files and modules.
+    "load", "loads", "save", "savez", "savetxt", "loadtxt", "genfromtxt", "fromfile", "tofile",
+    "memmap", "ctypeslib", "ctypes", "os", "sys", "lib", "testing",
+];
+
+// Prefixes of attributes reading or writing files, e.g pandas.read_csv and DataFrame.to_csv.
+const DENIED_ATTRIBUTE_PREFIXES: &[&str] = &["read_", "to_"];
+
+// Attributes starting with a denied prefix which don't touch files.
+const ALLOWED_PREFIXED_ATTRIBUTES: &[&str] = &["to_numpy", "to_list", "to_dict", "to_frame", "to_datetime", "to_numeric"];
+
+// A violation found in the code.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Violation {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "line {}, column {}: {}", self.line, self.column, self.message)
+    }
+}
+
+// Builtins the code runs with: only the allowed names, so builtins which pass the validator
+// e.g through a name it misses still can't be reached. log() has to be installed before.
+pub fn restricted_builtins<'py>(py: Python<'py>) -> PyResult<&'py PyDict> {
+    let builtins = py.import("builtins")?;
+
+    let restricted = PyDict::new(py);
+    for name in ALLOWED_NAMES {
+        if let Ok(value) = builtins.getattr(*name) {
+            restricted.set_item(*name, value)?;
+        }
+    }
+
+    let globals = PyDict::new(py);
+    globals.set_item("__builtins__", builtins)?;
+    globals.set_item("allowed_imports", ALLOWED_IMPORTS.to_vec())?;
+    py.run(GUARDED_IMPORT, Some(globals), None)?;
+    restricted.set_item("__import__", globals.get_item("guarded_import")?)?;
+
+    Ok(restricted)
+}
+
+// Validate the code. Returns all violations if the code is not allowed.
+pub fn validate(code: &str) -> Result<(), std::vec::Vec<Violation>> {
+    pyo3::prepare_freethreaded_python();
+
+    Python::with_gil(|py| {
+        match check(py, code) {
+            Ok(violations) if violations.is_empty() => Ok(()),
+            Ok(violations) => Err(violations),
+            Err(e) => Err(vec![Violation { line: 0, column: 0, message: format!("Could not validate code: {}", e) }]),
+        }
+    })
+}
+
+fn check(py: Python, code: &str) -> PyResult<std::vec::Vec<Violation>> {
+    let ast = py.import("ast")?;
+
+    // Parse the code. A syntax error is reported as violation.
+    let tree = match ast.call_method1("parse", (code,)) {
+        Ok(t) => t,
+        Err(e) => {
+            let value = e.value(py);
+            let line = value.getattr("lineno").and_then(|l| l.extract::<usize>()).unwrap_or(0);
+            let column = value.getattr("offset").and_then(|o| o.extract::<usize>()).unwrap_or(0);
+            let message = value.getattr("msg").and_then(|m| m.extract::<String>()).unwrap_or(e.to_string());
+
+            return Ok(vec![Violation { line, column, message: format!("Syntax error: {}", message) }]);
+        }
+    };
+
+    let nodes : std::vec::Vec<&PyAny> = ast.call_method1("walk", (tree,))?.iter()?.collect::<PyResult<_>>()?;
+
+    // Names defined by the code itself.
+    let mut defined = std::collections::HashSet::<String>::new();
+    for node in nodes.iter() {
+        match node_type(node)?.as_str() {
+            "Name" if node.getattr("ctx")?.get_type().name()? == "Store" => {
+                defined.insert(node.getattr("id")?.extract()?);
+            },
+            "FunctionDef" => { defined.insert(node.getattr("name")?.extract()?); },
+            "arg" => { defined.insert(node.getattr("arg")?.extract()?); },
+            "ExceptHandler" => {
+                if let Ok(name) = node.getattr("name")?.extract::<String>() {
+                    defined.insert(name);
+                }
+            },
+            _ => (),
+        }
+    }
+
+    // Names which can't be assigned or deleted.
+    let builtins : std::vec::Vec<String> = py.import("builtins")?.dir().extract()?;
+    let reserved = |name: &str| ALLOWED_NAMES.contains(&name) || builtins.iter().any(|b| b == name);
+
+    // Check every node, the children of a node which is not allowed are skipped.
+    let mut violations = std::vec::Vec::<Violation>::new();
+    let mut stack = vec![tree];
+    while let Some(node) = stack.pop() {
+        let node_type = node_type(node)?;
+        let line = node.getattr("lineno").and_then(|l| l.extract::<usize>()).unwrap_or(0);
+        let column = node.getattr("col_offset").and_then(|c| c.extract::<usize>()).unwrap_or(0);
+        let mut violation = |message: String| violations.push(Violation { line, column, message });
+
+        if !ALLOWED_NODES.contains(&node_type.as_str()) {
+            violation(format!("{} is not allowed.", node_type));
+            continue;
+        }
+
+        for child in ast.call_method1("iter_child_nodes", (node,))?.iter()? {
+            stack.push(child?);
+        }
+
+        match node_type.as_str() {
+            "Name" => {
+                let id : String = node.getattr("id")?.extract()?;
+                let ctx = node.getattr("ctx")?.get_type().name()?.to_string();
+                if id.starts_with('_') {
+                    violation(format!("Name \"{}\" is not allowed, names can't start with an underscore.", id));
+                } else if ctx != "Load" && reserved(&id) {
+                    violation(format!("Name \"{}\" is reserved and can't be assigned or deleted.", id));
+                } else if !defined.contains(&id) && !ALLOWED_NAMES.contains(&id.as_str()) {
+                    violation(format!("Name \"{}\" is not defined or not allowed.", id));
+                }
+            },
+            "FunctionDef" => {
+                let name : String = node.getattr("name")?.extract()?;
+                if name.starts_with('_') {
+                    violation(format!("Name \"{}\" is not allowed, names can't start with an underscore.", name));
+                } else if reserved(&name) {
+                    violation(format!("Name \"{}\" is reserved and can't be assigned or deleted.", name));
+                }
+                if node.getattr("decorator_list")?.is_true()? {
+                    violation("Decorators are not allowed.".into());
+                }
+            },
+            "arg" => {
+                let name : String = node.getattr("arg")?.extract()?;
+                if name.starts_with('_') {
+                    violation(format!("Name \"{}\" is not allowed, names can't start with an underscore.", name));
+                } else if reserved(&name) {
+                    violation(format!("Name \"{}\" is reserved and can't be assigned or deleted.", name));
+                }
+            },
+            "ExceptHandler" => {
+                if let Ok(name) = node.getattr("name")?.extract::<String>() {
+                    if reserved(&name) {
+                        violation(format!("Name \"{}\" is reserved and can't be assigned or deleted.", name));
+                    }
+                }
+            },
+            "Attribute" => {
+                let attr : String = node.getattr("attr")?.extract()?;
+                if !attribute_is_allowed(&attr) {
+                    violation(format!("Attribute \"{}\" is not allowed.", attr));
+                }
+            },
+            "Constant" => {
+                if let Ok(s) = node.getattr("value")?.extract::<String>() {
+                    if s.contains("__") {
+                        violation("Strings containing \"__\" are not allowed.".into());
+                    }
+                }
+            },
+            _ => (),
+        }
+    }
+
+    violations.sort_by_key(|v| (v.line, v.column));
+    Ok(violations)
+}
+
+fn node_type(node: &PyAny) -> PyResult<String> {
+    Ok(node.get_type().name()?.to_string())
+}
+
+fn attribute_is_allowed(attr: &str) -> bool {
+    if attr.starts_with('_') || DENIED_ATTRIBUTES.contains(&attr) {
+        return false;
+    }
+
+    if DENIED_ATTRIBUTE_PREFIXES.iter().any(|p| attr.starts_with(p)) {
+        return ALLOWED_PREFIXED_ATTRIBUTES.contains(&attr);
+    }
+
+    true
+}
+
+
+// Testing the validator.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allowed_code() {
+        let code = r#"
+def func(data, state):
+    threshold_file_count = 3
+    closes = [d.c for d in data]
+    avg = sum(closes) / len(closes) if closes else 0
+    try:
+        ratio = closes[-1] / avg
+    except ZeroDivisionError as e:
+        ratio = 0
+    state["readings"] = state.get("readings", 0) + 1
+    series = pandas.Series(closes).rolling(threshold_file_count).mean().to_list()
+    return math.floor(ratio) * numpy.sign(series[-1] or 0)
+"#;
+
+        assert_eq!(validate(code), Ok(()));
+    }
+
+    #[test]
+    fn test_escape_attempts() {
+        // Known ways to escape from the code, each has to be rejected.
+        let corpus = [
+            "import os",
+            "from os import system",
+            "__import__('os').system('ls')",
+            "eval('1 + 1')",
+            "exec('x = 1')",
+            "open('/etc/passwd').read()",
+            "getattr(data, 'c')",
+            "globals()['x'] = 1",
+            "vars()",
+            "compile('1', '', 'eval')",
+            "().__class__.__bases__[0].__subclasses__()",
+            "(lambda: 0).__globals__",
+            "'{0.__class__}'.format(1)",
+            "'{0.gi_frame}'.format(x for x in [])",
+            "(x for x in []).gi_frame.f_back.f_globals",
+            "numpy.load('file.npy')",
+            "numpy.fromfile('/etc/passwd')",
+            "pandas.read_csv('/etc/passwd')",
+            "pandas.DataFrame().to_csv('/tmp/x')",
+            "pandas.eval('1 + 1')",
+            "numpy.ctypeslib.load_library('libc', '/lib')",
+            "numpy.testing.os.system('ls')",
+            "math.__loader__",
+            "class A: pass",
+            "with x: pass",
+            "global x",
+            "async def f(): pass",
+            "def f():\n    yield 1",
+            "@decorator\ndef f(): pass",
+            "breakpoint()",
+            "type('A', (), {})",
+            "_private = 1",
+            "def func(data:\n    return 0",
+            "exec = 1\ndel exec\nexec('import os\\nos.getpid()')",
+            "x = open('/etc/passwd').read()\nopen = 0",
+            "def f(open):\n    return open",
+            "try:\n    pass\nexcept Exception as len:\n    pass",
+        ];
+
+        for code in corpus {
+            match validate(code) {
+                Err(violations) => assert!(!violations.is_empty()),
+                Ok(_) => panic!("Code was accepted: {}", code),
+            }
+        }
+    }
+
+    #[test]
+    fn test_restricted_builtins() {
+        pyo3::prepare_freethreaded_python();
+
+        Python::with_gil(|py| {
+            let globals = PyDict::new(py);
+            globals.set_item("__builtins__", restricted_builtins(py).unwrap()).unwrap();
+
+            // Allowed builtins and libraries work, everything else is not reachable.
+            assert!(py.run("import math\nx = max(1, math.floor(2.5))", Some(globals), None).is_ok());
+            assert_eq!(globals.get_item("x").unwrap().unwrap().extract::<i64>().unwrap(), 2);
+            assert!(py.run("open('/etc/passwd')", Some(globals), None).is_err());
+            assert!(py.run("exec('x = 1')", Some(globals), None).is_err());
+            assert!(py.run("import os", Some(globals), None).is_err());
+        });
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let code = "def func(data):\n    x = 1\n    return eval('x')";
+        let violations = validate(code).unwrap_err();
+
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].line, 3);
+        assert_eq!(violations[0].column, 11);
+    }
+}
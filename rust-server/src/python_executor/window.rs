@@ -0,0 +1,287 @@
+// Rolling window of the candles passed to func. At most max_history candles are kept. The
+// data passed to func depends on the data format of the algorithm:
+// - list: a list of CandleStick objects. The candles are kept in a ring buffer and the PyList
+//   passed to func is kept for the lifetime of the window and is updated with every new candle,
+//   instead of converting all candles to a new PyList on every execution.
+// - numpy: a dict with a numpy array per field, e.g data["c"] are the close prices.
+// - pandas: a DataFrame with the columns o, h, l, c, v indexed by the open time t.
+// The numpy arrays and the DataFrame are read-only views of the columns, see columns.rs.
+
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList};
+use std::collections::VecDeque;
+
+use crate::columns::{Column, Columns, VALUE_COLUMNS};
+use crate::CandleStick;
+
+// Format of the data passed to func.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DataFormat {
+    List,
+    Numpy,
+    Pandas,
+}
+
+impl std::str::FromStr for DataFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "list" => Ok(DataFormat::List),
+            "numpy" => Ok(DataFormat::Numpy),
+            "pandas" => Ok(DataFormat::Pandas),
+            _ => Err(format!("Unknown data format: {}", s)),
+        }
+    }
+}
+
+pub struct CandleWindow {
+    storage: Storage,
+}
+
+enum Storage {
+    List(CandleList),
+    Columns(Py<Columns>, DataFormat),
+}
+
+impl CandleWindow {
+    // Create a window from the initial data. Only the last max_history candles are kept.
+    pub fn new(data: std::vec::Vec<CandleStick>, max_history: usize, format: DataFormat) -> Result<Self, PyErr> {
+        let storage = match format {
+            DataFormat::List => Storage::List(CandleList::new(data, max_history)?),
+            _ => {
+                pyo3::prepare_freethreaded_python();
+                let columns = Python::with_gil(|py| Py::new(py, Columns::new(data, max_history)))?;
+                Storage::Columns(columns, format)
+            },
+        };
+
+        Ok(CandleWindow { storage })
+    }
+
+    // Add a candle. A candle with the same open time as the last candle is an update of the
+    // candle in progress and replaces it. Updates of older candles are ignored.
+    pub fn update(&mut self, py: Python, candle: CandleStick) -> Result<(), PyErr> {
+        match &mut self.storage {
+            Storage::List(list) => list.update(py, candle),
+            Storage::Columns(columns, _) => {
+                columns.borrow_mut(py).update(&candle);
+                Ok(())
+            },
+        }
+    }
+
+    // The data to pass to func.
+    pub fn data<'py>(&self, py: Python<'py>) -> Result<&'py PyAny, PyErr> {
+        let (columns, format) = match &self.storage {
+            Storage::List(list) => return Ok(list.list(py)),
+            Storage::Columns(columns, format) => (columns, format),
+        };
+
+        let numpy = py.import("numpy")?;
+        let array = |column: Column| numpy.call_method1("asarray", (Columns::view(columns, py, column)?,));
+
+        match format {
+            DataFormat::Pandas => {
+                let pandas = py.import("pandas")?;
+
+                let kwargs = PyDict::new(py);
+                kwargs.set_item("name", "t")?;
+                let index = pandas.call_method("Index", (array(Column::Timestamps)?,), Some(kwargs))?;
+
+                let kwargs = PyDict::new(py);
+                kwargs.set_item("columns", VALUE_COLUMNS.to_vec())?;
+                kwargs.set_item("index", index)?;
+                kwargs.set_item("copy", false)?;
+                pandas.call_method("DataFrame", (array(Column::Values)?,), Some(kwargs))
+            },
+            _ => {
+                let dict = PyDict::new(py);
+                dict.set_item("t", array(Column::Timestamps)?)?;
+                for (i, name) in VALUE_COLUMNS.iter().enumerate() {
+                    dict.set_item(name, array(Column::Value(i))?)?;
+                }
+                Ok(dict)
+            },
+        }
+    }
+}
+
+// Candles as a list of CandleStick objects.
+struct CandleList {
+    candles: VecDeque<Py<CandleStick>>,
+    list: Py<PyList>,
+    max_history: usize,
+}
+
+impl CandleList {
+    fn new(data: std::vec::Vec<CandleStick>, max_history: usize) -> Result<Self, PyErr> {
+        let max_history = max_history.max(1);
+        let skip = data.len().saturating_sub(max_history);
+
+        pyo3::prepare_freethreaded_python();
+
+        Python::with_gil(|py| {
+            let mut candles = VecDeque::with_capacity(max_history);
+            for candle in data.into_iter().skip(skip) {
+                candles.push_back(Py::new(py, candle)?);
+            }
+
+            let list = PyList::new(py, candles.iter()).into();
+
+            Ok(CandleList {
+                candles,
+                list,
+                max_history,
+            })
+        })
+    }
+
+    fn update(&mut self, py: Python, candle: CandleStick) -> Result<(), PyErr> {
+        let last = self.candles.back().map(|c| c.borrow(py).timestamp);
+
+        match last {
+            Some(t) if candle.timestamp == t => {
+                self.sync(py);
+
+                let candle = Py::new(py, candle)?;
+                let list = self.list.as_ref(py);
+                list.set_item(list.len() - 1, candle.clone_ref(py))?;
+                *self.candles.back_mut().unwrap() = candle;
+
+                Ok(())
+            },
+            Some(t) if candle.timestamp < t => Ok(()),
+            _ => self.push(py, candle),
+        }
+    }
+
+    // Add a new candle. The oldest candle is dropped when the window is full.
+    fn push(&mut self, py: Python, candle: CandleStick) -> Result<(), PyErr> {
+        self.sync(py);
+
+        let candle = Py::new(py, candle)?;
+        let list = self.list.as_ref(py);
+
+        list.append(candle.clone_ref(py))?;
+        self.candles.push_back(candle);
+
+        if self.candles.len() > self.max_history {
+            self.candles.pop_front();
+            list.del_item(0)?;
+        }
+
+        Ok(())
+    }
+
+    fn list<'py>(&self, py: Python<'py>) -> &'py PyList {
+        self.sync(py);
+        self.list.clone_ref(py).into_ref(py)
+    }
+
+    // func may have modified the list, e.g with data.pop(). Rebuild it from the ring buffer
+    // when it doesn't match anymore.
+    fn sync(&self, py: Python) {
+        let list = self.list.as_ref(py);
+
+        let same = |index: usize, candle: Option<&Py<CandleStick>>| {
+            match (list.get_item(index), candle) {
+                (Ok(item), Some(candle)) => item.as_ptr() == candle.as_ptr(),
+                _ => false,
+            }
+        };
+
+        let in_sync = list.len() == self.candles.len()
+            && (self.candles.is_empty() || (same(0, self.candles.front()) && same(list.len() - 1, self.candles.back())));
+
+        if !in_sync {
+            let candles = PyList::new(py, self.candles.iter());
+            list.set_slice(0, list.len(), candles).unwrap_or_default();
+        }
+    }
+}
+
+
+// Testing the window.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candle(timestamp: u64) -> CandleStick {
+        CandleStick {
+            timestamp,
+            open: 1.0,
+            close: 1.0,
+            high: 1.0,
+            low: 1.0,
+            volume: 1.0,
+            closed: true,
+        }
+    }
+
+    fn list<'py>(py: Python<'py>, window: &CandleWindow) -> &'py PyList {
+        window.data(py).unwrap().downcast().unwrap()
+    }
+
+    fn timestamps(window: &CandleWindow) -> std::vec::Vec<u64> {
+        Python::with_gil(|py| {
+            list(py, window).iter().map(|c| c.extract::<CandleStick>().unwrap().timestamp).collect()
+        })
+    }
+
+    #[test]
+    fn test_window() {
+        let mut window = CandleWindow::new((0..5).map(candle).collect(), 3, DataFormat::List).unwrap();
+        assert_eq!(timestamps(&window), vec![2, 3, 4]);
+
+        Python::with_gil(|py| {
+            let pointer = list(py, &window).as_ptr();
+            window.update(py, candle(5)).unwrap();
+            window.update(py, candle(6)).unwrap();
+
+            // The same list is updated.
+            assert_eq!(list(py, &window).as_ptr(), pointer);
+        });
+
+        assert_eq!(timestamps(&window), vec![4, 5, 6]);
+    }
+
+    #[test]
+    fn test_modified_list() {
+        let mut window = CandleWindow::new((0..3).map(candle).collect(), 3, DataFormat::List).unwrap();
+
+        // Modifications of func are undone.
+        Python::with_gil(|py| {
+            list(py, &window).call_method0("pop").unwrap();
+            list(py, &window).insert(0, 42).unwrap();
+        });
+        assert_eq!(timestamps(&window), vec![0, 1, 2]);
+
+        Python::with_gil(|py| {
+            list(py, &window).call_method0("clear").unwrap();
+            window.update(py, candle(3)).unwrap();
+        });
+        assert_eq!(timestamps(&window), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_update() {
+        let mut window = CandleWindow::new((0..2).map(candle).collect(), 3, DataFormat::List).unwrap();
+
+        let closes = |window: &CandleWindow| Python::with_gil(|py| {
+            list(py, window).iter().map(|c| c.extract::<CandleStick>().unwrap().close).collect::<std::vec::Vec<f64>>()
+        });
+
+        // Updates of the candle in progress replace it, updates of older candles are ignored.
+        Python::with_gil(|py| {
+            window.update(py, CandleStick { close: 2.0, closed: false, ..candle(2) }).unwrap();
+            window.update(py, CandleStick { close: 3.0, closed: true, ..candle(2) }).unwrap();
+            window.update(py, CandleStick { close: 4.0, ..candle(1) }).unwrap();
+        });
+        assert_eq!(timestamps(&window), vec![0, 1, 2]);
+        assert_eq!(closes(&window), vec![1.0, 1.0, 3.0]);
+
+        Python::with_gil(|py| window.update(py, candle(3)).unwrap());
+        assert_eq!(timestamps(&window), vec![1, 2, 3]);
+    }
+}
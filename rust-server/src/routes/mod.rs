@@ -1,5 +1,4 @@
 use super::*;
-use async_recursion::async_recursion;
 
 // Struct Routes holds the following:
 // - A method `goto` to find and execute a the fn pointer to a route for the matching Http-method
@@ -78,76 +77,36 @@ impl Routes {
             }
         }
        
-        // Retrieve algorithm and get starttime of first run.
-        let algorithm = TradeAlgorithm::get(algo_id.to_string(), psql.clone()).await.unwrap();
-        let algorithm = Box::new(algorithm);
-        let current_time = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis();
-        let starttime = current_time - algorithm.prepend_data as u128;
-       
-        // Recursive function: If we receive an error in one of the threads we run
-        // the function again. This way the websocket connection is remade.
-        #[async_recursion]
-        async fn start_algorithm_recursive(algorithm: Box<TradeAlgorithm>, starttime: u128, psql: Psql, api: Api) -> Result<(), String> {
-            // Make a transmitter and receiver. The receiver is passed to the algorithm when started
-            // so it can receive the data from the transmitter which we pass to the exchange API.
-            let (tx, rx) = mpsc::channel::<CandleStick>(10);
-            let tx = Arc::new(Mutex::new(tx));
-            let rx = Arc::new(Mutex::new(rx));
-           
-            // Make websocket stream to API endpoint for making orders.
-            let url = url::Url::parse(api.get_urls("ws_api_url").unwrap()).unwrap();
-            let (ws_stream, _) = connect_async(url.clone()).await.expect("Failed to connect");
-            let (send, _) = ws_stream.split();
-            
-            let send = Arc::new(Mutex::new(send));
-
-            // Retrieve algorithm by id and start.
-            let interval = algorithm.interval.to_string();
-            let (process_handle, thread_a_handle, thread_b_handle) = match algorithm.clone().start(psql.clone(), rx, api.clone(), send, starttime).await {
-                Ok((ph, th_a, th_b)) => (ph, th_a, th_b),
-                Err(e) => {
-                    return Err(format!("Error starting algortithm: {}", e));
-                }
-            };
+        // Retrieve algorithm.
+        let algorithm = match TradeAlgorithm::get(algo_id.to_string(), psql.clone()).await {
+            Ok(algo) => algo,
+            Err(_) => {
+                return Ok(Routes::not_found().await);
+            }
+        };
 
-            // Start websocket API of exchange and pass the transmitter as parameter
-            // so the incoming data is send to the algorithm.
-            let api_clone = api.clone();
-            tokio::spawn(async move {
-                if let Err(e) = api_clone.ws_kline(tx, interval).await {
-                    eprintln!("{}", e);
-                }
-            }); 
-
-
-            // Add process handle to process-handle list so we can stop algorithm later.
-            let mut shared_process_handles = tradealgorithm::PROCESS_HANDLES.lock().await;
-            shared_process_handles.insert(algorithm.id.to_string(), process_handle);
-
-            tokio::spawn(async move {
-                if let Err(_) = tokio::try_join!(thread_a_handle, thread_b_handle) {
-                    if let Err(e) = algorithm.stop().await {
-                        match e  {
-                            tradealgorithm::Error::AlgorithmError(_) => {
-                                eprintln!("\x1b[31m[Error] {} - Exiting.\x1b[0m", algorithm.id);
-                                panic!();
-                            },
-                            _ => (),
-                        }
-                    }
-                    
-                    eprintln!("\x1b[31m[Error] {} - A thread panicked. Attempting to restart websocket...\x1b[0m]", algorithm.id);
-                    start_algorithm_recursive(algorithm, starttime, psql.clone(), api.clone()).await.unwrap();
-                    tokio::time::sleep(tokio::time::Duration::from_millis(10000)).await;
-                }
-            });
+        // An algorithm can trade on another exchange than the one selected by the user.
+        let api = match algorithm.exchange {
+            Some(exchange) => {
+                let session_token = req.headers.get("session_token").cloned().unwrap_or_default();
+                api::authenticated_instance(exchange, &session_token, psql.clone()).await?
+            },
+            None => api,
+        };
 
-            Ok(())
+        // The supervisor starts the PyExecutor and restarts it when it stops.
+        if let Err(e) = supervisor::start(algorithm, psql, api).await {
+            return Ok(http::HttpResponse {
+                status: 200,
+                headers: vec![
+                    ("Content-Type".into(), "text/plain".into()),
+                ],
+                body: match e {
+                    tradealgorithm::Error::AlgorithmError(msg) | tradealgorithm::Error::StateError(msg) => msg,
+                    _ => "Algorithm could not be started.".into(),
+                },
+            });
         }
-
-        tokio::spawn(async move {
-            start_algorithm_recursive(algorithm, starttime, psql, api).await.expect("Error starting algorithm.");
-        });
         
         // Forge and return response.
         let response = http::HttpResponse {
@@ -191,9 +150,14 @@ impl Routes {
         }
         
         // Retrieve algorithm.
-        let algorithm = TradeAlgorithm::get(algo_id.to_string(), psql.clone()).await.unwrap();
+        let algorithm = match TradeAlgorithm::get(algo_id.to_string(), psql.clone()).await {
+            Ok(algo) => algo,
+            Err(_) => {
+                return Ok(Routes::not_found().await);
+            }
+        };
         
-        let response = match algorithm.stop().await {
+        let response = match algorithm.stop(psql).await {
             Ok(_) => {
                 http::HttpResponse {
                     status: 200,
@@ -217,6 +181,117 @@ impl Routes {
         Ok(response)
     }
 
+    // Pause algorithm. The PyExecutor keeps running with its candles but no orders are placed
+    // until the algorithm is resumed.
+    pub async fn pause_algorithm(&self, req: http::Http, psql: Psql, _api: Api) -> Result<http::HttpResponse, http::Error> {
+        // Retrieve algorithm ID from URL-paremeter.
+        let algo_id = match req.params.get("id").cloned() {
+            Some(id) => id,
+            None => {
+                return Ok(Routes::not_found().await);
+            }
+        };
+        
+        // Check if algorithm belongs to user doing request.
+        match req.headers.get("session_token") {
+            Some(token) => {
+                match http::validate_session_token(token, http::DBTable::Algorithm(&*algo_id), psql.clone()).await {
+                    Ok(v) => {
+                        if !v {
+                            return Ok(Routes::unauthorized().await);
+                        }
+                    },
+                    Err(_) => {
+                        return Ok(Routes::internal_server_error().await);
+                    }
+                }
+            },
+            None => {
+                return Ok(Routes::unauthorized().await);
+            }
+        }
+        
+        // Retrieve algorithm.
+        let algorithm = TradeAlgorithm::get(algo_id, psql.clone()).await?;
+
+        match algorithm.pause(psql).await {
+            Ok(_) => {
+                Ok(http::HttpResponse {
+                    status: 200,
+                    headers: vec![
+                        ("Content-Type".into(), "text/plain".into()),
+                    ],
+                    body: "Algorithm paused.".into(),
+                })
+            },
+            Err(tradealgorithm::Error::StateError(msg)) => {
+                Ok(http::HttpResponse {
+                    status: 409,
+                    headers: vec![
+                        ("Content-Type".into(), "text/plain".into()),
+                    ],
+                    body: msg,
+                })
+            },
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // Resume a paused algorithm.
+    pub async fn resume_algorithm(&self, req: http::Http, psql: Psql, _api: Api) -> Result<http::HttpResponse, http::Error> {
+        // Retrieve algorithm ID from URL-paremeter.
+        let algo_id = match req.params.get("id").cloned() {
+            Some(id) => id,
+            None => {
+                return Ok(Routes::not_found().await);
+            }
+        };
+        
+        // Check if algorithm belongs to user doing request.
+        match req.headers.get("session_token") {
+            Some(token) => {
+                match http::validate_session_token(token, http::DBTable::Algorithm(&*algo_id), psql.clone()).await {
+                    Ok(v) => {
+                        if !v {
+                            return Ok(Routes::unauthorized().await);
+                        }
+                    },
+                    Err(_) => {
+                        return Ok(Routes::internal_server_error().await);
+                    }
+                }
+            },
+            None => {
+                return Ok(Routes::unauthorized().await);
+            }
+        }
+        
+        // Retrieve algorithm.
+        let algorithm = TradeAlgorithm::get(algo_id, psql.clone()).await?;
+
+        match algorithm.resume(psql).await {
+            Ok(_) => {
+                Ok(http::HttpResponse {
+                    status: 200,
+                    headers: vec![
+                        ("Content-Type".into(), "text/plain".into()),
+                    ],
+                    body: "Algorithm resumed.".into(),
+                })
+            },
+            Err(tradealgorithm::Error::StateError(msg)) => {
+                Ok(http::HttpResponse {
+                    status: 409,
+                    headers: vec![
+                        ("Content-Type".into(), "text/plain".into()),
+                    ],
+                    body: msg,
+                })
+            },
+            Err(e) => Err(e.into()),
+        }
+    }
+
     // Get properties of an algorithm like id, description, start_funds,...
     pub async fn get_algorithm(&self, req: http::Http, psql: Psql, api: Api) -> Result<http::HttpResponse, http::Error> {
 
@@ -261,17 +336,31 @@ impl Routes {
         struct Data<'a> {
             id: &'a str,
             description: &'a str,
+            symbol: String,
             start_funds: f64,
             current_funds: f64,
             is_running: bool,
+            state: lifecycle::State,
+            params: &'a serde_json::Value,
+            max_history: i32,
+            run_on_close: bool,
+            data_format: &'a str,
+            last_error: Option<serde_json::Value>,
         }
 
         let data = Data {
             id: &algorithm.id,
             description: &algorithm.description,
+            symbol: algorithm.symbol.to_string(),
             start_funds: algorithm.start_funds,
-            current_funds: algorithm.get_current_balance(psql, api).await?,
-            is_running: algorithm.active().await,
+            current_funds: algorithm.get_current_balance(psql.clone(), api).await?,
+            is_running: algorithm.active(),
+            state: algorithm.state,
+            params: &algorithm.params,
+            max_history: algorithm.max_history,
+            run_on_close: algorithm.run_on_close,
+            data_format: &algorithm.data_format,
+            last_error: algorithm.get_last_error(psql).await?,
         };
         
         Ok(http::HttpResponse{
@@ -319,16 +408,19 @@ impl Routes {
        };
 
         // Define the expected data from the POST-request/
-        #[derive(Serialize, Deserialize)]
+        #[derive(Deserialize)]
         struct Data<'a> {
             id: &'a str,
             description: &'a str,
+            symbol: Option<&'a str>,
             start_funds: f64,
             first_btc_order: f64,
             interval: &'a str,
             run_every_sec: i32,
             prepend_data: &'a str,
             code: &'a str,
+            #[serde(flatten)]
+            settings: tradealgorithm::AlgorithmSettings,
         }
 
         // Make Data object from POST request body. Return error 400
@@ -358,6 +450,48 @@ impl Routes {
             _ => 0,
         };
 
+        // Symbol this algorithm trades, its assets come from the exchange. Defaults to BTCUSDT.
+        let mut settings = data.settings;
+        settings.symbol = match data.symbol {
+            Some(s) => match api::find_symbol(&api, s).await {
+                Ok(s) => s,
+                Err(e) => {
+                    eprintln!("{}", e);
+
+                    return Ok(http::HttpResponse {
+                        status: 400,
+                        headers: vec![
+                            ("Content-Type".into(), "text/plain".into()),
+                        ],
+                        body: format!("Invalid symbol {}.", s),
+                    });
+                }
+            },
+            None => api::Symbol::default(),
+        };
+
+        // User-defined parameters passed to the Python function.
+        if !settings.params.is_object() {
+            return Ok(http::HttpResponse {
+                status: 400,
+                headers: vec![
+                    ("Content-Type".into(), "text/plain".into()),
+                ],
+                body: "Params must be a JSON object.".into(),
+            });
+        }
+
+        // Maximum amount of candles passed to the Python function.
+        if settings.max_history < 1 || settings.max_history > *config::MAX_HISTORY_LIMIT {
+            return Ok(http::HttpResponse {
+                status: 400,
+                headers: vec![
+                    ("Content-Type".into(), "text/plain".into()),
+                ],
+                body: format!("Max history must be between 1 and {}.", *config::MAX_HISTORY_LIMIT),
+            });
+        }
+
         // Create algorithm.
         let id = data.id.to_string().replace(" ", "_").replace("-", "_").to_lowercase();
         let algorithm = 
@@ -368,6 +502,7 @@ impl Routes {
                 data.interval.into(), 
                 data.run_every_sec,
                 prepend_data,
+                settings,
                 user_id,
                 psql.clone()
             ).await {
@@ -405,7 +540,12 @@ impl Routes {
             return Err(http::Error::TradeAlgorithmError(format!("Error writing to file: {}", e)));
         }
 
-        // Execute first BTC order.
+        // Execute first BTC order on the exchange of the algorithm.
+        let api = match algorithm.exchange {
+            Some(exchange) => api::authenticated_instance(exchange, session_token, psql.clone()).await?,
+            None => api,
+        };
+
         if data.first_btc_order > 0f64 {
             if let Err(e) = algorithm.first_btc_order(data.first_btc_order, psql.clone(), api).await {
                 eprintln!("{}", e);
@@ -458,7 +598,7 @@ impl Routes {
         let algorithm = TradeAlgorithm::get(algo_id, psql.clone()).await?;
 
         // Check if algorithm is running. 
-        if algorithm.active().await {
+        if algorithm.active() {
             return Ok(http::HttpResponse{
                 status: 409,
                 headers: vec![
@@ -514,7 +654,7 @@ impl Routes {
         let algorithm = TradeAlgorithm::get(algo_id, psql.clone()).await?;
 
         // Check if algorithm is running. 
-        if algorithm.active().await {
+        if algorithm.active() {
             return Ok(http::HttpResponse{
                 status: 409,
                 headers: vec![
@@ -545,14 +685,14 @@ impl Routes {
         
         // Get current USDT and BTC balance.
         let (usdt, btc) = match api.account_balance().await {
-            Ok((a, b)) => (a, b),
+            Ok(b) => (api::balance_of(&b, "USDT"), api::balance_of(&b, "BTC")),
             Err(e) => {
                 return Err(http::Error::RequestError(format!("Couldn't retrieve balances {}", e)));
             }
         };
 
         // Calculate total balance.
-        let btc_price = api.get_btc_price().await?;
+        let btc_price = api.get_price(&api::Symbol::default()).await?;
         let btc_in_usdt = btc * btc_price;
         let total = usdt + btc_in_usdt;
 
@@ -621,8 +761,8 @@ impl Routes {
                     let algorithm = TradeAlgorithm::get(row.get("id"), psql.clone()).await?;
                    
                     // Stop algorithm if it's still running.
-                    if algorithm.active().await {
-                        algorithm.clone().stop().await?;
+                    if algorithm.active() {
+                        algorithm.clone().stop(psql.clone()).await?;
                     }
 
                     // Delete algorithm.
@@ -635,10 +775,11 @@ impl Routes {
         }
        
         // Get current USDT and BTC balance.
-        let (usdt, btc) = api.account_balance().await?;
+        let balances = api.account_balance().await?;
+        let (usdt, btc) = (api::balance_of(&balances, "USDT"), api::balance_of(&balances, "BTC"));
 
         // Calculate total start funds.
-        let btc_price = api.get_btc_price().await?;
+        let btc_price = api.get_price(&api::Symbol::default()).await?;
         let btc_in_usdt = btc * btc_price;
         let total = usdt + btc_in_usdt;
 
@@ -673,9 +814,67 @@ impl Routes {
     }
 
 
+    // Select the exchange the user trades on. Algorithms without their own exchange use this
+    // exchange as well.
+    pub async fn set_user_exchange(&self, req: http::Http, psql: Psql, _api: Api) -> Result<http::HttpResponse, http::Error> {
+
+        // Retrieve session token if set.
+        let session_token = match req.headers.get("session_token") {
+            Some(token) => token,
+            None => {
+                return Ok(Routes::unauthorized().await);
+            }
+        };
+
+        #[derive(Deserialize)]
+        struct Data<'a> {
+            exchange: &'a str,
+        }
+
+        // Make Data object from PUT request body. Return error 400
+        // if sent data is malformed.
+        let exchange = match serde_json::from_str::<Data>(&*req.body).map(|d| d.exchange.parse::<api::ExchangeKind>()) {
+            Ok(Ok(e)) => e,
+            _ => {
+                return Ok(http::HttpResponse {
+                    status: 400,
+                    headers: vec![
+                        ("Content-Type".into(), "text/plain".into()),
+                    ],
+                    body: "Invalid exchange given.".into(),
+                });
+            }
+        };
+
+        let query = psql.lock().await
+            .query("
+                UPDATE
+                    users
+                SET
+                    exchange = $1
+                WHERE
+                    session_token = $2
+             ", &[&exchange.to_string(), &session_token]).await;
+
+        match query {
+            Ok(_) => (),
+            Err(e) => {
+                return Err(http::Error::DatabaseError(format!("{}", e)));
+            }
+        }
+
+        Ok(http::HttpResponse{
+            status: 200,
+            headers: vec![
+                ("Content-Type".into(), "text/plain".into()),
+            ],
+            body: format!("Exchange set to {}.", exchange),
+        })
+    }
+
     pub async fn trade_history(&self, _req: http::Http, _psql: Psql, api: Api) -> Result<http::HttpResponse, http::Error> {
     
-        api.trade_history().await?;
+        api.trade_history(&api::Symbol::default()).await?;
         Ok(http::HttpResponse{
             status: 200,
             headers: vec![
@@ -686,7 +885,7 @@ impl Routes {
     }
     
     pub async fn get_btc_price(&self, _req: http::Http, _psql: Psql, api: Api) -> Result<http::HttpResponse, http::Error> {
-        api.get_btc_price().await?;
+        api.get_price(&api::Symbol::default()).await?;
         Ok(http::HttpResponse{
             status: 200,
             headers: vec![
@@ -842,10 +1041,11 @@ impl Routes {
 
     }
     
-    // Get code of algorithm.
-    pub async fn get_algorithm_code(&self, req: http::Http, psql: Psql, _api: Api) -> Result<http::HttpResponse, http::Error> {
-        
-        // Retrieve algorithm ID from URL-paremeter.
+    // Get the output of the Python code of an algorithm, 100 lines at a time. The optional
+    // before parameter is the id of the oldest line already retrieved.
+    pub async fn get_algorithm_logs(&self, req: http::Http, psql: Psql, _api: Api) -> Result<http::HttpResponse, http::Error> {
+
+        // Retrieve all parameters from url.
         let algo_id = match req.params.get("id").cloned() {
             Some(id) => id,
             None => {
@@ -853,6 +1053,24 @@ impl Routes {
             }
         };
 
+        let before = match req.params.get("before") {
+            Some(b) => {
+                match b.parse::<i32>() {
+                    Ok(b) => Some(b),
+                    Err(_) => {
+                        return Ok(http::HttpResponse {
+                            status: 400,
+                            headers: vec![
+                                ("Content-Type".into(), "text/plain".into()),
+                            ],
+                            body: "before must be the id of a log line.".into(),
+                        });
+                    }
+                }
+            },
+            None => None,
+        };
+
         // Check if algorithm belongs to user doing request.
         match req.headers.get("session_token") {
             Some(token) => {
@@ -871,69 +1089,498 @@ impl Routes {
                 return Ok(Routes::unauthorized().await);
             }
         }
-                    
+
         // Retrieve algorithm.
         let algorithm = TradeAlgorithm::get(algo_id, psql.clone()).await?;
 
-        // Retrieve algorithm code and encode in Base64.
-        let algorithm_code = algorithm.get_code()?;
-        let mut code = std::vec::Vec::new();
-        let b64_engine = base64::engine::GeneralPurpose::new(&base64::alphabet::STANDARD, base64::engine::general_purpose::PAD);
-        let mut b64_encoder = base64::write::EncoderWriter::new(&mut code, &b64_engine);
-
-
-        if let Err(e) = b64_encoder.write_all(algorithm_code.as_bytes()) {
-            eprintln!("{}", e);
-            algorithm.delete(psql).await?;
-            return Err(http::Error::ParseError(format!("Error parsing base64 {}", e)));
-        }
-
-        drop(b64_encoder);
+        // Retrieve algorithm logs.
+        let data = algorithm.get_logs(psql, before).await?;
 
         Ok(http::HttpResponse{
             status: 200,
             headers: vec![
-                ("Content-Type".into(), "text/plain".into()),
+                ("Content-Type".into(), "application/json".into()),
             ],
-            body: String::from_utf8(code)?,
+            body: data.to_string(),
         })
+
     }
-    
-    pub async fn get_klines(&self, req: http::Http, _psql: Psql, api: Api) -> Result<http::HttpResponse, http::Error> {
 
-        // Retrieve all required parameters.
-        let interval = match req.params.get("interval").cloned() {
-            Some(int) => int,
-            None => "1s".into(),
-        };
-        
-        let amount = match req.params.get("amount").cloned() {
-            Some(amt) => amt.parse::<u32>()?,
-            None => 15,
-        };
+    // Get the transitions between the states of an algorithm with their time and reason,
+    // newest first.
+    pub async fn get_algorithm_events(&self, req: http::Http, psql: Psql, _api: Api) -> Result<http::HttpResponse, http::Error> {
 
-        // Convert selected interval to matching amount in ms.
-        let ms = match &*interval {
-            "1s" => 0,
-            "1m" => 60000,
-            "5m" => 60000 * 5,
-            "1h" => 60000 * 60,
-            "1d" => 60000 * 60 * 24,
-            _ => {
-                return Ok(Routes::internal_server_error().await);
+        // Retrieve algorithm ID from URL-paremeter.
+        let algo_id = match req.params.get("id").cloned() {
+            Some(id) => id,
+            None => {
+                return Ok(Routes::not_found().await);
             }
         };
 
-        // Set current time as endTime so we get all the data until now.
-        let endtime = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis();
-
-        // Set startTime to the current time minus the amount of ms of the interval * the amount
-        // of requested datapoints.
-        let starttime = endtime - (amount as u128 * ms);
-        
+        // Check if algorithm belongs to user doing request.
+        match req.headers.get("session_token") {
+            Some(token) => {
+                match http::validate_session_token(token, http::DBTable::Algorithm(&*algo_id), psql.clone()).await {
+                    Ok(v) => {
+                        if !v {
+                            return Ok(Routes::unauthorized().await);
+                        }
+                    },
+                    Err(_) => {
+                        return Ok(Routes::internal_server_error().await);
+                    }
+                }
+            },
+            None => {
+                return Ok(Routes::unauthorized().await);
+            }
+        }
+
+        // Retrieve algorithm.
+        let algorithm = TradeAlgorithm::get(algo_id, psql.clone()).await?;
+
+        // Retrieve algorithm events.
+        let data = algorithm.get_events(psql).await?;
+
+        Ok(http::HttpResponse{
+            status: 200,
+            headers: vec![
+                ("Content-Type".into(), "application/json".into()),
+            ],
+            body: data.to_string(),
+        })
+
+    }
+
+    // Get the open orders of an algorithm, newest first.
+    pub async fn get_algorithm_orders(&self, req: http::Http, psql: Psql, _api: Api) -> Result<http::HttpResponse, http::Error> {
+
+        // Retrieve algorithm ID from URL-paremeter.
+        let algo_id = match req.params.get("id").cloned() {
+            Some(id) => id,
+            None => {
+                return Ok(Routes::not_found().await);
+            }
+        };
+
+        // Check if algorithm belongs to user doing request.
+        match req.headers.get("session_token") {
+            Some(token) => {
+                match http::validate_session_token(token, http::DBTable::Algorithm(&*algo_id), psql.clone()).await {
+                    Ok(v) => {
+                        if !v {
+                            return Ok(Routes::unauthorized().await);
+                        }
+                    },
+                    Err(_) => {
+                        return Ok(Routes::internal_server_error().await);
+                    }
+                }
+            },
+            None => {
+                return Ok(Routes::unauthorized().await);
+            }
+        }
+
+        // Retrieve open orders.
+        let data = orders::open(&algo_id, psql).await?;
+
+        Ok(http::HttpResponse{
+            status: 200,
+            headers: vec![
+                ("Content-Type".into(), "application/json".into()),
+            ],
+            body: serde_json::json!(data).to_string(),
+        })
+    }
+
+    // Get the positions of an algorithm which are watched for their bracket, newest first.
+    pub async fn get_algorithm_positions(&self, req: http::Http, psql: Psql, _api: Api) -> Result<http::HttpResponse, http::Error> {
+
+        // Retrieve algorithm ID from URL-paremeter.
+        let algo_id = match req.params.get("id").cloned() {
+            Some(id) => id,
+            None => {
+                return Ok(Routes::not_found().await);
+            }
+        };
+
+        // Check if algorithm belongs to user doing request.
+        match req.headers.get("session_token") {
+            Some(token) => {
+                match http::validate_session_token(token, http::DBTable::Algorithm(&*algo_id), psql.clone()).await {
+                    Ok(v) => {
+                        if !v {
+                            return Ok(Routes::unauthorized().await);
+                        }
+                    },
+                    Err(_) => {
+                        return Ok(Routes::internal_server_error().await);
+                    }
+                }
+            },
+            None => {
+                return Ok(Routes::unauthorized().await);
+            }
+        }
+
+        // Retrieve watched positions.
+        let data = ordermanager::get_positions(&algo_id, psql).await?;
+
+        Ok(http::HttpResponse{
+            status: 200,
+            headers: vec![
+                ("Content-Type".into(), "application/json".into()),
+            ],
+            body: data.to_string(),
+        })
+    }
+
+    // Cancel an open order of an algorithm.
+    pub async fn cancel_algorithm_order(&self, req: http::Http, psql: Psql, api: Api) -> Result<http::HttpResponse, http::Error> {
+
+        // Retrieve algorithm ID from URL-paremeter.
+        let algo_id = match req.params.get("id").cloned() {
+            Some(id) => id,
+            None => {
+                return Ok(Routes::not_found().await);
+            }
+        };
+
+        // Check if algorithm belongs to user doing request.
+        match req.headers.get("session_token") {
+            Some(token) => {
+                match http::validate_session_token(token, http::DBTable::Algorithm(&*algo_id), psql.clone()).await {
+                    Ok(v) => {
+                        if !v {
+                            return Ok(Routes::unauthorized().await);
+                        }
+                    },
+                    Err(_) => {
+                        return Ok(Routes::internal_server_error().await);
+                    }
+                }
+            },
+            None => {
+                return Ok(Routes::unauthorized().await);
+            }
+        }
+
+        let client_order_id = match req.params.get("client_order_id").cloned() {
+            Some(id) => id,
+            None => {
+                return Ok(Routes::not_found().await);
+            }
+        };
+
+        // Retrieve algorithm.
+        let algorithm = TradeAlgorithm::get(algo_id.to_string(), psql.clone()).await?;
+
+        // An algorithm can trade on another exchange than the one selected by the user.
+        let api = match algorithm.exchange {
+            Some(exchange) => {
+                let session_token = req.headers.get("session_token").cloned().unwrap_or_default();
+                api::authenticated_instance(exchange, &session_token, psql.clone()).await?
+            },
+            None => api,
+        };
+
+        match orders::cancel(&algo_id, &client_order_id, api, psql).await {
+            Ok(Some(status)) => {
+                Ok(http::HttpResponse {
+                    status: 200,
+                    headers: vec![
+                        ("Content-Type".into(), "text/plain".into()),
+                    ],
+                    body: status.to_string(),
+                })
+            },
+            Ok(None) => Ok(Routes::not_found().await),
+            Err(tradealgorithm::Error::APIError(msg)) => {
+                Ok(http::HttpResponse {
+                    status: 400,
+                    headers: vec![
+                        ("Content-Type".into(), "text/plain".into()),
+                    ],
+                    body: msg,
+                })
+            },
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // Backtest algorithm over historical data. The body holds the start and end of the range
+    // as timestamps in ms and the interval of the candles.
+    pub async fn backtest_algorithm(&self, req: http::Http, psql: Psql, api: Api) -> Result<http::HttpResponse, http::Error> {
+
+        // Retrieve algorithm ID from URL-paremeter.
+        let algo_id = match req.params.get("id").cloned() {
+            Some(id) => id,
+            None => {
+                return Ok(Routes::not_found().await);
+            }
+        };
+
+        // Check if algorithm belongs to user doing request.
+        match req.headers.get("session_token") {
+            Some(token) => {
+                match http::validate_session_token(token, http::DBTable::Algorithm(&*algo_id), psql.clone()).await {
+                    Ok(v) => {
+                        if !v {
+                            return Ok(Routes::unauthorized().await);
+                        }
+                    },
+                    Err(_) => {
+                        return Ok(Routes::internal_server_error().await);
+                    }
+                }
+            },
+            None => {
+                return Ok(Routes::unauthorized().await);
+            }
+        }
+
+        #[derive(Deserialize)]
+        struct Data<'a> {
+            start: u64,
+            end: u64,
+            interval: &'a str,
+            params: Option<serde_json::Value>,
+        }
+
+        // Make Data object from POST request body. Return error 400
+        // if sent data is malformed.
+        let data : Data = match serde_json::from_str(&*req.body) {
+            Ok(d) => d,
+            Err(e) => {
+                return Ok(http::HttpResponse {
+                    status: 400,
+                    headers: vec![
+                        ("Content-Type".into(), "text/plain".into()),
+                    ],
+                    body: e.to_string(),
+                });
+            }
+        };
+
+        if data.params.as_ref().is_some_and(|p| !p.is_object()) {
+            return Ok(http::HttpResponse {
+                status: 400,
+                headers: vec![
+                    ("Content-Type".into(), "text/plain".into()),
+                ],
+                body: "Params must be a JSON object.".into(),
+            });
+        }
+
+        // Retrieve algorithm. The backtest can be run with other params than the stored ones.
+        let mut algorithm = TradeAlgorithm::get(algo_id, psql.clone()).await?;
+        if let Some(params) = data.params {
+            algorithm.params = params;
+        }
+
+        // Market data is retrieved from the exchange of the algorithm.
+        let api = match algorithm.exchange {
+            Some(exchange) => {
+                let session_token = req.headers.get("session_token").cloned().unwrap_or_default();
+                api::authenticated_instance(exchange, &session_token, psql.clone()).await?
+            },
+            None => api,
+        };
+
+        match backtest::run(&algorithm, api, data.start as u128, data.end as u128, data.interval).await {
+            Ok(result) => {
+                Ok(http::HttpResponse{
+                    status: 200,
+                    headers: vec![
+                        ("Content-Type".into(), "application/json".into()),
+                    ],
+                    body: serde_json::to_string(&result)?,
+                })
+            },
+            Err(e) => {
+                eprintln!("{}", e);
+
+                Ok(http::HttpResponse {
+                    status: 400,
+                    headers: vec![
+                        ("Content-Type".into(), "text/plain".into()),
+                    ],
+                    body: format!("Backtest failed: {}", match e {
+                        tradealgorithm::Error::AlgorithmError(msg) => msg,
+                        tradealgorithm::Error::PythonCodeError(msg) => msg,
+                        tradealgorithm::Error::StreamError(msg) => msg,
+                        tradealgorithm::Error::ExecutorError(e) => e.to_string(),
+                        _ => "internal error".into(),
+                    }),
+                })
+            }
+        }
+    }
+
+    // Replace the user-defined parameters of an algorithm. The body is {"params": {...}}.
+    // A running algorithm uses the new params after it is restarted.
+    pub async fn set_algorithm_params(&self, req: http::Http, psql: Psql, _api: Api) -> Result<http::HttpResponse, http::Error> {
+
+        // Retrieve algorithm ID from URL-paremeter.
+        let algo_id = match req.params.get("id").cloned() {
+            Some(id) => id,
+            None => {
+                return Ok(Routes::not_found().await);
+            }
+        };
+
+        // Check if algorithm belongs to user doing request.
+        match req.headers.get("session_token") {
+            Some(token) => {
+                match http::validate_session_token(token, http::DBTable::Algorithm(&*algo_id), psql.clone()).await {
+                    Ok(v) => {
+                        if !v {
+                            return Ok(Routes::unauthorized().await);
+                        }
+                    },
+                    Err(_) => {
+                        return Ok(Routes::internal_server_error().await);
+                    }
+                }
+            },
+            None => {
+                return Ok(Routes::unauthorized().await);
+            }
+        }
+
+        #[derive(Deserialize)]
+        struct Data {
+            params: serde_json::Value,
+        }
+
+        // Make Data object from PUT request body. Return error 400
+        // if sent data is malformed.
+        let params = match serde_json::from_str::<Data>(&*req.body) {
+            Ok(d) if d.params.is_object() => d.params,
+            _ => {
+                return Ok(http::HttpResponse {
+                    status: 400,
+                    headers: vec![
+                        ("Content-Type".into(), "text/plain".into()),
+                    ],
+                    body: "Params must be a JSON object.".into(),
+                });
+            }
+        };
+
+        // Retrieve algorithm and update params.
+        let mut algorithm = TradeAlgorithm::get(algo_id, psql.clone()).await?;
+        algorithm.set_params(params, psql).await?;
+
+        Ok(http::HttpResponse{
+            status: 204,
+            headers: vec![],
+            body: "".into(),
+        })
+    }
+
+    // Get code of algorithm.
+    pub async fn get_algorithm_code(&self, req: http::Http, psql: Psql, _api: Api) -> Result<http::HttpResponse, http::Error> {
+        
+        // Retrieve algorithm ID from URL-paremeter.
+        let algo_id = match req.params.get("id").cloned() {
+            Some(id) => id,
+            None => {
+                return Ok(Routes::not_found().await);
+            }
+        };
+
+        // Check if algorithm belongs to user doing request.
+        match req.headers.get("session_token") {
+            Some(token) => {
+                match http::validate_session_token(token, http::DBTable::Algorithm(&*algo_id), psql.clone()).await {
+                    Ok(v) => {
+                        if !v {
+                            return Ok(Routes::unauthorized().await);
+                        }
+                    },
+                    Err(_) => {
+                        return Ok(Routes::internal_server_error().await);
+                    }
+                }
+            },
+            None => {
+                return Ok(Routes::unauthorized().await);
+            }
+        }
+                    
+        // Retrieve algorithm.
+        let algorithm = TradeAlgorithm::get(algo_id, psql.clone()).await?;
+
+        // Retrieve algorithm code and encode in Base64.
+        let algorithm_code = algorithm.get_code()?;
+        let mut code = std::vec::Vec::new();
+        let b64_engine = base64::engine::GeneralPurpose::new(&base64::alphabet::STANDARD, base64::engine::general_purpose::PAD);
+        let mut b64_encoder = base64::write::EncoderWriter::new(&mut code, &b64_engine);
+
+
+        if let Err(e) = b64_encoder.write_all(algorithm_code.as_bytes()) {
+            eprintln!("{}", e);
+            algorithm.delete(psql).await?;
+            return Err(http::Error::ParseError(format!("Error parsing base64 {}", e)));
+        }
+
+        drop(b64_encoder);
+
+        Ok(http::HttpResponse{
+            status: 200,
+            headers: vec![
+                ("Content-Type".into(), "text/plain".into()),
+            ],
+            body: String::from_utf8(code)?,
+        })
+    }
+    
+    pub async fn get_klines(&self, req: http::Http, _psql: Psql, api: Api) -> Result<http::HttpResponse, http::Error> {
+
+        // Retrieve all required parameters.
+        let symbol = match req.params.get("symbol").map(|s| s.parse::<api::Symbol>()) {
+            Some(Ok(s)) => s,
+            Some(Err(_)) => {
+                return Ok(Routes::not_found().await);
+            },
+            None => api::Symbol::default(),
+        };
+
+        let interval = match req.params.get("interval").cloned() {
+            Some(int) => int,
+            None => "1s".into(),
+        };
+        
+        let amount = match req.params.get("amount").cloned() {
+            Some(amt) => amt.parse::<u32>()?,
+            None => 15,
+        };
+
+        // Convert selected interval to matching amount in ms.
+        let ms = match &*interval {
+            "1s" => 0,
+            "1m" => 60000,
+            "5m" => 60000 * 5,
+            "1h" => 60000 * 60,
+            "1d" => 60000 * 60 * 24,
+            _ => {
+                return Ok(Routes::internal_server_error().await);
+            }
+        };
+
+        // Set current time as endTime so we get all the data until now.
+        let endtime = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis();
+
+        // Set startTime to the current time minus the amount of ms of the interval * the amount
+        // of requested datapoints.
+        let starttime = endtime - (amount as u128 * ms);
+        
         // Create request parameters.
         let mut params = std::collections::HashMap::<String, String>::new();
-        params.insert("symbol".into(), "BTCUSDT".into());
+        params.insert("symbol".into(), symbol.to_string());
         params.insert("interval".into(), interval.into());
         params.insert("limit".into(), amount.to_string());
 
@@ -961,14 +1608,31 @@ impl Routes {
 
     }
     
-    // Make order
+    // Make order. The body holds the side, the symbol (defaults to BTCUSDT) and the type of
+    // the order with its fields, e.g:
+    // {"side": "BUY", "symbol": "ETHUSDT", "type": "LIMIT", "quantity": 0.5, "price": 3000}
+    // See api::order::OrderKind for the fields of every type. Without a type a MARKET order is
+    // made for the amount in the quote asset, e.g {"action": "buy", "amount": 100}.
+    // Returns the orders as returned by the exchange.
     pub async fn order(&self, req: http::Http, _psql: Psql, api: Api) -> Result<http::HttpResponse, http::Error> {
         
         #[derive(Serialize, Deserialize)]
         struct Data<'a> {
-            action: &'a str,
-            amount: f64,
+            side: Option<&'a str>,
+            action: Option<&'a str>,
+            amount: Option<f64>,
+            symbol: Option<&'a str>,
+            #[serde(rename = "type")]
+            order_type: Option<&'a str>,
         }
+
+        let bad_request = |body: String| http::HttpResponse {
+            status: 400,
+            headers: vec![
+                ("Content-Type".into(), "text/plain".into()),
+            ],
+            body,
+        };
         
         // Make Data object from POST request body. Return error 400
         // if sent data is malformed.
@@ -977,13 +1641,7 @@ impl Routes {
             Err(e) => {
                 eprintln!("Error 4 {}", e);
 
-                return Ok(http::HttpResponse {
-                    status: 400,
-                    headers: vec![
-                        ("Content-Type".into(), "text/plain".into()),
-                    ],
-                    body: "".to_string(),
-                });
+                return Ok(bad_request("".to_string()));
             }
         };
 
@@ -994,32 +1652,136 @@ impl Routes {
         .map(char::from)
         .collect();
         
-        // Convert amount in USDT to BTC.
-        let btc_price = api.get_btc_price().await?;
-        let usdt_in_btc = data.amount / btc_price;
+        // Symbol to trade. Defaults to BTCUSDT.
+        let symbol = match data.symbol.map(|s| s.parse::<api::Symbol>()) {
+            Some(Ok(s)) => s,
+            Some(Err(e)) => {
+                return Ok(bad_request(e.into()));
+            },
+            None => api::Symbol::default(),
+        };
 
-        // Create order parameters.
-        let mut params = std::collections::HashMap::<String, String>::new();
-        params.insert("symbol".into(), "BTCUSDT".into());
-        params.insert("side".into(), data.action.to_string().to_uppercase());
-        params.insert("type".into(), "MARKET".into());
-        params.insert("quantity".into(), format!("{:.5}", usdt_in_btc));
-        params.insert("newClientOrderId".into(), order_id.to_string());
+        let side = match data.side.or(data.action).map(|s| s.parse::<protocol::Side>()) {
+            Some(Ok(s)) => s,
+            Some(Err(e)) => {
+                return Ok(bad_request(e.into()));
+            },
+            None => {
+                return Ok(bad_request("Order requires a side.".into()));
+            }
+        };
+
+        let price = api.get_price(&symbol).await?;
+        let kind = match (data.order_type, data.amount) {
+            (Some(_), _) => match serde_json::from_str::<api::order::OrderKind>(&req.body) {
+                Ok(k) => k,
+                Err(e) => {
+                    return Ok(bad_request(e.to_string()));
+                }
+            },
+            // Convert amount in the quote asset to the base asset.
+            (None, Some(amount)) => {
+                api::order::OrderKind::Market { quantity: Some(amount / price), quote_quantity: None }
+            },
+            (None, None) => {
+                return Ok(bad_request("Order requires a type or an amount.".into()));
+            }
+        };
+
+        let mut order = api::order::OrderRequest {
+            symbol,
+            side,
+            kind,
+            client_order_id: order_id,
+        };
+        if let Err(e) = order.validate() {
+            return Ok(bad_request(e));
+        }
+
+        // Round the order to the rules of the symbol at the exchange.
+        if let Err(e) = api.symbol_rules(&order.symbol).await?.normalize(&mut order, price) {
+            return Ok(bad_request(e));
+        }
         
         // Execute order.
-        match api.order(&mut params).await {
-            Ok(_) => {
+        match api.place_order(&order).await {
+            Ok(orders) => {
                 Ok(http::HttpResponse {
                     status: 200,
                     headers: vec![
-                        ("Content-Type".into(), "text/plain".into()),
+                        ("Content-Type".into(), "application/json".into()),
                     ],
-                    body: "Order made.".into(),
+                    body: serde_json::json!(orders).to_string(),
                 })
             },
             Err(e) => {
                 eprintln!("error {}", e);
                 
+                Ok(bad_request(e.to_string()))
+            }
+        }
+    }
+
+    // Get the open orders of the user at the exchange, of a single symbol when it is given in
+    // the path e.g /orders/ETHUSDT.
+    pub async fn get_open_orders(&self, req: http::Http, _psql: Psql, api: Api) -> Result<http::HttpResponse, http::Error> {
+        let symbol = match req.params.get("symbol").map(|s| s.parse::<api::Symbol>()) {
+            Some(Ok(s)) => Some(s),
+            Some(Err(e)) => {
+                return Ok(http::HttpResponse {
+                    status: 400,
+                    headers: vec![
+                        ("Content-Type".into(), "text/plain".into()),
+                    ],
+                    body: e.into(),
+                });
+            },
+            None => None,
+        };
+
+        match api.open_orders(symbol.as_ref()).await {
+            Ok(orders) => {
+                Ok(http::HttpResponse {
+                    status: 200,
+                    headers: vec![
+                        ("Content-Type".into(), "application/json".into()),
+                    ],
+                    body: serde_json::json!(orders).to_string(),
+                })
+            },
+            Err(e) => {
+                Ok(http::HttpResponse {
+                    status: 400,
+                    headers: vec![
+                        ("Content-Type".into(), "text/plain".into()),
+                    ],
+                    body: e.to_string(),
+                })
+            }
+        }
+    }
+
+    // Cancel an open order of the user. An order of an algorithm gets its final status when the
+    // algorithm syncs its orders, use cancel_algorithm_order to update it right away.
+    pub async fn cancel_order(&self, req: http::Http, _psql: Psql, api: Api) -> Result<http::HttpResponse, http::Error> {
+        let (symbol, client_order_id) = match (req.params.get("symbol").map(|s| s.parse::<api::Symbol>()), req.params.get("client_order_id")) {
+            (Some(Ok(symbol)), Some(id)) => (symbol, id),
+            _ => {
+                return Ok(Routes::not_found().await);
+            }
+        };
+
+        match api.cancel_order(&symbol, client_order_id).await {
+            Ok(order) => {
+                Ok(http::HttpResponse {
+                    status: 200,
+                    headers: vec![
+                        ("Content-Type".into(), "application/json".into()),
+                    ],
+                    body: order.to_string(),
+                })
+            },
+            Err(e) => {
                 Ok(http::HttpResponse {
                     status: 400,
                     headers: vec![
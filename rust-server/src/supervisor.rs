@@ -0,0 +1,468 @@
+// Supervisor of the PyExecutors of running algorithms. Every started algorithm gets a task
+// which starts its PyExecutor and keeps an eye on it:
+// - The process is checked with Child::try_wait.
+// - The PyExecutor sends a heartbeat every few seconds. When nothing is received for
+//   EXECUTOR_HEARTBEAT_TIMEOUT_SEC the PyExecutor is considered hung and killed.
+// - When the PyExecutor or one of the threads of the algorithm stops, everything is started
+//   again after a delay which doubles after every restart.
+// When the maximum amount of restarts is reached, or the PyExecutor gave up itself, the
+// algorithm is marked as errored and the reason is stored as the last error.
+//
+// The supervisor moves the algorithm through its lifecycle, see lifecycle.rs. Whether an
+// algorithm should be running is stored in the database as well, so the algorithms can be
+// resumed when the server restarts. It is cleared when the algorithm is stopped or errored.
+
+use super::*;
+
+lazy_static! {
+    static ref SUPERVISED: Mutex<std::collections::HashMap<String, Supervised>> = Mutex::new(std::collections::HashMap::new());
+}
+
+struct Supervised {
+    stop: tokio::sync::watch::Sender<bool>,
+    // A paused algorithm doesn't place orders, the PyExecutor keeps running.
+    paused: Arc<std::sync::atomic::AtomicBool>,
+    handle: tokio::task::JoinHandle<()>,
+}
+
+// A running PyExecutor with the threads passing candles and signals, see TradeAlgorithm::start.
+pub struct Execution {
+    pub process: std::process::Child,
+    pub sender: tokio::task::JoinHandle<()>,
+    // Returns the error when the PyExecutor gave up.
+    pub receiver: tokio::task::JoinHandle<Result<(), protocol::ExecutorError>>,
+    pub price: tokio::task::JoinHandle<()>,
+    // Watches the brackets of the positions, see ordermanager.rs.
+    pub positions: tokio::task::JoinHandle<()>,
+    // Time in seconds of the last message of the PyExecutor.
+    pub last_message: Arc<std::sync::atomic::AtomicU64>,
+}
+
+impl Execution {
+    // Kill the PyExecutor and stop the threads.
+    fn abort(&mut self) {
+        self.process.kill().unwrap_or_default();
+        self.process.wait().ok();
+        self.sender.abort();
+        self.receiver.abort();
+        self.price.abort();
+        self.positions.abort();
+    }
+}
+
+// Why an execution ended.
+#[derive(Debug)]
+enum Exit {
+    Stopped,
+    // The PyExecutor gave up, restarting would give the same result.
+    Fatal(protocol::ExecutorError),
+    // The PyExecutor or one of the threads stopped, the algorithm is restarted.
+    Failed(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct RestartPolicy {
+    pub backoff: std::time::Duration,
+    pub max_backoff: std::time::Duration,
+    pub max_restarts: u32,
+    // An execution running this long gets the restarts back.
+    pub reset_after: std::time::Duration,
+    pub heartbeat_timeout: std::time::Duration,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        RestartPolicy {
+            backoff: std::time::Duration::from_millis(*config::EXECUTOR_RESTART_BACKOFF_MS),
+            max_backoff: std::time::Duration::from_millis(*config::EXECUTOR_RESTART_MAX_BACKOFF_MS),
+            max_restarts: *config::EXECUTOR_MAX_RESTARTS,
+            reset_after: std::time::Duration::from_secs(*config::EXECUTOR_RESTART_RESET_SEC),
+            heartbeat_timeout: std::time::Duration::from_secs(*config::EXECUTOR_HEARTBEAT_TIMEOUT_SEC),
+        }
+    }
+}
+
+impl RestartPolicy {
+    // Delay before the given restart, the first restart is 1.
+    pub fn delay(&self, restart: u32) -> std::time::Duration {
+        let factor = 2u32.saturating_pow(restart.saturating_sub(1));
+        self.backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+}
+
+// Current time in seconds.
+pub fn now_sec() -> u64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs()
+}
+
+// Start an algorithm under supervision. Fails if the algorithm is already running.
+pub async fn start(algorithm: TradeAlgorithm, psql: Psql, api: Api) -> Result<(), tradealgorithm::Error> {
+    if is_supervised(&algorithm.id).await {
+        return Err(tradealgorithm::Error::AlgorithmError("Algorithm is already running".into()));
+    }
+
+    algorithm.transition(lifecycle::State::Starting, "Started by user.", psql.clone()).await?;
+
+    // The error of a previous run is cleared.
+    algorithm.set_last_error(None, psql.clone()).await?;
+    algorithm.set_desired_running(true, psql.clone()).await?;
+
+    launch(algorithm, psql, api, false).await
+}
+
+// Start the algorithms which were running when the server stopped. The candles before the
+// start are prepended again. Algorithms which were being stopped are marked as stopped.
+pub async fn resume(psql: Psql) {
+    match TradeAlgorithm::get_interrupted(psql.clone()).await {
+        Ok(ids) => {
+            for id in ids {
+                let stopped = async {
+                    let algorithm = TradeAlgorithm::get(id.to_string(), psql.clone()).await?;
+                    if algorithm.state != lifecycle::State::Stopping {
+                        algorithm.transition(lifecycle::State::Stopping, "Server restarted.", psql.clone()).await?;
+                    }
+                    algorithm.transition(lifecycle::State::Stopped, "Server restarted.", psql.clone()).await
+                };
+
+                if let Err(e) = stopped.await {
+                    eprintln!("{}", e);
+                }
+            }
+        },
+        Err(e) => eprintln!("{}", e),
+    }
+
+    let algorithms = match TradeAlgorithm::get_desired_running(psql.clone()).await {
+        Ok(a) => a,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+
+    for (id, user_id) in algorithms {
+        let algorithm = match TradeAlgorithm::get(id.to_string(), psql.clone()).await {
+            Ok(a) => a,
+            Err(e) => {
+                eprintln!("{}", e);
+                continue;
+            }
+        };
+
+        let resumed = async {
+            // An algorithm can trade on another exchange than the one selected by the user. The
+            // stored keys of the user are used, the user doesn't have to be logged in.
+            let exchange = match algorithm.exchange {
+                Some(e) => e,
+                None => api::exchange_of_user(user_id, psql.clone()).await?,
+            };
+            let api = api::user_instance(exchange, user_id, psql.clone()).await?;
+
+            // A paused algorithm stays paused.
+            let paused = algorithm.state == lifecycle::State::Paused;
+            algorithm.transition(lifecycle::State::Starting, "Server restarted.", psql.clone()).await?;
+            launch(algorithm.clone(), psql.clone(), api, paused).await
+        };
+
+        match resumed.await {
+            Ok(_) => println!("Resumed algorithm {}.", id),
+            Err(e) => {
+                let message = format!("Could not resume the algorithm: {}", e);
+                eprintln!("\x1b[31m[Error] {} - Supervisor: {}\x1b[0m", id, message);
+                record_error(&algorithm, message.to_string(), psql.clone()).await;
+                transition(&algorithm, lifecycle::State::Errored, &message, psql.clone()).await;
+            },
+        }
+    }
+}
+
+async fn launch(algorithm: TradeAlgorithm, psql: Psql, api: Api, paused: bool) -> Result<(), tradealgorithm::Error> {
+    let mut supervised = SUPERVISED.lock().await;
+
+    if let Some(s) = supervised.get(&algorithm.id) {
+        if !s.handle.is_finished() {
+            return Err(tradealgorithm::Error::AlgorithmError("Algorithm is already running".into()));
+        }
+    }
+
+    let (stop, stop_rx) = tokio::sync::watch::channel(false);
+    let paused = Arc::new(std::sync::atomic::AtomicBool::new(paused));
+    let id = algorithm.id.to_string();
+    let handle = tokio::spawn(supervise(algorithm, psql, api, RestartPolicy::default(), paused.clone(), stop_rx));
+
+    supervised.insert(id, Supervised { stop, paused, handle });
+
+    Ok(())
+}
+
+// Stop a supervised algorithm and wait until its PyExecutor is killed.
+pub async fn stop(id: &str) -> Result<(), tradealgorithm::Error> {
+    let supervised = match SUPERVISED.lock().await.remove(id) {
+        Some(s) => s,
+        None => {
+            return Err(tradealgorithm::Error::AlgorithmError("Algorithm is not running".into()));
+        }
+    };
+
+    let running = !supervised.handle.is_finished();
+    supervised.stop.send(true).unwrap_or_default();
+    supervised.handle.await.unwrap_or_default();
+
+    if !running {
+        return Err(tradealgorithm::Error::AlgorithmError("Algorithm is not running".into()));
+    }
+
+    Ok(())
+}
+
+// Stop placing the orders of a running algorithm. The PyExecutor keeps its candles and the
+// state of the Python-script.
+pub async fn pause(algorithm: &TradeAlgorithm, psql: Psql) -> Result<(), tradealgorithm::Error> {
+    set_paused(algorithm, true, psql).await
+}
+
+// Place the orders of a paused algorithm again.
+pub async fn unpause(algorithm: &TradeAlgorithm, psql: Psql) -> Result<(), tradealgorithm::Error> {
+    set_paused(algorithm, false, psql).await
+}
+
+async fn set_paused(algorithm: &TradeAlgorithm, paused: bool, psql: Psql) -> Result<(), tradealgorithm::Error> {
+    // The supervisor isn't locked during the transition, so other algorithms aren't blocked.
+    let flag = match SUPERVISED.lock().await.get(&algorithm.id) {
+        Some(s) if !s.handle.is_finished() => s.paused.clone(),
+        _ => {
+            return Err(tradealgorithm::Error::StateError("Algorithm is not running".into()));
+        }
+    };
+
+    if paused {
+        algorithm.transition(lifecycle::State::Paused, "Paused by user.", psql).await?;
+    } else {
+        algorithm.transition(lifecycle::State::Running, "Resumed by user.", psql).await?;
+    }
+    flag.store(paused, std::sync::atomic::Ordering::SeqCst);
+
+    Ok(())
+}
+
+// Check if the supervisor still looks after the algorithm, also while it waits to restart it.
+pub async fn is_supervised(id: &str) -> bool {
+    SUPERVISED.lock().await.get(id).map(|s| !s.handle.is_finished()).unwrap_or(false)
+}
+
+async fn supervise(algorithm: TradeAlgorithm, psql: Psql, api: Api, policy: RestartPolicy, paused: Arc<std::sync::atomic::AtomicBool>, mut stop: tokio::sync::watch::Receiver<bool>) {
+    let mut restarts = 0;
+
+    // The algorithm isn't resumed anymore once it stopped or errored.
+    let stopped = |to: lifecycle::State, reason: String, psql: Psql| {
+        let algorithm = &algorithm;
+        async move {
+            transition(algorithm, to, &reason, psql.clone()).await;
+            if let Err(e) = algorithm.set_desired_running(false, psql).await {
+                eprintln!("{}", e);
+            }
+        }
+    };
+
+    loop {
+        let started = std::time::Instant::now();
+
+        let reason = match run(&algorithm, psql.clone(), api.clone(), &policy, paused.clone(), &mut stop).await {
+            Exit::Stopped => {
+                stopped(lifecycle::State::Stopped, "PyExecutor stopped.".into(), psql).await;
+                return;
+            },
+            Exit::Fatal(e) => {
+                // The error is stored by the algorithm.
+                eprintln!("\x1b[31m[Error] {} - Supervisor: PyExecutor gave up: {}\x1b[0m", algorithm.id, e.message);
+                stopped(lifecycle::State::Errored, e.message, psql).await;
+                return;
+            },
+            Exit::Failed(reason) => reason,
+        };
+
+        if started.elapsed() >= policy.reset_after {
+            restarts = 0;
+        }
+        restarts += 1;
+
+        if restarts > policy.max_restarts {
+            let message = format!("{} Gave up after {} restarts.", reason, policy.max_restarts);
+            eprintln!("\x1b[31m[Error] {} - Supervisor: {}\x1b[0m", algorithm.id, message);
+            record_error(&algorithm, message.to_string(), psql.clone()).await;
+            stopped(lifecycle::State::Errored, message, psql).await;
+            return;
+        }
+
+        let delay = policy.delay(restarts);
+        let message = format!("{} Restart {} of {} in {} ms.", reason, restarts, policy.max_restarts, delay.as_millis());
+        eprintln!("\x1b[31m[Error] {} - Supervisor: {}\x1b[0m", algorithm.id, message);
+        record_error(&algorithm, message.to_string(), psql.clone()).await;
+        transition(&algorithm, lifecycle::State::Starting, &message, psql.clone()).await;
+
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => (),
+            _ = stop.changed() => {
+                transition(&algorithm, lifecycle::State::Stopping, "Stopped by user.", psql.clone()).await;
+                stopped(lifecycle::State::Stopped, "Stopped before restarting.".into(), psql).await;
+                return;
+            }
+        }
+    }
+}
+
+// Start the algorithm once and wait until the execution ends.
+async fn run(algorithm: &TradeAlgorithm, psql: Psql, api: Api, policy: &RestartPolicy, paused: Arc<std::sync::atomic::AtomicBool>, stop: &mut tokio::sync::watch::Receiver<bool>) -> Exit {
+
+    // Make a transmitter and receiver. The receiver is passed to the algorithm when started
+    // so it can receive the data from the transmitter which we pass to the exchange API.
+    let (tx, rx) = mpsc::channel::<CandleStick>(10);
+    let tx = Arc::new(Mutex::new(tx));
+    let rx = Arc::new(Mutex::new(rx));
+
+    // Connect to the exchange and start the PyExecutor. The algorithm can be stopped meanwhile,
+    // the start-up is cancelled then.
+    let starting = async {
+        // Make websocket stream to API endpoint for making orders.
+        let url = match api.get_urls("ws_api_url").map(url::Url::parse) {
+            Some(Ok(url)) => url,
+            _ => {
+                return Err(Exit::Failed("Invalid websocket URL of the exchange.".into()));
+            }
+        };
+        let ws_api = match connect_async(url).await {
+            Ok((ws_stream, _)) => Arc::new(api::wsapi::WsApi::new(ws_stream)),
+            Err(e) => {
+                return Err(Exit::Failed(format!("Could not connect to the exchange: {}.", e)));
+            }
+        };
+
+        // The candles before the start are prepended.
+        let current_time = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis();
+        let starttime = current_time - algorithm.prepend_data as u128;
+
+        match algorithm.clone().start(psql.clone(), rx, api.clone(), ws_api.clone(), starttime, paused.clone()).await {
+            Ok(e) => Ok((e, ws_api)),
+            Err(tradealgorithm::Error::ExecutorError(e)) => Err(Exit::Fatal(e)),
+            Err(e) => Err(Exit::Failed(format!("Could not start the algorithm: {}.", e))),
+        }
+    };
+
+    let (mut execution, ws_api) = tokio::select! {
+        started = starting => match started {
+            Ok(s) => s,
+            Err(exit) => return exit,
+        },
+        _ = stop.changed() => {
+            transition(algorithm, lifecycle::State::Stopping, "Stopped by user.", psql.clone()).await;
+            return Exit::Stopped;
+        },
+    };
+    transition(algorithm, lifecycle::State::Running, "PyExecutor started.", psql.clone()).await;
+    if paused.load(std::sync::atomic::Ordering::SeqCst) {
+        transition(algorithm, lifecycle::State::Paused, "Paused before the restart.", psql.clone()).await;
+    }
+
+    // Start websocket API of exchange and pass the transmitter as parameter
+    // so the incoming data is send to the algorithm.
+    let interval = algorithm.interval.to_string();
+    let symbol = algorithm.symbol.clone();
+    let api_clone = api.clone();
+    let klines = tokio::spawn(async move {
+        if let Err(e) = api_clone.ws_kline(tx, &symbol, interval).await {
+            eprintln!("{}", e);
+        }
+    });
+
+    let exit = loop {
+        tokio::select! {
+            _ = tokio::time::sleep(tokio::time::Duration::from_millis(1000)) => (),
+            _ = stop.changed() => {
+                transition(algorithm, lifecycle::State::Stopping, "Stopped by user.", psql.clone()).await;
+                break Exit::Stopped;
+            },
+        }
+
+        if execution.receiver.is_finished() {
+            break match (&mut execution.receiver).await {
+                Ok(Err(e)) => Exit::Fatal(e),
+                Ok(Ok(_)) => Exit::Failed("Connection to the PyExecutor closed.".into()),
+                Err(e) => Exit::Failed(format!("Thread reading the PyExecutor failed: {}.", e)),
+            };
+        }
+
+        match execution.process.try_wait() {
+            Ok(Some(status)) => {
+                // A PyExecutor giving up exits right after reporting why.
+                break match tokio::time::timeout(tokio::time::Duration::from_millis(1000), &mut execution.receiver).await {
+                    Ok(Ok(Err(e))) => Exit::Fatal(e),
+                    _ => Exit::Failed(format!("PyExecutor exited with {}.", status)),
+                };
+            },
+            Ok(None) => (),
+            Err(e) => break Exit::Failed(format!("Could not check the PyExecutor: {}.", e)),
+        }
+
+        if execution.sender.is_finished() || klines.is_finished() {
+            break Exit::Failed("Datastream of the exchange closed.".into());
+        }
+
+        if ws_api.is_closed() {
+            break Exit::Failed("Websocket API of the exchange closed.".into());
+        }
+
+        let last_message = execution.last_message.load(std::sync::atomic::Ordering::SeqCst);
+        if now_sec().saturating_sub(last_message) > policy.heartbeat_timeout.as_secs() {
+            break Exit::Failed(format!("No heartbeat of the PyExecutor for {} seconds.", policy.heartbeat_timeout.as_secs()));
+        }
+    };
+
+    execution.abort();
+    klines.abort();
+
+    exit
+}
+
+// Move the algorithm to another state. The supervisor follows the PyExecutor, so a transition
+// which isn't allowed is only logged.
+async fn transition(algorithm: &TradeAlgorithm, to: lifecycle::State, reason: &str, psql: Psql) {
+    if let Err(e) = algorithm.transition(to, reason, psql).await {
+        eprintln!("{}", e);
+    }
+}
+
+// Store why the algorithm was restarted or gave up.
+async fn record_error(algorithm: &TradeAlgorithm, message: String, psql: Psql) {
+    let error = protocol::ExecutorError {
+        kind: protocol::ErrorKind::Executor,
+        message,
+        traceback: None,
+        candle_timestamp: None,
+    };
+
+    if let Err(e) = algorithm.set_last_error(Some(&error), psql).await {
+        eprintln!("{}", e);
+    }
+}
+
+
+// Testing the supervisor.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restart_delay() {
+        let policy = RestartPolicy {
+            backoff: std::time::Duration::from_millis(500),
+            max_backoff: std::time::Duration::from_millis(3000),
+            max_restarts: 5,
+            reset_after: std::time::Duration::from_secs(600),
+            heartbeat_timeout: std::time::Duration::from_secs(30),
+        };
+
+        let delays = (1..=5).map(|r| policy.delay(r).as_millis()).collect::<std::vec::Vec<u128>>();
+        assert_eq!(delays, vec![500, 1000, 2000, 3000, 3000]);
+        assert_eq!(policy.delay(100).as_millis(), 3000);
+    }
+}
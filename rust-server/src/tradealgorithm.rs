@@ -4,9 +4,11 @@
 
 use super::*;
 
-lazy_static! {
-    pub static ref PROCESS_HANDLES: Mutex<std::collections::HashMap<String, std::process::Child>> = Mutex::new(std::collections::HashMap::new()); 
-}
+// Intervals of candlesticks an algorithm can run on.
+pub const INTERVALS: [&str; 10] = ["1s", "1m", "5m", "15m", "30m", "1h", "2h", "12h", "1d", "3d"];
+
+// Formats in which the candles can be passed to the Python function.
+pub const DATA_FORMATS: [&str; 3] = ["list", "numpy", "pandas"];
 
 // All the trading algorithms rely on candlestick charts.
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -17,6 +19,39 @@ pub struct CandleStick {
     pub high: f64,
     pub low: f64,
     pub volume: f64,
+    pub closed: bool,
+}
+
+impl From<&CandleStick> for protocol::Candle {
+    fn from(c: &CandleStick) -> Self {
+        protocol::Candle {
+            timestamp: c.timestamp,
+            open: c.open,
+            close: c.close,
+            high: c.high,
+            low: c.low,
+            volume: c.volume,
+            closed: c.closed,
+        }
+    }
+}
+
+impl CandleStick {
+    // Create a candlestick from a kline as returned by the klines endpoint of the REST API.
+    // [open time, open, high, low, close, volume, close time, ...]
+    pub fn from_kline(kl: &serde_json::Value) -> Result<Self, tradealgorithm::Error> {
+        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis() as u64;
+
+        Ok(CandleStick {
+            timestamp: serde_json::from_value::<u64>(kl[0].clone())?,
+            open: serde_json::from_value::<String>(kl[1].clone())?.parse::<f64>()?,
+            high: serde_json::from_value::<String>(kl[2].clone())?.parse::<f64>()?,
+            low: serde_json::from_value::<String>(kl[3].clone())?.parse::<f64>()?,
+            close: serde_json::from_value::<String>(kl[4].clone())?.parse::<f64>()?,
+            volume: serde_json::from_value::<String>(kl[5].clone())?.parse::<f64>()?,
+            closed: serde_json::from_value::<u64>(kl[6].clone())? < now,
+        })
+    }
 }
 
 // X-axis timespan of the chart of the algorithm.
@@ -40,34 +75,112 @@ impl std::str::FromStr for ChartInterval {
 }
 
 
+// Settings of an algorithm, read from the body when it is added. The symbol is given by name and
+// looked up at the exchange, see api::find_symbol.
+#[derive(Clone, Debug, Deserialize)]
+pub struct AlgorithmSettings {
+    #[serde(skip)]
+    pub symbol: api::Symbol,
+    #[serde(default, deserialize_with = "exchange_kind")]
+    pub exchange: Option<api::ExchangeKind>,
+    #[serde(default = "empty_params")]
+    pub params: serde_json::Value,
+    #[serde(default = "default_max_history")]
+    pub max_history: i32,
+    #[serde(default)]
+    pub run_on_close: bool,
+    #[serde(default = "default_data_format")]
+    pub data_format: String,
+}
+
+// The exchange is given by name e.g paper, if not given the exchange of the user is used.
+fn exchange_kind<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<api::ExchangeKind>, D::Error> {
+    match Option::<String>::deserialize(deserializer)? {
+        Some(s) => s.parse().map(Some).map_err(serde::de::Error::custom),
+        None => Ok(None),
+    }
+}
+
+fn empty_params() -> serde_json::Value {
+    serde_json::json!({})
+}
+
+fn default_max_history() -> i32 {
+    *config::DEFAULT_MAX_HISTORY
+}
+
+fn default_data_format() -> String {
+    "list".into()
+}
+
 // A TradeAlgorithm has a Python-script containing the algorithm we want to test.
-// Each algorithm gets a specific amount of funds assigned to play with.
+// Each algorithm gets a specific amount of funds assigned to play with and trades
+// a single symbol. The start funds are in the quote asset of the symbol.
 #[derive(Clone)]
 pub struct TradeAlgorithm {
     pub description: String,
     pub id: String,
+    pub symbol: api::Symbol,
     pub start_funds: f64,
     pub interval: String,
     pub run_every_sec: i32,
     pub prepend_data: i32,
+    pub exchange: Option<api::ExchangeKind>,
+    pub params: serde_json::Value,
+    pub max_history: i32,
+    pub run_on_close: bool,
+    pub data_format: String,
+    pub state: lifecycle::State,
 }
 
 impl TradeAlgorithm {
     //Create a new trading algorithm and insert into database.
-    pub async fn new(id: String, description: String, funds: f64, interval: String, run_every_sec: i32, prepend_data: i32, user_id: i32, psql: Psql) -> Result<Self, tradealgorithm::Error> {
+    pub async fn new(id: String, description: String, funds: f64, interval: String, run_every_sec: i32, prepend_data: i32, settings: AlgorithmSettings, user_id: i32, psql: Psql) -> Result<Self, tradealgorithm::Error> {
+        let AlgorithmSettings { symbol, exchange, params, max_history, run_on_close, data_format } = settings;
 
         // Check if interval is valid.
-        if !vec!["1s", "1m", "5m", "15m", "30m", "1h", "2h", "12h", "1d", "3d"].contains(&&*interval) {
+        if !INTERVALS.contains(&&*interval) {
            return Err(tradealgorithm::Error::AlgorithmError("Invalid interval given".into()));
         }
 
+        // Check if data format is valid.
+        if !DATA_FORMATS.contains(&&*data_format) {
+           return Err(tradealgorithm::Error::AlgorithmError("Invalid data format given".into()));
+        }
+
+        // Check if params are valid.
+        if !params.is_object() {
+           return Err(tradealgorithm::Error::AlgorithmError("Params must be a JSON object".into()));
+        }
+
+        // Check if the amount of candles passed to the Python function is valid.
+        if max_history < 1 || max_history > *config::MAX_HISTORY_LIMIT {
+           return Err(tradealgorithm::Error::AlgorithmError(format!("Max history must be between 1 and {}", *config::MAX_HISTORY_LIMIT)));
+        }
+
         let query = psql.lock().await
            .query("
                 INSERT INTO algorithms 
-                    (id, description, start_funds_usdt, interval, run_every_sec, user_id, prepend_data)
+                    (id, description, symbol, base_asset, quote_asset, start_funds_usdt, interval, run_every_sec, user_id, prepend_data, exchange, params, max_history, run_on_close, data_format)
+                VALUES
+                    ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12::TEXT::JSONB, $13, $14, $15)
+            ", &[&id, &description, &symbol.to_string(), &symbol.base, &symbol.quote, super::sqlf64!(funds), &interval, &run_every_sec, &user_id, &prepend_data, &exchange.map(|e| e.to_string()), &params.to_string(), &max_history, &run_on_close, &data_format]).await;
+
+       match query {
+           Ok(_) => (),
+           Err(e) => {
+                return Err(tradealgorithm::Error::DatabaseError(e.to_string()));
+           }
+       };
+
+        // The first event of the algorithm has no previous state.
+        let query = psql.lock().await
+           .query("
+                INSERT INTO algorithm_events
+                    (algorithm_id, from_state, to_state, reason)
                 VALUES
-                    ($1, $2, $3, $4, $5, $6, $7)
-            ", &[&id, &description, super::sqlf64!(funds), &interval, &run_every_sec, &user_id, &prepend_data]).await;
+                    ($1, NULL, $2, $3)
+            ", &[&id, &lifecycle::State::Created.to_string(), &"Created by user."]).await;
 
        match query {
            Ok(_) => (),
@@ -79,15 +192,26 @@ impl TradeAlgorithm {
         Ok(TradeAlgorithm {
             id: id.into(),
             description: description.into(),
+            symbol: symbol,
             start_funds: funds,
             interval: interval,
             run_every_sec: run_every_sec,
             prepend_data: prepend_data,
+            exchange: exchange,
+            params: params,
+            max_history: max_history,
+            run_on_close: run_on_close,
+            data_format: data_format,
+            state: lifecycle::State::Created,
         })
     }
 
-    // Delete algorithm from database.
+    // Delete algorithm from database. The algorithm must be stopped.
     pub async fn delete(&self, psql: Psql) -> Result<(), tradealgorithm::Error> {
+        if self.get_state(psql.clone()).await?.is_active() {
+            return Err(tradealgorithm::Error::StateError("Algorithm is still running".into()));
+        }
+
         let query = psql.lock().await
            .query("
                 DELETE FROM algorithms
@@ -98,6 +222,7 @@ impl TradeAlgorithm {
         match query {
             Ok(_) => {
                 std::fs::remove_file(format!("trading_algos/{}.py", self.id))?;
+                self.remove_state();
                 Ok(())
             },
             Err(e) => {
@@ -111,7 +236,7 @@ impl TradeAlgorithm {
        let query = psql.lock().await
            .query("
                 SELECT
-                    id, description, start_funds_usdt, interval, run_every_sec, prepend_data
+                    id, description, symbol, base_asset, quote_asset, start_funds_usdt, interval, run_every_sec, prepend_data, exchange, params::TEXT, max_history, run_on_close, data_format, state
                 FROM
                     algorithms
                 WHERE
@@ -127,10 +252,17 @@ impl TradeAlgorithm {
                 Ok(TradeAlgorithm{
                     id: q[0].get("id"),
                     description: q[0].get("description"),
+                    symbol: api::Symbol::from_stored(q[0].get("symbol"), q[0].get("base_asset"), q[0].get("quote_asset")).map_err(|e| tradealgorithm::Error::ParseError(e.into()))?,
                     start_funds: sqldec!(q[0].get::<&str, Decimal>("start_funds_usdt")),
                     interval: q[0].get("interval"),
                     run_every_sec: q[0].get("run_every_sec"),
                     prepend_data: q[0].get("prepend_data"),
+                    exchange: q[0].get::<&str, Option<String>>("exchange").and_then(|e| e.parse().ok()),
+                    params: serde_json::from_str(q[0].get("params"))?,
+                    max_history: q[0].get("max_history"),
+                    run_on_close: q[0].get("run_on_close"),
+                    data_format: q[0].get("data_format"),
+                    state: q[0].get::<&str, &str>("state").parse().map_err(|e: &str| tradealgorithm::Error::ParseError(e.into()))?,
                 })
             },
             Err(e) => {
@@ -139,8 +271,291 @@ impl TradeAlgorithm {
        }
     }
 
+    // Replace the user-defined parameters of this algorithm. They are passed to the Python
+    // function the next time the algorithm starts.
+    pub async fn set_params(&mut self, params: serde_json::Value, psql: Psql) -> Result<(), tradealgorithm::Error> {
+        if !params.is_object() {
+           return Err(tradealgorithm::Error::AlgorithmError("Params must be a JSON object".into()));
+        }
+
+        let query = psql.lock().await
+           .query("
+                UPDATE
+                    algorithms
+                SET
+                    params = $1::TEXT::JSONB
+                WHERE
+                    id = $2
+            ", &[&params.to_string(), &self.id]).await;
+
+        match query {
+            Ok(_) => {
+                self.params = params;
+                Ok(())
+            },
+            Err(e) => {
+                Err(tradealgorithm::Error::DatabaseError(e.to_string()))
+            }
+        }
+    }
+
+    // Store the last error of the PyExecutor. None clears the error.
+    pub async fn set_last_error(&self, error: Option<&protocol::ExecutorError>, psql: Psql) -> Result<(), tradealgorithm::Error> {
+        let error = match error {
+            Some(e) => Some(serde_json::to_string(e)?),
+            None => None,
+        };
+
+        let query = psql.lock().await
+           .query("
+                UPDATE
+                    algorithms
+                SET
+                    last_error = $1::TEXT::JSONB
+                WHERE
+                    id = $2
+            ", &[&error, &self.id]).await;
+
+        match query {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                Err(tradealgorithm::Error::DatabaseError(e.to_string()))
+            }
+        }
+    }
+
+    // Retrieve the last error of the PyExecutor, with its kind, message, traceback and the
+    // candle it occurred on.
+    pub async fn get_last_error(&self, psql: Psql) -> Result<Option<serde_json::Value>, tradealgorithm::Error> {
+        let query = psql.lock().await
+           .query("
+                SELECT
+                    last_error::TEXT
+                FROM
+                    algorithms
+                WHERE
+                    id = $1
+            ", &[&self.id]).await;
+
+        match query {
+            Ok(q) => {
+                if q.is_empty() {
+                    return Err(tradealgorithm::Error::AlgorithmError("Algorithm not found".into()));
+                }
+
+                match q[0].get::<_, Option<&str>>("last_error") {
+                    Some(e) => Ok(Some(serde_json::from_str(e)?)),
+                    None => Ok(None),
+                }
+            },
+            Err(e) => {
+                Err(tradealgorithm::Error::DatabaseError(e.to_string()))
+            }
+        }
+    }
+
+    // Store whether the algorithm should be running. Algorithms which should be running are
+    // started again when the server restarts.
+    pub async fn set_desired_running(&self, running: bool, psql: Psql) -> Result<(), tradealgorithm::Error> {
+        let query = psql.lock().await
+           .query("
+                UPDATE
+                    algorithms
+                SET
+                    desired_running = $1
+                WHERE
+                    id = $2
+            ", &[&running, &self.id]).await;
+
+        match query {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                Err(tradealgorithm::Error::DatabaseError(e.to_string()))
+            }
+        }
+    }
+
+    // Retrieve the algorithms which should be running, with the ID of their user to
+    // authenticate with the exchange.
+    pub async fn get_desired_running(psql: Psql) -> Result<std::vec::Vec<(String, i32)>, tradealgorithm::Error> {
+        let query = psql.lock().await
+           .query("
+                SELECT
+                    id, user_id
+                FROM
+                    algorithms
+                WHERE
+                    desired_running
+            ", &[]).await;
+
+        match query {
+            Ok(q) => Ok(q.iter().map(|row| (row.get("id"), row.get("user_id"))).collect()),
+            Err(e) => {
+                Err(tradealgorithm::Error::DatabaseError(e.to_string()))
+            }
+        }
+    }
+
+    // Retrieve the algorithms which are starting, running or stopping while they shouldn't be
+    // running, because the server stopped before they were stopped.
+    pub async fn get_interrupted(psql: Psql) -> Result<std::vec::Vec<String>, tradealgorithm::Error> {
+        let query = psql.lock().await
+           .query("
+                SELECT
+                    id
+                FROM
+                    algorithms
+                WHERE
+                    state IN ('starting', 'running', 'paused', 'stopping')
+                AND
+                    NOT desired_running
+            ", &[]).await;
+
+        match query {
+            Ok(q) => Ok(q.iter().map(|row| row.get("id")).collect()),
+            Err(e) => {
+                Err(tradealgorithm::Error::DatabaseError(e.to_string()))
+            }
+        }
+    }
+
+    // Move the algorithm to another state and store the transition with its reason. Fails when
+    // the current state doesn't allow the transition. Returns the previous state.
+    pub async fn transition(&self, to: lifecycle::State, reason: &str, psql: Psql) -> Result<lifecycle::State, tradealgorithm::Error> {
+        let mut client = psql.lock().await;
+
+        // The state is locked until the transition is stored, so two transitions can't both
+        // start from the same state.
+        let transaction = client.transaction().await?;
+
+        let query = transaction
+           .query("
+                SELECT
+                    state
+                FROM
+                    algorithms
+                WHERE
+                    id = $1
+                FOR UPDATE
+            ", &[&self.id]).await;
+
+        let from: lifecycle::State = match query {
+            Ok(q) => {
+                if q.is_empty() {
+                    return Err(tradealgorithm::Error::AlgorithmError("Algorithm not found".into()));
+                }
+                q[0].get::<_, &str>("state").parse().map_err(|e: &str| tradealgorithm::Error::ParseError(e.into()))?
+            },
+            Err(e) => {
+                return Err(tradealgorithm::Error::DatabaseError(e.to_string()));
+            }
+        };
+
+        if !from.can_transition(to) {
+            return Err(tradealgorithm::Error::StateError(format!("Algorithm can't go from {} to {}", from, to)));
+        }
+
+        let query = transaction
+           .query("
+                UPDATE
+                    algorithms
+                SET
+                    state = $1
+                WHERE
+                    id = $2
+            ", &[&to.to_string(), &self.id]).await;
+
+        if let Err(e) = query {
+            return Err(tradealgorithm::Error::DatabaseError(e.to_string()));
+        }
+
+        let query = transaction
+           .query("
+                INSERT INTO algorithm_events
+                    (algorithm_id, from_state, to_state, reason)
+                VALUES
+                    ($1, $2, $3, $4)
+            ", &[&self.id, &from.to_string(), &to.to_string(), &reason]).await;
+
+        if let Err(e) = query {
+            return Err(tradealgorithm::Error::DatabaseError(e.to_string()));
+        }
+
+        transaction.commit().await?;
+
+        Ok(from)
+    }
+
+    // Retrieve the current state of the algorithm.
+    pub async fn get_state(&self, psql: Psql) -> Result<lifecycle::State, tradealgorithm::Error> {
+        let query = psql.lock().await
+           .query("
+                SELECT
+                    state
+                FROM
+                    algorithms
+                WHERE
+                    id = $1
+            ", &[&self.id]).await;
+
+        match query {
+            Ok(q) => {
+                if q.is_empty() {
+                    return Err(tradealgorithm::Error::AlgorithmError("Algorithm not found".into()));
+                }
+                q[0].get::<_, &str>("state").parse().map_err(|e: &str| tradealgorithm::Error::ParseError(e.into()))
+            },
+            Err(e) => {
+                Err(tradealgorithm::Error::DatabaseError(e.to_string()))
+            }
+        }
+    }
+
+    // Get the transitions between the states of the algorithm, newest first.
+    pub async fn get_events(&self, psql: Psql) -> Result<serde_json::value::Value, tradealgorithm::Error> {
+        let query = psql.lock().await
+           .query("
+                SELECT
+                    id, from_state, to_state, reason, created_at::TEXT
+                FROM
+                    algorithm_events
+                WHERE
+                    algorithm_id = $1
+                ORDER BY
+                    id DESC
+                LIMIT 100
+            ", &[&self.id]).await;
+
+        let rows = match query {
+            Ok(q) => q,
+            Err(e) => {
+                return Err(tradealgorithm::Error::DatabaseError(e.to_string()));
+            }
+        };
+
+        #[derive(Serialize)]
+        struct Data {
+            id: i32,
+            from_state: Option<String>,
+            to_state: String,
+            reason: Option<String>,
+            created_at: String,
+        }
+
+        let data = rows.iter().map(|row| Data {
+            id: row.get("id"),
+            from_state: row.get("from_state"),
+            to_state: row.get("to_state"),
+            reason: row.get("reason"),
+            created_at: row.get("created_at"),
+        }).collect::<std::vec::Vec<Data>>();
+
+        Ok(serde_json::json!(data))
+    }
+
     // Get current funds from this algorithm. We sum the total amount from the history.
-    // On success returns a tuple (f64, f64) -> (USDT, BTC)
+    // The btc and usdt columns of the history hold the base and quote asset of the symbol.
+    // On success returns a tuple (f64, f64) -> (quote, base)
     pub async fn get_current_funds(&self, psql: Psql) -> Result<(f64, f64), tradealgorithm::Error> {
         let query = psql.lock().await
            .query("
@@ -173,83 +588,246 @@ impl TradeAlgorithm {
        }
     }
 
-    // Get current balance. The difference between funds and balance is that the balance keeps
-    // in account the volatility of BTC.
+    // Get current balance in the quote asset. The difference between funds and balance is that
+    // the balance keeps in account the volatility of the base asset.
     pub async fn get_current_balance(&self, psql: Psql, api: Api) -> Result<f64, tradealgorithm::Error> {
       
-        // Get current USDT and BTC funds.
+        // Get current quote and base funds.
         let (current_funds_usdt, current_funds_btc) = self.get_current_funds(psql.clone()).await?;
 
-        // Get BTC price.
-        let btc_price = api.get_btc_price().await?;
+        // Get price of the base asset.
+        let btc_price = api.get_price(&self.symbol).await?;
         let btc_in_usdt = current_funds_btc * btc_price;
 
         Ok(current_funds_usdt + btc_in_usdt)
     }
     
-    // Start the trading algorithm. The algorithm keeps running on a seperate thread until it is aborted.
-    pub async fn start(self, psql: Psql, datastream: Arc<Mutex<mpsc::Receiver<CandleStick>>>, api: Api, ws_send: Arc<Mutex<SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>>>, starttime: u128) -> Result<(std::process::Child, tokio::task::JoinHandle<()>, tokio::task::JoinHandle<()>), tradealgorithm::Error> {
-
-        // Variable to save initial prepended kline data. We serialize this later
-        // to share it through shared memory.
-        let mut data = std::vec::Vec::<CandleStick>::new();
+    // Start the PyExecutor of the trading algorithm. The algorithm keeps running on seperate
+    // threads until the execution is aborted, see supervisor.rs.
+    pub async fn start(self, psql: Psql, datastream: Arc<Mutex<mpsc::Receiver<CandleStick>>>, api: Api, ws_api: Arc<api::wsapi::WsApi>, starttime: u128, paused: Arc<std::sync::atomic::AtomicBool>) -> Result<supervisor::Execution, tradealgorithm::Error> {
 
         // Prepend data if required.
+        let mut data = std::vec::Vec::<CandleStick>::new();
         if self.prepend_data > 0 {
             let endtime = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis();
-            
+
+            data = match self.fetch_candles(api.clone(), &self.interval, starttime, endtime).await {
+                Ok(d) => d,
+                Err(_) => {
+                    return Err(tradealgorithm::Error::APIError("Could not prepend data".into()));
+                }
+            };
+        }
+
+        // Current price of the symbol to add to history of algorithms.
+        let current_btc_price = api.get_price(&self.symbol).await?;
+        let current_btc_price = Arc::new(Mutex::new(current_btc_price));
+
+        // Start PyExecutor and connect to it. The reason the code could not be loaded is kept.
+        let (process_handle, unix_stream) = match self.spawn_executor(&self.id, &data, self.run_every_sec).await {
+            Ok(r) => r,
+            Err(tradealgorithm::Error::ExecutorError(e)) => {
+                self.set_last_error(Some(&e), psql.clone()).await?;
+                return Err(tradealgorithm::Error::ExecutorError(e));
+            },
+            Err(e) => return Err(e),
+        };
+
+        // Split UnixStream into receiver and transmitter so we can
+        // send data from the API to PyExecutor and receive the result.
+        let (mut rx, mut tx) = unix_stream.into_split();
+
+        // Thread to update the current_btc_price and the open orders every 10 seconds.
+        let api_clone = api.clone();
+        let current_btc_price_clone = current_btc_price.clone();
+        let symbol = self.symbol.clone();
+        let algorithm_id = self.id.to_string();
+        let psql_clone = psql.clone();
+        let price_handle = tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(tokio::time::Duration::from_millis(10000)).await;
+
+                if let Err(e) = orders::sync(&algorithm_id, api_clone.clone(), psql_clone.clone()).await {
+                    eprintln!("{}", e);
+                }
+
+                let mut current_btc_price_guard = current_btc_price_clone.lock().await;
+                *current_btc_price_guard = match api_clone.clone().get_price(&symbol).await {
+                    Ok(p) => p,
+                    Err(_) => continue,
+                };
+                ordermanager::publish(&symbol, *current_btc_price_guard);
+                drop(current_btc_price_guard);
+            }
+        });
+
+        // Thread to sell the positions of the algorithm when their bracket is hit.
+        let positions_handle = tokio::spawn(ordermanager::run(self.clone(), api.clone(), ws_api.clone(), psql.clone()));
+
+        // Thread to receive data from API (websocket) and send it to UnixSocket. The close of
+        // every candle is the last price of the symbol.
+        let symbol = self.symbol.clone();
+        let thread_recv_websocket_data_handle = tokio::spawn(async move {
+            while let Some(n) = datastream.lock().await.recv().await {   
+                ordermanager::publish(&symbol, n.close);
+                if let Err(e) = protocol::write_message(&mut tx, &protocol::Message::Candle((&n).into())).await {
+                    eprintln!("\x1b[31m[Error] Error writing to stream: {}\x1b[0m", e);
+                    break;
+                }
+            }
+        });
+
+        // Time of the last message of the PyExecutor, heartbeats included.
+        let last_message = Arc::new(std::sync::atomic::AtomicU64::new(supervisor::now_sec()));
+        let last_message_clone = last_message.clone();
+     
+        // Thread to read messages from PyExecutor. Returns the error when the PyExecutor gave up.
+        let thread_process_websocket_data = tokio::spawn(async move {
+            loop {
+                let message = protocol::read_message(&mut rx).await;
+                if let Ok(Some(_)) = message {
+                    last_message_clone.store(supervisor::now_sec(), std::sync::atomic::Ordering::SeqCst);
+                }
+
+                let received_signal = match message {
+                    Ok(Some(protocol::Message::Signal(signal))) => signal,
+                    Ok(Some(protocol::Message::Log(line))) => {
+                        if let Err(e) = self.add_log(&line, psql.clone()).await {
+                            eprintln!("{}", e);
+                        }
+                        continue;
+                    },
+                    Ok(Some(protocol::Message::Error(e))) => {
+                        // The PyExecutor continues with the next candle, the error is kept so
+                        // the user can see what went wrong.
+                        eprintln!("\x1b[31m[Error] {} - PyExecutor: {}\x1b[0m", self.id, e);
+                        if let Err(e) = self.set_last_error(Some(&e), psql.clone()).await {
+                            eprintln!("{}", e);
+                        }
+                        continue;
+                    },
+                    Ok(Some(protocol::Message::Fatal(e))) => {
+                        // The PyExecutor gave up, the algorithm is marked as errored instead
+                        // of restarting it.
+                        eprintln!("\x1b[31m[Error] {} - PyExecutor stopped: {}\x1b[0m", self.id, e);
+                        if let Err(e) = self.set_last_error(Some(&e), psql.clone()).await {
+                            eprintln!("{}", e);
+                        }
+                        return Err(e);
+                    },
+                    Ok(Some(_)) => continue,
+                    Ok(None) => return Ok(()),
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        return Ok(());
+                    }
+                };
+
+                // A paused algorithm keeps receiving candles but doesn't trade. The user can see
+                // which orders were skipped.
+                if paused.load(std::sync::atomic::Ordering::SeqCst) {
+                    if received_signal.is_some() {
+                        let line = protocol::LogLine {
+                            stream: protocol::LogStream::Log,
+                            message: "Order skipped, the algorithm is paused.".into(),
+                            candle_timestamp: None,
+                        };
+                        if let Err(e) = self.add_log(&line, psql.clone()).await {
+                            eprintln!("{}", e);
+                        }
+                    }
+                    continue;
+                }
+
+                // Process result and execute order if necessary.
+                match self.process(psql.clone(), received_signal, current_btc_price.clone(), api.clone(), ws_api.clone()).await {
+                    Ok(_) => (),
+                    Err(e) => {
+                        match e {
+                           tradealgorithm::Error::APIError(ee) => {
+                                // The connection to the API is remade by restarting.
+                                eprintln!("\x1b[31m[Error] Error in API while processing: {}\x1b[0m", ee);
+                                return Ok(());
+                           },
+                           _ => {
+                                eprintln!("\x1b[31m[Error] {}\x1b[0m", e);
+                           }
+                        }
+                    }
+                }
+            }
+        });
+
+        Ok(supervisor::Execution {
+            process: process_handle,
+            sender: thread_recv_websocket_data_handle,
+            receiver: thread_process_websocket_data,
+            price: price_handle,
+            positions: positions_handle,
+            last_message,
+        })
+    }
+
+    // Retrieve the candlesticks of the symbol of this algorithm between starttime and endtime.
+    // The klines endpoint returns at most 1000 klines per request so we page through the range.
+    pub async fn fetch_candles(&self, api: Api, interval: &str, starttime: u128, endtime: u128) -> Result<std::vec::Vec<CandleStick>, tradealgorithm::Error> {
+        let mut data = std::vec::Vec::<CandleStick>::new();
+        let mut starttime = starttime;
+
+        loop {
             // Create request parameters.
             let mut params = std::collections::HashMap::<String, String>::new();
-            params.insert("symbol".into(), "BTCUSDT".into());
-            params.insert("interval".into(), self.interval.to_string());
+            params.insert("symbol".into(), self.symbol.to_string());
+            params.insert("interval".into(), interval.to_string());
             params.insert("startTime".into(), starttime.to_string());
             params.insert("endTime".into(), endtime.to_string());
+            params.insert("limit".into(), "1000".into());
 
             // Execute request.
-            match api.klines(&mut params).await {
-                Ok(d) => {
-                    for kl in d {
-                        data.push(CandleStick {
-                            timestamp: serde_json::from_value::<u64>(kl[0].clone())?,
-                            open: serde_json::from_value::<String>(kl[1].clone())?.parse::<f64>()?,
-                            high: serde_json::from_value::<String>(kl[2].clone())?.parse::<f64>()?,
-                            low: serde_json::from_value::<String>(kl[3].clone())?.parse::<f64>()?,
-                            close: serde_json::from_value::<String>(kl[4].clone())?.parse::<f64>()?,
-                            volume: serde_json::from_value::<String>(kl[5].clone())?.parse::<f64>()?,
-                        });
-                    }
+            let klines = api.klines(&mut params).await?;
+            for kl in klines.iter() {
+                data.push(CandleStick::from_kline(kl)?);
+            }
+
+            // Continue after the last received kline until the whole range is retrieved.
+            match data.last() {
+                Some(last) if klines.len() == 1000 && (last.timestamp as u128) < endtime => {
+                    starttime = last.timestamp as u128 + 1;
                 },
-                Err(_) => {
-                    return Err(tradealgorithm::Error::APIError("Could not prepend data".into()));
-                }
-            };
+                _ => break,
+            }
         }
 
-        // Write prepended data to shared memory.
-        let shmem_path = format!("tmp/shmem/{}.bin", self.id);
-        std::fs::remove_file(shmem_path).unwrap_or_default();
-        let serialized_data = serde_json::to_string(&data).expect("Failed to serialize data.");
+        Ok(data)
+    }
+
+    // Start a PyExecutor process for this algorithm. The initial data is shared with the process
+    // through shared memory, afterwards we connect to the UnixSocket of the process.
+    // The channel is the name used for the shared memory and socket files so multiple
+    // processes for the same algorithm (e.g a backtest) don't collide.
+    pub async fn spawn_executor(&self, channel: &str, data: &std::vec::Vec<CandleStick>, run_every_sec: i32) -> Result<(std::process::Child, tokio::net::UnixStream), tradealgorithm::Error> {
+
+        // Write initial data to shared memory.
+        let shmem_path = format!("tmp/shmem/{}.bin", channel);
+        std::fs::remove_file(&shmem_path).unwrap_or_default();
+        let serialized_data = serde_json::to_string(data)?;
         let data_size = serialized_data.len();
         let memfile = std::fs::OpenOptions::new()
             .read(true)
             .write(true)
             .create(true)
-            .open(format!("tmp/shmem/{}.bin", self.id))
-            .expect("Failed to open file");
-        memfile.set_len(data_size as u64)
-            .expect("Failed to set file size");
+            .open(&shmem_path)?;
+        memfile.set_len(data_size as u64)?;
         let mut mapped_data = unsafe {
             MmapOptions::new()
                 .len(data_size)
-                .map_mut(&memfile)
-                .expect("Failed to map file into memory")
+                .map_mut(&memfile)?
         };
         mapped_data[..data_size].copy_from_slice(serialized_data.as_bytes());
 
-
         // Check if PyExecutor binary is legit.
         let pyexecutor_path = "./python-executor";
-        let pyexecutor_bytes = std::fs::read(pyexecutor_path).unwrap();
+        let pyexecutor_bytes = std::fs::read(pyexecutor_path)?;
         let pyexecutor_sha256 = sha256::digest(&pyexecutor_bytes);
 
         // Check if hash of PyExecutor binary matches predefined SHA256 hash.
@@ -257,14 +835,38 @@ impl TradeAlgorithm {
             return Err(tradealgorithm::Error::AlgorithmError("PythonExecutor hash does not match.".into())); 
         }
 
+        // The PyExecutor is killed when it could not be connected to, or when the start-up is
+        // cancelled because the algorithm was stopped meanwhile.
+        struct Executor(Option<std::process::Child>);
+        impl Drop for Executor {
+            fn drop(&mut self) {
+                if let Some(mut process_handle) = self.0.take() {
+                    process_handle.kill().unwrap_or_default();
+                    process_handle.wait().unwrap_or_default();
+                }
+            }
+        }
+
         // Create a new process to execute algorithm.
         let process_handle = std::process::Command::new(pyexecutor_path)
-        .args(&[self.id.to_string(), self.run_every_sec.to_string()])
+        .args(&[
+            self.id.to_string(),
+            run_every_sec.to_string(),
+            channel.to_string(),
+            config::EXECUTOR_TIMEOUT_MS.to_string(),
+            config::EXECUTOR_MAX_CONSECUTIVE_TIMEOUTS.to_string(),
+            format!("--memory-limit-mb={}", *config::EXECUTOR_MEMORY_LIMIT_MB),
+            format!("--cpu-limit-sec={}", *config::EXECUTOR_CPU_LIMIT_SEC),
+            format!("--max-history={}", self.max_history),
+            format!("--data-format={}", self.data_format),
+        ])
+        .args(self.run_on_close.then_some("--run-on-close"))
         .spawn()?;
+        let mut executor = Executor(Some(process_handle));
 
         // Create a stream so we can write data to the PyExecutor and receive the
         // result back.
-        let unix_socket_path = &*format!("tmp/sockets/{}.sock", self.id);
+        let unix_socket_path = &*format!("tmp/sockets/{}.sock", channel);
 
         // Function to connect to UnixSocket and retry 3 times if failure.
         async fn connect_to_unix_socket(unix_socket_path: &str) -> Result<tokio::net::UnixStream, tradealgorithm::Error> {
@@ -284,269 +886,186 @@ impl TradeAlgorithm {
             Err(tradealgorithm::Error::StreamError("Could not connect to UnixSocket after 3 tries.".into()))
         }
 
-        // Connect to UnixSocket and split into receiver and transmitter so we can
-        // send data from the API to PyExecutor and receive the result.
-        let unix_stream = connect_to_unix_socket(unix_socket_path).await?;
-        let (mut rx, mut tx) = unix_stream.into_split();
-
-        // Current BTC price to add to history of algorithms.
-        let current_btc_price = api.get_btc_price().await?;
-        let current_btc_price = Arc::new(Mutex::new(current_btc_price));
-
-        // Thread to update the current_btc_price every 10 seconds.
-        let api_clone = api.clone();
-        let current_btc_price_clone = current_btc_price.clone();
-        tokio::spawn(async move {
-            loop {
-                tokio::time::sleep(tokio::time::Duration::from_millis(10000)).await;
-
-                let mut current_btc_price_guard = current_btc_price_clone.lock().await;
-                *current_btc_price_guard = match api_clone.clone().get_btc_price().await {
-                    Ok(p) => p,
-                    Err(_) => continue,
-                };
-                drop(current_btc_price_guard);
-            }
-        });
-
-        // Thread to receive data from API (websocket) and send it to UnixSocket.
-        let thread_recv_websocket_data_handle = tokio::spawn(async move {
-            while let Some(n) = datastream.lock().await.recv().await {   
-                let ready = tx.ready(Interest::READABLE | Interest::WRITABLE).await.expect("Could not check if UnixSocket is ready to write.");
-                if ready.is_writable() {
-                    if let Err(e) = tx.write_all(serde_json::json!(n).to_string().as_bytes()).await  {
-                        if e.kind() != std::io::ErrorKind::BrokenPipe {
-                            eprintln!("\x1b[31m[Error] {}\x1b[0m",e);
-                        } else {
-                            panic!("Error writing to stream");
-                        }
+        // Function to wait until the PyExecutor reports it is ready to receive candles.
+        async fn wait_until_ready(unix_stream: &mut tokio::net::UnixStream) -> Result<(), tradealgorithm::Error> {
+            let ready = tokio::time::timeout(tokio::time::Duration::from_millis(5000), async {
+                loop {
+                    match protocol::read_message(unix_stream).await? {
+                        Some(protocol::Message::Ready) => return Ok(()),
+                        Some(protocol::Message::Error(e)) | Some(protocol::Message::Fatal(e)) => return Err(tradealgorithm::Error::ExecutorError(e)),
+                        Some(_) => continue,
+                        None => return Err(tradealgorithm::Error::StreamError("PyExecutor closed the connection".into())),
                     }
                 }
+            }).await;
+
+            match ready {
+                Ok(r) => r,
+                Err(_) => Err(tradealgorithm::Error::StreamError("PyExecutor did not become ready in time".into())),
             }
-        });
-     
-        // Thread to read result from PyExecutor.
-        let thread_process_websocket_data = tokio::spawn(async move {
-            loop {
+        }
 
-                let mut buffer = [0; 1024];
-                match rx.read(&mut buffer).await {
-                    Ok(0) => {
-                        break;
-                    },
-                    Ok(n) => {
+        // Connect, wait until the PyExecutor is ready and pass the parameters of the algorithm.
+        let connected = match connect_to_unix_socket(unix_socket_path).await {
+            Ok(mut unix_stream) => match wait_until_ready(&mut unix_stream).await {
+                Ok(_) => protocol::write_message(&mut unix_stream, &protocol::Message::Params(self.params.clone())).await
+                    .map(|_| unix_stream)
+                    .map_err(|e| e.into()),
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
+        };
 
-                        let received_result = match String::from_utf8_lossy(&buffer[..n]).parse::<f64>() {
-                            Ok(r) => r,
-                            Err(_) => {
-                                panic!("Could not parse result to f64.");
-                            }
-                        };
+        let unix_stream = connected?;
+        Ok((executor.0.take().unwrap(), unix_stream))
+    }
 
+    // Stop the trading algorithm. An algorithm which is still marked as running without
+    // PyExecutor, because the server stopped, is marked as stopped.
+    pub async fn stop(&self, psql: Psql) -> Result<(), tradealgorithm::Error> {
+        if supervisor::is_supervised(&self.id).await {
+            return supervisor::stop(&self.id).await;
+        }
 
-                        // Process result and execute order if necessary.
-                        match self.process(psql.clone(), received_result, current_btc_price.clone(), api.clone(), ws_send.clone()).await {
-                            Ok(_) => (),
-                            Err(e) => {
-                                match e {
-                                   tradealgorithm::Error::APIError(ee) => {
-                                        eprintln!("\x1b[31m[Error] Error in API while processing: {}\x1b[0m", ee);
-                                        panic!("Error in API while processing: {}", ee);
-                                   },
-                                   _ => {
-                                        eprintln!("\x1b[31m[Error] {}\x1b[0m", e);
-                                   }
-                                }
-                            }
-                        }
-                    },
-                    Err(e) => {
-                        eprintln!("\x1b[31m[Error] {}\x1b[0m", e);
-                    }
-                }
-            }
-        });
+        let state = self.get_state(psql.clone()).await?;
+        if !state.is_active() {
+            return Err(tradealgorithm::Error::StateError("Algorithm is not running".into()));
+        }
 
-        Ok((process_handle, thread_recv_websocket_data_handle, thread_process_websocket_data))
+        if state != lifecycle::State::Stopping {
+            self.transition(lifecycle::State::Stopping, "Stopped by user.", psql.clone()).await?;
+        }
+        self.transition(lifecycle::State::Stopped, "PyExecutor was not running.", psql.clone()).await?;
+        self.set_desired_running(false, psql).await
     }
 
-    // Stop the trading algorithm.
-    pub async fn stop(&self) -> Result<(), tradealgorithm::Error> {
-        let mut shared_process_handles = PROCESS_HANDLES.lock().await;
-        
-        // Retrieve process handle and kill if found.
-        match shared_process_handles.remove(&self.id) {
-            Some(mut ph) => {
-                ph.kill()?;
-            },
-            None => {
-                return Err(tradealgorithm::Error::AlgorithmError("Algorithm is not running".into()));
-            }
-        };
+    // Pause the trading algorithm. It keeps receiving candles but its orders aren't placed.
+    pub async fn pause(&self, psql: Psql) -> Result<(), tradealgorithm::Error> {
+        supervisor::pause(self, psql).await
+    }
 
-        Ok(())
+    // Continue placing the orders of a paused algorithm.
+    pub async fn resume(&self, psql: Psql) -> Result<(), tradealgorithm::Error> {
+        supervisor::unpause(self, psql).await
     }
 
-    // Check if the algorithm is currently running or not.
-    pub async fn active(&self) -> bool {
-        let shared_process_handles = PROCESS_HANDLES.lock().await;
-            
-        match shared_process_handles.get(&self.id) {
-            Some(_) => true,
-            None => false,
-        }
+    // Check if the algorithm is currently running or not, according to the state it had when
+    // it was retrieved. An algorithm waiting to be restarted is running.
+    pub fn active(&self) -> bool {
+        self.state.is_active()
     }
     
-    // Here we process the result of the Python function. If r > 0 it means we want to buy r
-    // amount. If r < 0 it means we want to sell r amount. r == 0 means do nothing.
-    async fn process(&self, psql: Psql, r: f64, current_btc_price: Arc<Mutex<f64>>, api: Api, ws_send: Arc<Mutex<SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>>>) -> Result<(), tradealgorithm::Error> {
-        if r > 0f64 {
-            return self.buy(psql, r, current_btc_price, api, ws_send).await;
-        }
-        if r < 0f64 {
-            return self.sell(psql, r, current_btc_price, api, ws_send).await;
+    // Here we process the order-signal of the Python function and place the order it
+    // describes. No signal means do nothing.
+    async fn process(&self, psql: Psql, signal: Option<protocol::Signal>, current_btc_price: Arc<Mutex<f64>>, api: Api, ws_api: Arc<api::wsapi::WsApi>) -> Result<(), tradealgorithm::Error> {
+        match signal {
+            Some(signal) => self.place_signal(psql, signal, current_btc_price, api, ws_api).await,
+            None => Ok(()),
         }
-       
-        Ok(())
     }
 
-    // Buy.
-    async fn buy(&self, psql: Psql, r: f64, current_btc_price: Arc<Mutex<f64>>, api: Api, ws_send: Arc<Mutex<SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>>>) -> Result<(), tradealgorithm::Error> {
-      
-        // Generate OrderID.
-        let order_id : String = rand::thread_rng()
+    // Place the order of a signal. The stop-loss and take-profit are placed as orders closing
+    // the position once it is filled, as an OCO when there are both. A bracket is watched by
+    // the server, see ordermanager.rs.
+    async fn place_signal(&self, psql: Psql, signal: protocol::Signal, current_btc_price: Arc<Mutex<f64>>, api: Api, ws_api: Arc<api::wsapi::WsApi>) -> Result<(), tradealgorithm::Error> {
+
+        if let Err(e) = signal.validate() {
+            return Err(tradealgorithm::Error::PythonCodeError(format!("{} - Invalid signal: {}", self.id, e)));
+        }
+
+        // Generate OrderID. The tag of the signal is used as prefix.
+        let random_id : String = rand::thread_rng()
         .sample_iter(&rand::distributions::Alphanumeric)
         .take(12)
         .map(char::from)
         .collect();
+        let order_id = match &signal.tag {
+            Some(tag) => format!("{}-{}", tag, random_id),
+            None => random_id,
+        };
 
-        // Create order parameters.
-        let mut params = std::collections::HashMap::<String, String>::new();
-        params.insert("symbol".into(), "BTCUSDT".into());
-        params.insert("side".into(), "BUY".into());
-        params.insert("type".into(), "MARKET".into());
-        params.insert("quantity".into(), r.to_string());
-        params.insert("newClientOrderId".into(), order_id.to_string());
-      
         let current_btc_price_guard = current_btc_price.lock().await;
         let current_btc_price = *current_btc_price_guard;
         drop(current_btc_price_guard);
 
-        // Order amount in USDT.
-        let usdt = r * current_btc_price;
+        // Expected price of the order and its amount in base and quote asset.
+        let price = signal.limit_price.unwrap_or(current_btc_price);
+        let (quantity, quote) = match (signal.quantity, signal.quote_quantity) {
+            (Some(q), _) => (q, q * price),
+            (None, Some(q)) => (q / price, q),
+            (None, None) => {
+                return Err(tradealgorithm::Error::PythonCodeError(format!("{} - Signal without quantity.", self.id)));
+            }
+        };
 
-        // Get current USDT funds.
-        let (current_funds_usdt, _) = self.get_current_funds(psql.clone()).await?;
+        // Create the order. Binance only accepts an amount in the quote asset for MARKET orders.
+        let kind = match (signal.order_type, signal.stop_price) {
+            (protocol::OrderType::Market, _) => api::order::OrderKind::Market { quantity: signal.quantity, quote_quantity: signal.quote_quantity },
+            (protocol::OrderType::StopLossLimit, Some(stop_price)) => api::order::OrderKind::StopLossLimit { quantity, price, stop_price, time_in_force: signal.time_in_force },
+            (protocol::OrderType::TakeProfitLimit, Some(stop_price)) => api::order::OrderKind::TakeProfitLimit { quantity, price, stop_price, time_in_force: signal.time_in_force },
+            _ => api::order::OrderKind::Limit { quantity, price, time_in_force: signal.time_in_force },
+        };
+        let mut order = api::order::OrderRequest {
+            symbol: self.symbol.clone(),
+            side: signal.side,
+            kind,
+            client_order_id: order_id.to_string(),
+        };
 
-        // Check if algorithm has enough USDT assigned.
-        if current_funds_usdt - usdt < 0f64 {
+        // Round the order to the rules of the symbol. An order the exchange would refuse, e.g
+        // below the minimum notional, isn't sent.
+        let rules = api.symbol_rules(&self.symbol).await?;
+        if let Err(e) = rules.normalize(&mut order, price) {
+            return Err(tradealgorithm::Error::AlgorithmError(format!("{} - Invalid order: {}", self.id, e)));
+        }
+        let (quantity, quote) = match order.quantity() {
+            Some(q) => (q, q * price),
+            None => (quantity, quote),
+        };
+
+        // Get current funds.
+        let (current_funds_usdt, current_funds_btc) = self.get_current_funds(psql.clone()).await?;
+
+        // Check if algorithm has enough funds assigned.
+        let insufficient = match signal.side {
+            protocol::Side::Buy => current_funds_usdt - quote < 0f64,
+            protocol::Side::Sell => current_funds_btc - quantity < 0f64,
+        };
+        if insufficient {
             return Err(tradealgorithm::Error::AlgorithmError(format!("{} - Insufficient algorithm funds.", self.id)));
         }
 
         // Check if account has enough funds.
-        if !self.check_funds(api.clone(), "BUY", r, usdt).await? {
+        if !self.check_funds(api.clone(), &signal.side.to_string(), quantity, quote).await? {
             return Err(tradealgorithm::Error::AlgorithmError(format!("{} - Insufficient account funds.", self.id)));
         }
 
-        // Execute order.
-        match api.ws_order(&mut params, ws_send).await {
-            Ok(_) => {
-                println!("\x1b[32m[order] {} - Buying USDT {}\x1b[0m", self.id, usdt);
-            },
-            Err(e) => {
-                return Err(e.into());
-            }
-        };
-
-        // Register order in database.
-        let query = psql.lock().await
-           .query("
-                INSERT INTO history 
-                    (algorithm_id, order_id, action, btc, usdt, btc_price)
-                VALUES
-                    ($1, $2, 'BUY', $3, $4, $5)
-            ", &[&self.id, &order_id, super::sqlf64!(r), super::sqlf64!(usdt * -1f64), super::sqlf64!(current_btc_price)]).await;
-
-        match query {
-            Ok(_) => (),
-            Err(e) => {
-                return Err(tradealgorithm::Error::DatabaseError(format!("{}", e)));
-            }
+        // Execute order. The fills of the order are registered in the history.
+        let status = orders::place(&self.id, &order, api.clone(), &ws_api, psql.clone()).await?;
+        match signal.side {
+            protocol::Side::Buy => println!("\x1b[32m[order] {} - Buying {} for {} {}: {}\x1b[0m", self.id, self.symbol.base, quote, self.symbol.quote, status),
+            protocol::Side::Sell => println!("\x1b[32m[order] {} - Selling {} for {} {}: {}\x1b[0m", self.id, self.symbol.base, quote, self.symbol.quote, status),
         }
 
-       Ok(())
-    }
-    
-    // Sell.
-    async fn sell(&self, psql: Psql, r: f64, current_btc_price: Arc<Mutex<f64>>, api: Api, ws_send: Arc<Mutex<SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>>>) -> Result<(), tradealgorithm::Error> {
-        
-        // Generate OrderID.
-        let order_id : String = rand::thread_rng()
-        .sample_iter(&rand::distributions::Alphanumeric)
-        .take(12)
-        .map(char::from)
-        .collect();
-        
-        // Create order parameters.
-        let mut params = std::collections::HashMap::<String, String>::new();
-        params.insert("symbol".into(), "BTCUSDT".into());
-        params.insert("side".into(), "SELL".into());
-        params.insert("type".into(), "MARKET".into());
-        params.insert("quantity".into(), (r * -1f64).to_string());
-        params.insert("newClientOrderId".into(), order_id.to_string());
-        
-        let current_btc_price_guard = current_btc_price.lock().await;
-        let current_btc_price = *current_btc_price_guard;
-        drop(current_btc_price_guard);
-        
-        // Order amount in USDT.
-        let usdt = r * current_btc_price;
-        
-        // Get current BTC funds.
-        let (_, current_funds_btc) = self.get_current_funds(psql.clone()).await?;
-   
-        // Check if algorithm has enough BTC.
-        if current_funds_btc - (r * -1f64) < 0f64 {
-            return Err(tradealgorithm::Error::AlgorithmError(format!("{} - Insufficient algorithm funds.", self.id)));
+        // The bracket of a buy is watched by the server.
+        if let (protocol::Side::Buy, Some(bracket)) = (signal.side, &signal.bracket) {
+            ordermanager::open(&self.id, &order, bracket, psql.clone()).await?;
         }
-        
-        // Check if account has enough funds.
-        if !self.check_funds(api.clone(), "SELL", r * -1f64, usdt).await? {
-            return Err(tradealgorithm::Error::AlgorithmError(format!("{} - Insufficient account funds.", self.id)));
+
+        // The stop-loss and take-profit are placed once the order is filled, for the filled
+        // quantity, see ordermanager.rs.
+        if signal.stop_loss.is_some() || signal.take_profit.is_some() {
+            ordermanager::protect(&self.id, &order, signal.stop_loss, signal.take_profit, psql.clone()).await?;
         }
-        
-        // Execute order.
-        match api.ws_order(&mut params, ws_send).await {
-            Ok(_) => {
-                println!("\x1b[32m[order] {} - Selling USDT {}\x1b[0m", self.id, usdt);
-            },
-            Err(e) => {
-                return Err(e.into());
-            }
-        };
-       
-        // Register order in database.
-        let query = psql.lock().await
-           .query("
-                INSERT INTO history 
-                    (algorithm_id, order_id, action, btc, usdt, btc_price)
-                VALUES
-                    ($1, $2, 'SELL', $3, $4, $5)
-            ", &[&self.id, &order_id, super::sqlf64!(r), super::sqlf64!(usdt * -1f64), super::sqlf64!(current_btc_price)]).await;
 
-       match query {
-           Ok(_) => { return Ok(())  },
-           Err(e) => {
-               return Err(tradealgorithm::Error::DatabaseError(e.to_string()));
-           }
-       }
+        Ok(())
     }
 
-    // Make first BTC order.
+    // Make first order of the base asset, paid with the given amount of the quote asset.
     pub async fn first_btc_order(&self, usdt: f64, psql: Psql, api: Api) -> Result<(), tradealgorithm::Error> {
         
-        // Get BTC price.
-        let btc_price = api.get_btc_price().await?;
+        // Get price of the base asset.
+        let btc_price = api.get_price(&self.symbol).await?;
         let usdt_in_btc = usdt / btc_price;
         
         // Generate OrderID.
@@ -556,59 +1075,55 @@ impl TradeAlgorithm {
         .map(char::from)
         .collect();
         
-        // Create order parameters.
-        let mut params = std::collections::HashMap::<String, String>::new();
-        params.insert("symbol".into(), "BTCUSDT".into());
-        params.insert("side".into(), "BUY".into());
-        params.insert("type".into(), "MARKET".into());
-        params.insert("quantity".into(), format!("{:.5}", usdt_in_btc));
-        params.insert("newClientOrderId".into(), order_id.to_string());
+        // Create order, rounded to the rules of the symbol.
+        let mut order = api::order::OrderRequest {
+            symbol: self.symbol.clone(),
+            side: protocol::Side::Buy,
+            kind: api::order::OrderKind::Market { quantity: Some(usdt_in_btc), quote_quantity: None },
+            client_order_id: order_id.to_string(),
+        };
+        if let Err(e) = api.symbol_rules(&self.symbol).await?.normalize(&mut order, btc_price) {
+            return Err(tradealgorithm::Error::AlgorithmError(format!("Invalid order: {}", e)));
+        }
+
+        // The funds are checked for the rounded order.
+        let quantity = order.quantity().unwrap_or(usdt_in_btc);
+        let usdt = quantity * btc_price;
         
-        // Get current USDT funds.
+        // Get current quote funds.
         let (current_funds_usdt, _) = self.get_current_funds(psql.clone()).await?;
 
-        // Check if algorithm has enough USDT assigned.
+        // Check if algorithm has enough quote funds assigned.
         if current_funds_usdt - usdt < 0f64 {
             return Err(tradealgorithm::Error::AlgorithmError("Insufficient algorithm funds".into()));
         }
 
         // Check if account has enough funds.
-        if !self.check_funds(api.clone(), "BUY", usdt_in_btc, usdt).await? {
+        if !self.check_funds(api.clone(), "BUY", quantity, usdt).await? {
             return Err(tradealgorithm::Error::AlgorithmError("Insufficient account funds".into()));
         }
 
-        // Execute order.
-        api.order(&mut params).await?;
-
-        // Register order in database.
-        let query = psql.lock().await
-           .query("
-                INSERT INTO history 
-                    (algorithm_id, order_id, action, btc, usdt, btc_price)
-                VALUES
-                    ($1, $2, 'BUY', $3, $4, $5)
-            ", &[&self.id, &order_id, super::sqlf64!(usdt_in_btc), super::sqlf64!(usdt * -1f64), super::sqlf64!(btc_price)]).await;
+        // Execute order. The fills of the order are registered in the history.
+        orders::insert(&self.id, &order, psql.clone()).await?;
+        let response = api.place_order(&order).await;
+        orders::record(&self.id, &order, response, psql).await?;
 
-        match query {
-            Ok(_) => Ok(()),
-            Err(e) => {
-                return Err(tradealgorithm::Error::DatabaseError(e.to_string()));
-            }
-        }
+        Ok(())
     }
 
-    // Check if there are enough funds in the account to make the order.
-    pub async fn check_funds(&self, api: Api, side: &str, quantity: f64, usdt_price: f64) -> Result<bool, tradealgorithm::Error> {
-       let balance = api.account_balance().await?;
+    // Check if there are enough funds in the account to make the order. For a buy we need
+    // enough of the quote asset, for a sell enough of the base asset.
+    pub async fn check_funds(&self, api: Api, side: &str, quantity: f64, quote: f64) -> Result<bool, tradealgorithm::Error> {
+       let balances = api.account_balance().await?;
 
         match side {
             "BUY" => {
-                if balance.0 < usdt_price {
+                if api::balance_of(&balances, &self.symbol.quote) < quote {
                     return Ok(false);
                 }
             },
             "SELL" => {
-                if balance.1 < quantity {
+                if api::balance_of(&balances, &self.symbol.base) < quantity {
                     return Ok(false);
                 }
             },
@@ -694,7 +1209,7 @@ impl TradeAlgorithm {
         let query = psql.lock().await
            .query("
                 SELECT
-                    order_id, action, btc, usdt, btc_price, created_at::TEXT
+                    order_id, action, btc, usdt, btc_price, commission, commission_asset, created_at::TEXT
                 FROM
                     history
                 WHERE
@@ -723,6 +1238,8 @@ impl TradeAlgorithm {
             btc: f64,
             usdt: f64,
             btc_price: f64,
+            commission: f64,
+            commission_asset: Option<String>,
             created_at: String,
         }
 
@@ -734,6 +1251,8 @@ impl TradeAlgorithm {
                 btc: sqldec!(row.get::<_, Decimal>("btc")),
                 usdt: sqldec!(row.get::<_, Decimal>("usdt")),
                 btc_price: sqldec!(row.get::<_, Decimal>("btc_price")),
+                commission: sqldec!(row.get::<_, Decimal>("commission")),
+                commission_asset: row.get("commission_asset"),
                 created_at: row.get("created_at"),
             };
 
@@ -743,25 +1262,17 @@ impl TradeAlgorithm {
         Ok(serde_json::json!(data))
     }
 
-    pub async fn insert_btc_price(api: Api, psql: Psql) -> Result<(), tradealgorithm::Error> {
-        // Get BTC price.
-        let btc_price = api.get_btc_price().await?;
-
-        let sql = format!("
-            DO
-            $$
-            declare f record;
-            begin
-                for f in SELECT DISTINCT id from algorithms
-                    loop
-                        insert into history(algorithm_id, btc, usdt, btc_price) values (f.id, 0.0, 0.00, {});
-                    end loop;
-            end
-            $$;
-        ", btc_price);
+    // Store a line of output of the Python code.
+    pub async fn add_log(&self, line: &protocol::LogLine, psql: Psql) -> Result<(), tradealgorithm::Error> {
+        let candle_timestamp = line.candle_timestamp.map(|t| t as i64);
 
         let query = psql.lock().await
-           .query(&*sql, &[]).await;
+           .query("
+                INSERT INTO algorithm_logs
+                    (algorithm_id, stream, message, candle_timestamp)
+                VALUES
+                    ($1, $2, $3, $4)
+            ", &[&self.id, &line.stream.to_string(), &line.message, &candle_timestamp]).await;
 
         match query {
             Ok(_) => Ok(()),
@@ -771,12 +1282,106 @@ impl TradeAlgorithm {
         }
     }
 
+    // Get the output of the Python code, newest first. before is the id of the oldest line
+    // already retrieved.
+    pub async fn get_logs(&self, psql: Psql, before: Option<i32>) -> Result<serde_json::value::Value, tradealgorithm::Error> {
+        let query = psql.lock().await
+           .query("
+                SELECT
+                    id, stream, message, candle_timestamp, created_at::TEXT
+                FROM
+                    algorithm_logs
+                WHERE
+                    algorithm_id = $1
+                AND
+                    ($2::INTEGER IS NULL OR id < $2)
+                ORDER BY
+                    id DESC
+                LIMIT 100
+            ", &[&self.id, &before]).await;
+
+        let rows = match query {
+            Ok(q) => q,
+            Err(e) => {
+                return Err(tradealgorithm::Error::DatabaseError(e.to_string()));
+            }
+        };
+
+        #[derive(Serialize)]
+        struct Data {
+            id: i32,
+            stream: String,
+            message: String,
+            candle_timestamp: Option<i64>,
+            created_at: String,
+        }
+
+        let data = rows.iter().map(|row| Data {
+            id: row.get("id"),
+            stream: row.get("stream"),
+            message: row.get("message"),
+            candle_timestamp: row.get("candle_timestamp"),
+            created_at: row.get("created_at"),
+        }).collect::<std::vec::Vec<Data>>();
+
+        Ok(serde_json::json!(data))
+    }
+
+    // Insert the current price of the traded symbol into the history of all algorithms.
+    pub async fn insert_prices(api: Api, psql: Psql) -> Result<(), tradealgorithm::Error> {
+        // Get all traded symbols.
+        let query = psql.lock().await
+           .query("SELECT DISTINCT ON (symbol) symbol, base_asset, quote_asset FROM algorithms ORDER BY symbol, base_asset", &[]).await;
+
+        let rows = match query {
+            Ok(q) => q,
+            Err(e) => {
+                return Err(tradealgorithm::Error::DatabaseError(e.to_string()));
+            }
+        };
+
+        for row in rows {
+            let symbol : String = row.get("symbol");
+            let api_symbol = match api::Symbol::from_stored(&symbol, row.get("base_asset"), row.get("quote_asset")) {
+                Ok(s) => s,
+                Err(_) => continue,
+            };
+
+            // A symbol without price e.g because it was delisted doesn't stop the others.
+            let price = match api.get_price(&api_symbol).await {
+                Ok(p) => p,
+                Err(e) => {
+                    eprintln!("\x1b[31m[Error] Could not retrieve price of {}: {}\x1b[0m", symbol, e);
+                    continue;
+                }
+            };
+
+            let query = psql.lock().await
+               .query("
+                    INSERT INTO history
+                        (algorithm_id, btc, usdt, btc_price)
+                    SELECT
+                        id, 0.0, 0.00, $1
+                    FROM
+                        algorithms
+                    WHERE
+                        symbol = $2
+                ", &[super::sqlf64!(price), &symbol]).await;
+
+            if let Err(e) = query {
+                return Err(tradealgorithm::Error::DatabaseError(e.to_string()));
+            }
+        }
+
+        Ok(())
+    }
+
 
     pub async fn reset(&self, psql: Psql, api: Api) -> Result<(), tradealgorithm::Error> {
 
         // Check if algorithm is still running.
-        if self.active().await {
-            return Err(tradealgorithm::Error::AlgorithmError("Algorithm is still running".into()));
+        if self.get_state(psql.clone()).await?.is_active() {
+            return Err(tradealgorithm::Error::StateError("Algorithm is still running".into()));
         }
 
         // Get current total balance in USDT.
@@ -804,7 +1409,12 @@ impl TradeAlgorithm {
                     ", &[super::sqlf64!(current_balance), &self.id]).await;
 
                 match query {
-                    Ok(_) => Ok(()),
+                    Ok(_) => {
+                        // The algorithm starts over, so does the state of its Python-script.
+                        // Its positions are no longer watched.
+                        self.remove_state();
+                        ordermanager::close_all(&self.id, "Algorithm reset.", psql).await
+                    },
                     Err(e) => {
                         return Err(tradealgorithm::Error::DatabaseError(e.to_string()));
                     }
@@ -816,6 +1426,11 @@ impl TradeAlgorithm {
         }
     }
     
+    // Remove the snapshot of the state the Python-script keeps between executions.
+    pub fn remove_state(&self) {
+        std::fs::remove_file(format!("tmp/state/{}.json", self.id)).unwrap_or_default();
+    }
+
     pub fn get_code(&self) -> Result<String, tradealgorithm::Error> {
         let python_code = match std::fs::read_to_string(format!("trading_algos/{}.py", self.id)) {
             Ok(code) => code,
@@ -838,6 +1453,9 @@ pub enum Error {
     DatabaseError(String),
     PythonCodeError(String),
     StreamError(String),
+    ExecutorError(protocol::ExecutorError),
+    // The algorithm is in a state which doesn't allow the action.
+    StateError(String),
 }
 
 impl std::error::Error for Error {}
@@ -878,6 +1496,12 @@ impl From<tokio_postgres::Error> for Error {
     }
 }
 
+impl From<protocol::Error> for Error {
+    fn from(e: protocol::Error) -> Self {
+        Error::StreamError(e.to_string())
+    }
+}
+
 impl From<api::Error> for Error {
     fn from(e: api::Error) -> Self {
         Error::APIError(e.to_string())
@@ -893,7 +1517,34 @@ impl std::fmt::Display for Error {
             Error::PythonCodeError(error_msg) => write!(f, "\x1b[31m[Error] TradeAlgorithm - PythonCodeError: {}\x1b[0m", error_msg),
             Error::AlgorithmError(error_msg) => write!(f, "\x1b[31m[Error] TradeAlgorithm - AlgorithmError: {}\x1b[0m", error_msg),
             Error::StreamError(error_msg) => write!(f, "\x1b[31m[Error] TradeAlgorithm - StreamError: {}\x1b[0m", error_msg),
+            Error::ExecutorError(error) => write!(f, "\x1b[31m[Error] TradeAlgorithm - ExecutorError: {}\x1b[0m", error),
+            Error::StateError(error_msg) => write!(f, "\x1b[31m[Error] TradeAlgorithm - StateError: {}\x1b[0m", error_msg),
         }
     }
 }
 
+
+
+// Testing the settings of algorithms.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_settings() {
+        let settings: AlgorithmSettings = serde_json::from_str("{}").unwrap();
+        assert_eq!(settings.exchange, None);
+        assert_eq!(settings.params, serde_json::json!({}));
+        assert_eq!(settings.max_history, *config::DEFAULT_MAX_HISTORY);
+        assert!(!settings.run_on_close);
+        assert_eq!(settings.data_format, "list");
+
+        let settings: AlgorithmSettings = serde_json::from_str(r#"{"symbol": "ETHBTC", "exchange": "paper", "params": {"fast": 5}, "max_history": 50, "run_on_close": true, "data_format": "pandas"}"#).unwrap();
+        assert_eq!(settings.symbol, api::Symbol::default());
+        assert_eq!(settings.exchange, Some(api::ExchangeKind::Paper));
+        assert_eq!(settings.params["fast"], 5);
+        assert_eq!((settings.max_history, settings.run_on_close, &*settings.data_format), (50, true, "pandas"));
+
+        assert!(serde_json::from_str::<AlgorithmSettings>(r#"{"exchange": "kraken"}"#).is_err());
+    }
+}
@@ -28,14 +28,16 @@ pub async fn handle_websocket(ws_stream: TcpStream, psql: Psql) -> Result<(), we
                 let request : WebsocketRequest = serde_json::from_str(&*data)?;
 
                 // Create API instance for this request.
-                let mut api = create_api_instance();
+                let mut api = create_api_instance(api::ExchangeKind::Binance);
                 if let Some(params) = request.clone().params {
                     if let Some(session_token) = params.get("session_token") {
+                        let exchange = api::user_exchange(session_token, psql.clone()).await?;
+                        api = create_api_instance(exchange);
                         api.auth(session_token, psql.clone()).await?;
                     }
                 }
 
-                let api = Arc::new(api);
+                let api : Api = Arc::from(api);
 
 
                 if let Err(e) = process_request(request.clone(), ws_sender.clone(), psql.clone(), api.clone()).await {
@@ -72,6 +74,7 @@ pub async fn process_request(request: WebsocketRequest, ws_sender: WsSender, psq
     match &*request.action {
         "btc-candlestick" => btc_candlestick(ws_sender, request.params, api).await,
         "algorithm-stats" => algorithm_stats(ws_sender, request.params, psql).await,
+        "algorithm-logs" => algorithm_logs(ws_sender, &request.session_token, request.params, psql).await,
         _ => Err(websocket::Error::WebsocketError("Unknown action".into())),
     }
 
@@ -80,7 +83,7 @@ pub async fn process_request(request: WebsocketRequest, ws_sender: WsSender, psq
 pub async fn btc_candlestick(ws_sender: WsSender, params: Option<std::collections::HashMap<String, String>>, api: Api) -> Result<(), websocket::Error> {
 
     // Get parameters for this function.
-    let interval = match params {
+    let interval = match params.clone() {
         Some(p) => {
             if let Some(val) = p.get(&"interval".to_string()) {
                 val.to_string()
@@ -93,6 +96,12 @@ pub async fn btc_candlestick(ws_sender: WsSender, params: Option<std::collection
         }
     };
 
+    // Symbol is optional and defaults to BTCUSDT.
+    let symbol = match params.and_then(|p| p.get("symbol").cloned()) {
+        Some(s) => s.parse::<api::Symbol>().map_err(|e| websocket::Error::ParseError(e.into()))?,
+        None => api::Symbol::default(),
+    };
+
     // Make a transmitter and receiver. The transmitter is passed to the Exchange API so the
     // receiver can send the incoming responsed back to the websocket cliet.
     let (tx, rx) = mpsc::channel::<tradealgorithm::CandleStick>(10);
@@ -102,7 +111,7 @@ pub async fn btc_candlestick(ws_sender: WsSender, params: Option<std::collection
     // Start websocket API of exchange and pass the transmitter as parameter
     // so the incoming data is send to the algorithm.
     tokio::spawn(async move {
-        if let Err(e) = api.ws_kline(tx, interval).await {
+        if let Err(e) = api.ws_kline(tx, &symbol, interval).await {
             eprintln!("{}", e);
         }
     }); 
@@ -288,6 +297,82 @@ pub async fn algorithm_stats(ws_sender: WsSender, params: Option<std::collection
     Ok(())
 }
 
+// Send new lines of output of the Python code of an algorithm.
+pub async fn algorithm_logs(ws_sender: WsSender, session_token: &str, params: Option<std::collections::HashMap<String, String>>, psql: Psql) -> Result<(), websocket::Error> {
+
+    // Get parameters for this function.
+    let algorithm_id = match params.as_ref().and_then(|p| p.get("id")) {
+        Some(id) => id.to_string(),
+        None => {
+            return Err(websocket::Error::WebsocketError("Required parameter not given.".into()));
+        }
+    };
+
+    // The output can contain anything the code prints, only the owner can read it.
+    match http::validate_session_token(session_token, http::DBTable::Algorithm(&algorithm_id), psql.clone()).await {
+        Ok(true) => (),
+        _ => {
+            return Err(websocket::Error::WebsocketError("Algorithm does not belong to user.".into()));
+        }
+    }
+
+    // PostgreSQL connection.
+    let (client, mut connection) = tokio_postgres::connect(&format!("host={} user={} password={} dbname={}",
+        config::DB_HOST.as_str(),
+        config::DB_USER.as_str(),
+        config::DB_PASS.as_str(),
+        config::DB_NAME.as_str()
+    ), NoTls).await.map_err(|e| websocket::Error::StreamError(e.to_string()))?;
+
+    // Make transmitter and receiver.
+    let (tx, rx) = futures_channel::mpsc::unbounded();
+    let stream = stream::poll_fn(move |cx| connection.poll_message(cx)).map_err(|e| panic!("{}", e));
+    let listener_connection = stream.forward(tx).map(|r| r.unwrap());
+    tokio::spawn(listener_connection);
+
+    // Execute listen.
+    if let Err(e) = client.query("LISTEN algorithm_log_inserted", &[]).await {
+        eprintln!("{}", e);
+    }
+
+    // Wait for notifications and send the lines of this algorithm to the websocket client.
+    rx.filter_map(|m| {
+        futures_util::future::ready(
+            if let tokio_postgres::AsyncMessage::Notification(n) = m {
+                Some(n)
+            } else {
+                None
+            }
+        )
+    })
+    .for_each(|n| {
+        let ws_sender_clone = ws_sender.clone();
+        let algorithm_id = algorithm_id.to_string();
+        async move {
+            let json: serde_json::Value = match serde_json::from_str(n.payload()) {
+                Ok(j) => j,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return;
+                }
+            };
+
+            if json["algorithm_id"].as_str() != Some(&*algorithm_id) {
+                return;
+            }
+
+            let mut sender = ws_sender_clone.lock().await;
+            if let Err(e) = sender.send(Message::Text(json.to_string())).await {
+                eprintln!("\x1b[31m[Error] Websocket error: {}\x1b[0m", e);
+                sender.close().await.unwrap_or_default();
+            }
+        }
+    })
+    .await;
+
+    Ok(())
+}
+
 // Error type for the Websocket.
 #[derive(Debug)]
 pub enum Error {
@@ -0,0 +1,3 @@
+def func(data, state):
+    state["calls"] = state.get("calls", 0) + 1
+    return state["calls"]
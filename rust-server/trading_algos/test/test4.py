@@ -0,0 +1,2 @@
+def func(data, params):
+    return params.get("amount", 1)
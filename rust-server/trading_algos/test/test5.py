@@ -0,0 +1,3 @@
+def func(data):
+    while True:
+        pass
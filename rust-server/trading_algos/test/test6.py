@@ -0,0 +1,5 @@
+def ratio(a, b):
+    return a / b
+
+def func(data):
+    return ratio(data[-1].c, 0)